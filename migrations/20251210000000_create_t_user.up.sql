@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS t_user (
+    f_id            TEXT        PRIMARY KEY,
+    f_email         TEXT        NOT NULL UNIQUE,
+    f_nickname      TEXT        NOT NULL,
+    f_password_hash TEXT        NOT NULL,
+    f_created_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS t_user_role;
+DROP TABLE IF EXISTS t_role_permission;
+DROP TABLE IF EXISTS t_role;
@@ -0,0 +1,27 @@
+CREATE TABLE t_role (
+    f_name        TEXT PRIMARY KEY,
+    f_description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE t_role_permission (
+    f_role       TEXT NOT NULL REFERENCES t_role (f_name) ON DELETE CASCADE,
+    f_permission TEXT NOT NULL,
+    PRIMARY KEY (f_role, f_permission)
+);
+
+CREATE TABLE t_user_role (
+    f_user_id TEXT NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_role    TEXT NOT NULL REFERENCES t_role (f_name) ON DELETE CASCADE,
+    PRIMARY KEY (f_user_id, f_role)
+);
+
+INSERT INTO t_role (f_name, f_description)
+VALUES ('admin', 'Administrators'),
+       ('user', 'Regular users');
+
+INSERT INTO t_role_permission (f_role, f_permission)
+VALUES ('admin', 'user:read:any'),
+       ('admin', 'role:assign');
+
+INSERT INTO t_user_role (f_user_id, f_role)
+SELECT f_id, 'user' FROM t_user;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS t_membership;
+DROP TABLE IF EXISTS t_organization;
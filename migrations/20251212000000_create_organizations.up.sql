@@ -0,0 +1,15 @@
+CREATE TABLE t_organization (
+    f_id         TEXT        PRIMARY KEY,
+    f_name       TEXT        NOT NULL,
+    f_created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE t_membership (
+    f_org_id     TEXT        NOT NULL REFERENCES t_organization (f_id) ON DELETE CASCADE,
+    f_user_id    TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_role       TEXT        NOT NULL CHECK (f_role IN ('owner', 'admin', 'member')),
+    f_created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (f_org_id, f_user_id)
+);
+
+CREATE INDEX i_membership_user_id ON t_membership (f_user_id);
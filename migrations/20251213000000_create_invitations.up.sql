@@ -0,0 +1,14 @@
+CREATE TABLE t_invitation (
+    f_id          TEXT        PRIMARY KEY,
+    f_org_id      TEXT        NOT NULL REFERENCES t_organization (f_id) ON DELETE CASCADE,
+    f_email       TEXT        NOT NULL,
+    f_role        TEXT        NOT NULL CHECK (f_role IN ('owner', 'admin', 'member')),
+    f_token_hash  TEXT        NOT NULL UNIQUE,
+    f_invited_by  TEXT        REFERENCES t_user (f_id) ON DELETE SET NULL,
+    f_created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    f_expires_at  TIMESTAMPTZ NOT NULL,
+    f_accepted_at TIMESTAMPTZ,
+    f_revoked_at  TIMESTAMPTZ
+);
+
+CREATE INDEX i_invitation_org_id ON t_invitation (f_org_id);
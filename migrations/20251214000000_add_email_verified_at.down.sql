@@ -0,0 +1 @@
+ALTER TABLE t_user DROP COLUMN IF EXISTS f_email_verified_at;
@@ -0,0 +1,4 @@
+ALTER TABLE t_user ADD COLUMN f_email_verified_at TIMESTAMPTZ;
+
+-- Accounts created before verification existed are trusted as they are.
+UPDATE t_user SET f_email_verified_at = f_created_at;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS t_recovery_code;
+DROP TABLE IF EXISTS t_user_totp;
@@ -0,0 +1,16 @@
+CREATE TABLE t_user_totp (
+    f_user_id      TEXT        PRIMARY KEY REFERENCES t_user (f_id) ON DELETE CASCADE,
+    -- Sealed with the secret box key, never stored in clear.
+    f_secret       BYTEA       NOT NULL,
+    -- Time step of the last accepted code, so that a code cannot be replayed.
+    f_last_step    BIGINT      NOT NULL DEFAULT 0,
+    f_created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    f_confirmed_at TIMESTAMPTZ
+);
+
+CREATE TABLE t_recovery_code (
+    f_user_id   TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_code_hash TEXT        NOT NULL,
+    f_used_at   TIMESTAMPTZ,
+    PRIMARY KEY (f_user_id, f_code_hash)
+);
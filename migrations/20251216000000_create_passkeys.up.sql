@@ -0,0 +1,13 @@
+CREATE TABLE t_passkey (
+    -- Base64url credential ID chosen by the authenticator.
+    f_id           TEXT        PRIMARY KEY,
+    f_user_id      TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_name         TEXT        NOT NULL,
+    -- COSE encoded credential public key.
+    f_public_key   BYTEA       NOT NULL,
+    f_sign_count   BIGINT      NOT NULL DEFAULT 0,
+    f_created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    f_last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX i_passkey_user_id ON t_passkey (f_user_id);
@@ -0,0 +1,12 @@
+CREATE TABLE t_user_identity (
+    f_provider      TEXT        NOT NULL,
+    -- `sub` claim of the provider's ID tokens, stable for the account at that provider.
+    f_subject       TEXT        NOT NULL,
+    f_user_id       TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_email         TEXT,
+    f_created_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+    f_last_login_at TIMESTAMPTZ,
+    PRIMARY KEY (f_provider, f_subject)
+);
+
+CREATE INDEX i_user_identity_user_id ON t_user_identity (f_user_id);
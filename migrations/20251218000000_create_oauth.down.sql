@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS t_oauth_consent;
+DROP TABLE IF EXISTS t_oauth_client;
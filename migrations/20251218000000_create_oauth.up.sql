@@ -0,0 +1,25 @@
+CREATE TABLE t_oauth_client (
+    f_id            TEXT        PRIMARY KEY,
+    f_owner_id      TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_name          TEXT        NOT NULL,
+    -- Digest of the client secret, public clients have none and rely on PKCE.
+    f_secret_hash   TEXT,
+    f_redirect_uris TEXT[]      NOT NULL,
+    -- Scopes the client may ask for.
+    f_scopes        TEXT[]      NOT NULL,
+    f_created_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX i_oauth_client_owner_id ON t_oauth_client (f_owner_id);
+
+CREATE TABLE t_oauth_consent (
+    f_user_id    TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    f_client_id  TEXT        NOT NULL REFERENCES t_oauth_client (f_id) ON DELETE CASCADE,
+    -- Scopes the user has granted the client so far.
+    f_scopes     TEXT[]      NOT NULL,
+    f_created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    f_updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (f_user_id, f_client_id)
+);
+
+CREATE INDEX i_oauth_consent_client_id ON t_oauth_consent (f_client_id);
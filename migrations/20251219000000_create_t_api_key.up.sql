@@ -0,0 +1,16 @@
+CREATE TABLE t_api_key (
+    f_id           TEXT        PRIMARY KEY,
+    f_user_id      TEXT        NOT NULL REFERENCES t_user (f_id) ON DELETE CASCADE,
+    -- Organization an org-scoped key is bound to, null for user-scoped keys.
+    f_org_id       TEXT        REFERENCES t_organization (f_id) ON DELETE CASCADE,
+    f_name         TEXT        NOT NULL,
+    -- Leading characters of the key, enough to recognize it in a list.
+    f_prefix       TEXT        NOT NULL,
+    f_key_hash     TEXT        NOT NULL UNIQUE,
+    f_scopes       TEXT[]      NOT NULL,
+    f_expires_at   TIMESTAMPTZ,
+    f_last_used_at TIMESTAMPTZ,
+    f_created_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX i_api_key_user_id ON t_api_key (f_user_id);
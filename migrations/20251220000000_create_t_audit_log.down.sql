@@ -0,0 +1,3 @@
+DELETE FROM t_role_permission WHERE f_permission = 'user:unlock';
+
+DROP TABLE IF EXISTS t_audit_log;
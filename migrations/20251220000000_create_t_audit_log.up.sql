@@ -0,0 +1,17 @@
+CREATE TABLE t_audit_log (
+    f_id         TEXT        PRIMARY KEY,
+    -- Dotted event name, e.g. `account.locked`.
+    f_action     TEXT        NOT NULL,
+    -- Account the event is about, kept as history when the account goes away.
+    f_user_id    TEXT        REFERENCES t_user (f_id) ON DELETE SET NULL,
+    -- User who caused the event, null for the system or an anonymous client.
+    f_actor_id   TEXT        REFERENCES t_user (f_id) ON DELETE SET NULL,
+    f_ip         TEXT,
+    f_detail     TEXT        NOT NULL DEFAULT '',
+    f_created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX i_audit_log_user_id ON t_audit_log (f_user_id, f_created_at);
+
+INSERT INTO t_role_permission (f_role, f_permission)
+VALUES ('admin', 'user:unlock');
@@ -0,0 +1,8 @@
+DELETE FROM t_role_permission WHERE f_permission = 'user:update:any';
+
+ALTER TABLE t_user
+    DROP COLUMN IF EXISTS f_version,
+    DROP COLUMN IF EXISTS f_updated_at,
+    DROP COLUMN IF EXISTS f_timezone,
+    DROP COLUMN IF EXISTS f_locale,
+    DROP COLUMN IF EXISTS f_avatar_url;
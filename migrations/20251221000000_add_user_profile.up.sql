@@ -0,0 +1,12 @@
+ALTER TABLE t_user
+    ADD COLUMN f_avatar_url TEXT,
+    ADD COLUMN f_locale     TEXT,
+    ADD COLUMN f_timezone   TEXT,
+    ADD COLUMN f_updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Bumped by every profile update, so that concurrent edits are detected.
+    ADD COLUMN f_version    BIGINT      NOT NULL DEFAULT 1;
+
+UPDATE t_user SET f_updated_at = f_created_at;
+
+INSERT INTO t_role_permission (f_role, f_permission)
+VALUES ('admin', 'user:update:any');
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS i_user_email;
+
+ALTER TABLE t_user ADD CONSTRAINT t_user_f_email_key UNIQUE (f_email);
@@ -0,0 +1,5 @@
+-- Addresses differing only in case reach the same mailbox, so they cannot be two accounts.
+-- Existing accounts clashing this way must be merged by hand before this runs.
+ALTER TABLE t_user DROP CONSTRAINT IF EXISTS t_user_f_email_key;
+
+CREATE UNIQUE INDEX i_user_email ON t_user (lower(f_email));
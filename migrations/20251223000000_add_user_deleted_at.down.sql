@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS i_user_deleted_at;
+
+ALTER TABLE t_user DROP COLUMN IF EXISTS f_deleted_at;
@@ -0,0 +1,5 @@
+-- When the user asked for the account to be deleted. It stays restorable for the grace
+-- period, after which the purge erases it.
+ALTER TABLE t_user ADD COLUMN f_deleted_at TIMESTAMPTZ;
+
+CREATE INDEX i_user_deleted_at ON t_user (f_deleted_at) WHERE f_deleted_at IS NOT NULL;
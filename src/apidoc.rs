@@ -1,13 +1,59 @@
+use utoipa::Modify;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+mod api_key;
 mod auth;
 mod health;
+mod invitation;
+mod mfa;
+mod oauth;
+mod oidc;
+mod organization;
+mod passkey;
+mod session;
 mod user;
+mod well_known;
+
+/// Registers the bearer scheme that guarded paths list in their `security` requirements.
+/// Scopes of a requirement are the permissions it needs.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
+    modifiers(&SecurityAddon),
     nest(
         (path = "/check", api = health::HealthApiDoc),
         (path = "/api/users", api = user::UserApiDoc),
+        (path = "/api/users", api = mfa::MfaApiDoc),
+        (path = "/api/users", api = api_key::ApiKeyApiDoc),
+        (path = "/api/users", api = passkey::PasskeyApiDoc),
+        (path = "/api/users", api = session::SessionApiDoc),
+        (path = "/api/oauth", api = oauth::OAuthApiDoc),
+        (path = "/api/orgs", api = organization::OrgApiDoc),
+        (path = "/api/orgs", api = invitation::InvitationApiDoc),
         (path = "/auth", api = auth::AuthApiDoc),
+        (path = "/auth", api = invitation::AcceptInvitationApiDoc),
+        (path = "/auth", api = mfa::VerifyMfaApiDoc),
+        (path = "/auth", api = passkey::PasskeyLoginApiDoc),
+        (path = "/auth", api = oidc::OidcApiDoc),
+        (path = "/oauth", api = oauth::OAuthProtocolApiDoc),
+        (path = "/.well-known", api = well_known::WellKnownApiDoc),
     ),
 )]
 pub struct ApiDoc;
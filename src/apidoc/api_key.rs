@@ -0,0 +1,14 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::api_key::create_api_key,
+        crate::http::api_key::list_api_keys,
+        crate::http::api_key::revoke_api_key,
+    ),
+    tags(
+        (name = "API Key", description = "Personal API keys for scripts and CI jobs")
+    )
+)]
+pub struct ApiKeyApiDoc;
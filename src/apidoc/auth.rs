@@ -3,10 +3,22 @@ use utoipa::OpenApi;
 #[derive(OpenApi)]
 #[openapi(
     paths(
+        crate::http::auth::register_user,
         crate::http::auth::login_user,
+        crate::http::auth::refresh_token,
+        crate::http::auth::logout_user,
+        crate::http::auth::logout_all,
+        crate::http::auth::verify_email,
+        crate::http::auth::confirm_email_change,
+        crate::http::auth::revert_email_change,
+        crate::http::auth::resend_verification,
+        crate::http::auth::forgot_password,
+        crate::http::auth::reset_password,
+        crate::http::auth::request_magic_link,
+        crate::http::auth::consume_magic_link,
     ),
     tags(
-        (name = "Auth", description = "Authentication endpoint")
+        (name = "Auth", description = "Authentication endpoints")
     )
 )]
 pub struct AuthApiDoc;
@@ -0,0 +1,18 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::invitation::create_invitation,
+        crate::http::invitation::list_invitations,
+        crate::http::invitation::revoke_invitation,
+    ),
+    tags(
+        (name = "Invitation", description = "Organization invitation endpoints")
+    )
+)]
+pub struct InvitationApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(paths(crate::http::invitation::accept_invitation))]
+pub struct AcceptInvitationApiDoc;
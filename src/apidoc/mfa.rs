@@ -0,0 +1,18 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::mfa::enroll_totp,
+        crate::http::mfa::confirm_totp,
+        crate::http::mfa::disable_totp,
+    ),
+    tags(
+        (name = "MFA", description = "Two-factor authentication endpoints")
+    )
+)]
+pub struct MfaApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(paths(crate::http::mfa::verify_mfa))]
+pub struct VerifyMfaApiDoc;
@@ -0,0 +1,24 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::oauth::create_client,
+        crate::http::oauth::list_clients,
+        crate::http::oauth::delete_client,
+        crate::http::oauth::authorize,
+        crate::http::oauth::consent,
+    ),
+    tags(
+        (name = "OAuth", description = "OAuth 2.0 authorization server for third-party clients")
+    )
+)]
+pub struct OAuthApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    crate::http::oauth::token,
+    crate::http::oauth::introspect,
+    crate::http::oauth::revoke,
+))]
+pub struct OAuthProtocolApiDoc;
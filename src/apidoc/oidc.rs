@@ -0,0 +1,13 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::oidc::authorize,
+        crate::http::oidc::callback,
+    ),
+    tags(
+        (name = "OIDC", description = "Sign in with external OpenID Connect providers")
+    )
+)]
+pub struct OidcApiDoc;
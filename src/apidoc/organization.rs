@@ -0,0 +1,20 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::organization::create_org,
+        crate::http::organization::list_orgs,
+        crate::http::organization::get_org,
+        crate::http::organization::update_org,
+        crate::http::organization::delete_org,
+        crate::http::organization::list_members,
+        crate::http::organization::set_member_role,
+        crate::http::organization::remove_member,
+        crate::http::organization::switch_org,
+    ),
+    tags(
+        (name = "Organization", description = "Organization and membership endpoints")
+    )
+)]
+pub struct OrgApiDoc;
@@ -0,0 +1,20 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::passkey::begin_registration,
+        crate::http::passkey::finish_registration,
+        crate::http::passkey::list_passkeys,
+        crate::http::passkey::rename_passkey,
+        crate::http::passkey::delete_passkey,
+    ),
+    tags(
+        (name = "Passkey", description = "WebAuthn passkey endpoints")
+    )
+)]
+pub struct PasskeyApiDoc;
+
+#[derive(OpenApi)]
+#[openapi(paths(crate::http::passkey::begin_login, crate::http::passkey::finish_login,))]
+pub struct PasskeyLoginApiDoc;
@@ -0,0 +1,13 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::session::list_sessions,
+        crate::http::session::terminate_session,
+    ),
+    tags(
+        (name = "Session", description = "Devices the user is logged in on")
+    )
+)]
+pub struct SessionApiDoc;
@@ -4,6 +4,13 @@ use utoipa::OpenApi;
 #[openapi(
     paths(
         crate::http::user::get_user,
+        crate::http::user::update_me,
+        crate::http::user::delete_me,
+        crate::http::user::update_user,
+        crate::http::user::set_user_roles,
+        crate::http::user::unlock_user,
+        crate::http::user::change_password,
+        crate::http::user::change_email,
     ),
     tags(
         (name = "User", description = "User related endpoints")
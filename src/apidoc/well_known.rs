@@ -0,0 +1,12 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::http::well_known::jwks,
+    ),
+    tags(
+        (name = "Well-Known", description = "Well-known discovery endpoints")
+    )
+)]
+pub struct WellKnownApiDoc;
@@ -1,27 +1,37 @@
-use redis::{AsyncTypedCommands, Client, RedisResult};
+use redis::{AsyncTypedCommands, Client, RedisResult, aio::ConnectionManager};
 
-#[derive(Debug)]
+/// Redis, reached through a single multiplexed connection shared by all requests.
+/// The connection is re-established in the background when it drops.
 pub struct Cache {
-    client: Client,
+    conn: ConnectionManager,
 }
 
 impl Cache {
-    pub fn new() -> anyhow::Result<Self> {
+    pub async fn new() -> anyhow::Result<Self> {
         let redis_url = std::env::var("REDIS_URL")
             .map_err(|e| anyhow::anyhow!("Error when acquiring REDIS_URL: {}", e))?;
 
-        let client = Client::open(redis_url.as_str())
+        Self::open(&redis_url).await
+    }
+
+    pub async fn open(redis_url: &str) -> anyhow::Result<Self> {
+        let client = Client::open(redis_url)
+            .map_err(|e| anyhow::anyhow!("Error when connecting to Redis: {}", e))?;
+
+        let conn = ConnectionManager::new(client)
+            .await
             .map_err(|e| anyhow::anyhow!("Error when connecting to Redis: {}", e))?;
 
-        Ok(Self { client })
+        Ok(Self { conn })
     }
 
-    pub fn client(&self) -> &Client {
-        &self.client
+    /// A handle on the shared connection, cheap to clone.
+    pub fn conn(&self) -> ConnectionManager {
+        self.conn.clone()
     }
 
     pub async fn ping(&self) -> RedisResult<()> {
-        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let mut conn = self.conn();
 
         conn.ping().await?;
 
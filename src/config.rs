@@ -1,10 +1,184 @@
+use jsonwebtoken::Algorithm;
 use serde::Deserialize;
 
+/// What to do when a security check cannot be completed, e.g. Redis is unreachable.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Let the request through and log the failure.
+    FailOpen,
+    /// Reject the request with `503 Service Unavailable`.
+    FailClosed,
+}
+
+/// How login hands the session to the client.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionMode {
+    /// Tokens are returned in the reply body and sent back as `Authorization: Bearer`.
+    Bearer,
+    /// Tokens stay on the server, browsers get an HttpOnly session cookie and a CSRF token.
+    /// Bearer tokens and API keys are still accepted.
+    Cookie,
+}
+
+/// `SameSite` attribute of the session cookie.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Cross-site requests carry the cookie too, requires `Secure`.
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+/// A JWT signing key pair in PEM format. Retired keys only need the public half.
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtKeyConfig {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub public_key_path: String,
+    pub private_key_path: Option<String>,
+}
+
+/// Where outgoing mail goes, see `Mailer`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MailTransportConfig {
+    Console,
+    File { dir: String },
+}
+
+/// An OpenID Connect provider users can sign in with.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OidcProviderConfig {
+    /// Name used in the routes, e.g. `google`.
+    pub name: String,
+    /// Issuer URL, discovery is fetched from `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    /// Environment variable holding the client secret, public clients rely on PKCE alone.
+    pub client_secret_env: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// Link to an existing account with the same email when the provider vouches for it.
+    pub trust_email: bool,
+}
+
+/// What an account with an unverified email may do.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailVerificationPolicy {
+    /// Verification is offered but never required.
+    Optional,
+    /// Routes guarded by `VerifiedEmail` are refused until the email is verified.
+    BlockRoutes,
+    /// Login is refused until the email is verified.
+    BlockLogin,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct AppConfig {
     pub server_host: String,
     pub server_port: u16,
+    /// Take the client address from the last `X-Forwarded-For` entry,
+    /// only safe behind a reverse proxy that appends it.
+    pub trust_forwarded_for: bool,
+    /// Public URL of the frontend, links in mails point there.
+    pub app_base_url: String,
     pub jwt_exp_seconds: usize,
+    pub jwt_issuer: String,
+    pub jwt_audience: String,
+    pub jwt_leeway_seconds: u64,
+    pub jwt_active_kid: String,
+    pub jwt_keys: Vec<JwtKeyConfig>,
+    pub refresh_token_exp_seconds: u64,
+    pub revocation_check_timeout_ms: u64,
+    pub revocation_failure_policy: FailurePolicy,
+    pub mail_from: String,
+    pub mail_transport: MailTransportConfig,
+    pub invitation_exp_seconds: i64,
+    pub email_verification_policy: EmailVerificationPolicy,
+    pub email_verification_exp_seconds: u64,
+    pub verification_resend_limit: u64,
+    pub verification_resend_window_seconds: u64,
+    pub password_reset_exp_seconds: u64,
+    pub password_reset_email_limit: u64,
+    pub password_reset_ip_limit: u64,
+    pub password_reset_window_seconds: u64,
+    /// Lifetime of the link confirming a new email address.
+    pub email_change_exp_seconds: u64,
+    /// Lifetime of the link mailed to the former address to undo a change, which
+    /// should outlast the confirmation so that a hijacked account can be taken back.
+    pub email_change_revert_exp_seconds: u64,
+    /// Time a deleted account can still be restored by logging in before it is purged.
+    pub account_deletion_grace_seconds: u64,
+    /// How often the background purge looks for accounts past their grace period.
+    pub account_purge_interval_seconds: u64,
+    pub password_min_length: usize,
+    pub password_max_length: usize,
+    /// Lowest accepted strength, on zxcvbn's scale from 0 to 4.
+    pub password_min_score: u8,
+    /// File of common passwords to refuse, one per line.
+    pub password_banned_list_path: Option<String>,
+    /// Directory of Pwned Passwords range files (`{PREFIX}.txt`), no breach check when null.
+    pub password_hibp_dir: Option<String>,
+    /// Argon2id memory cost in KiB, raised over time as hardware improves.
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    /// Environment variable holding a secret mixed into every password hash, no pepper when null.
+    pub password_pepper_env: Option<String>,
+    pub mfa_issuer: String,
+    pub mfa_pending_exp_seconds: u64,
+    pub mfa_verify_attempt_limit: u64,
+    /// Domain passkeys are bound to, the origin's host or a registrable suffix of it.
+    pub webauthn_rp_id: String,
+    pub webauthn_rp_name: String,
+    /// Origin of the frontend running the WebAuthn ceremonies.
+    pub webauthn_origin: String,
+    pub webauthn_challenge_exp_seconds: u64,
+    pub webauthn_require_user_verification: bool,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    pub oidc_state_exp_seconds: u64,
+    /// Lifetime of OAuth authorization codes, RFC 6749 recommends at most ten minutes.
+    pub oauth_code_exp_seconds: u64,
+    /// Offer passwordless login through mailed links.
+    pub magic_link_enabled: bool,
+    pub magic_link_exp_seconds: u64,
+    pub magic_link_email_limit: u64,
+    pub magic_link_ip_limit: u64,
+    pub magic_link_window_seconds: u64,
+    /// Failed logins are counted per email and per client address over this window.
+    pub login_failure_window_seconds: u64,
+    /// Failures tolerated before each further one delays the next attempt.
+    pub login_delay_after_failures: u64,
+    /// First delay, doubled on every further failure up to the maximum.
+    pub login_delay_base_seconds: u64,
+    pub login_delay_max_seconds: u64,
+    /// Failures for one email that lock it out.
+    pub login_lockout_threshold: u64,
+    pub login_lockout_seconds: u64,
+    /// Failures from one client address, across all emails, that block it.
+    pub login_ip_failure_limit: u64,
+    pub session_mode: SessionMode,
+    pub session_cookie_name: String,
+    /// Cookie carrying the CSRF token, readable by scripts so they can echo it in `csrf_header_name`.
+    pub csrf_cookie_name: String,
+    pub csrf_header_name: String,
+    /// Only turn off for local development over plain HTTP.
+    pub session_cookie_secure: bool,
+    pub session_cookie_same_site: SameSite,
 }
 
 impl AppConfig {
@@ -17,6 +191,15 @@ impl AppConfig {
         let config: AppConfig = serde_json::from_str(&content)
             .map_err(|e| anyhow::anyhow!("Error when parsing config file {}: {}", path, e))?;
 
+        // Browsers drop `SameSite=None` cookies that are not `Secure`.
+        if config.session_cookie_same_site == SameSite::None && !config.session_cookie_secure {
+            anyhow::bail!("session_cookie_same_site none requires session_cookie_secure");
+        }
+
+        if config.account_purge_interval_seconds == 0 {
+            anyhow::bail!("account_purge_interval_seconds must be positive");
+        }
+
         Ok(config)
     }
 }
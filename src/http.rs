@@ -1,17 +1,32 @@
-use std::net::SocketAddrV4;
+use std::net::{SocketAddr, SocketAddrV4};
 
 use axum::Router;
 use axum::middleware::from_fn_with_state;
-use axum::routing::{get, post};
+use axum::routing::{delete, get, patch, post, put};
 use tokio::net::TcpListener;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+pub mod api_key;
 pub mod auth;
+pub mod client_ip;
+pub mod cookie_session;
+pub mod guard;
 pub mod health;
+pub mod invitation;
+pub mod mfa;
 pub mod middleware;
+pub mod oauth;
+pub mod oidc;
+pub mod organization;
+pub mod passkey;
 pub mod result;
+pub mod session;
 pub mod user;
+pub mod well_known;
+
+#[cfg(test)]
+mod test_support;
 
 use crate::apidoc::ApiDoc;
 use crate::http::middleware::authorization::authorize_middleware;
@@ -20,13 +35,113 @@ use crate::state::AppState;
 async fn init_router(app_state: &AppState) -> anyhow::Result<Router> {
     let health_router = Router::new().route("/health", get(health::health_check));
 
-    let user_router = Router::new().route("/{user_id}", get(user::get_user));
+    let user_router = Router::new()
+        .route("/me", patch(user::update_me).delete(user::delete_me))
+        .route("/me/password", post(user::change_password))
+        .route("/me/email", post(user::change_email))
+        .route(
+            "/me/api-keys",
+            get(api_key::list_api_keys).post(api_key::create_api_key),
+        )
+        .route("/me/api-keys/{api_key_id}", delete(api_key::revoke_api_key))
+        .route("/me/sessions", get(session::list_sessions))
+        .route(
+            "/me/sessions/{session_id}",
+            delete(session::terminate_session),
+        )
+        .route("/me/mfa/totp", post(mfa::enroll_totp))
+        .route("/me/mfa/totp/confirm", post(mfa::confirm_totp))
+        .route("/me/mfa/totp/disable", post(mfa::disable_totp))
+        .route("/me/passkeys", get(passkey::list_passkeys))
+        .route(
+            "/me/passkeys/register/begin",
+            post(passkey::begin_registration),
+        )
+        .route(
+            "/me/passkeys/register/finish",
+            post(passkey::finish_registration),
+        )
+        .route(
+            "/me/passkeys/{passkey_id}",
+            patch(passkey::rename_passkey).delete(passkey::delete_passkey),
+        )
+        .route("/{user_id}", get(user::get_user).patch(user::update_user))
+        .route("/{user_id}/roles", put(user::set_user_roles))
+        .route("/{user_id}/unlock", post(user::unlock_user));
+
+    let org_router = Router::new()
+        .route(
+            "/",
+            get(organization::list_orgs).post(organization::create_org),
+        )
+        .route(
+            "/{org_id}",
+            get(organization::get_org)
+                .patch(organization::update_org)
+                .delete(organization::delete_org),
+        )
+        .route("/{org_id}/members", get(organization::list_members))
+        .route(
+            "/{org_id}/members/{user_id}",
+            put(organization::set_member_role).delete(organization::remove_member),
+        )
+        .route("/{org_id}/switch", post(organization::switch_org))
+        .route(
+            "/{org_id}/invitations",
+            get(invitation::list_invitations).post(invitation::create_invitation),
+        )
+        .route(
+            "/{org_id}/invitations/{invitation_id}",
+            delete(invitation::revoke_invitation),
+        );
+
+    let oauth_router = Router::new()
+        .route("/authorize", get(oauth::authorize))
+        .route("/consent", post(oauth::consent))
+        .route(
+            "/clients",
+            get(oauth::list_clients).post(oauth::create_client),
+        )
+        .route("/clients/{client_id}", delete(oauth::delete_client));
 
     let api_router = Router::new()
         .nest("/users", user_router)
+        .nest("/orgs", org_router)
+        .nest("/oauth", oauth_router)
         .route_layer(from_fn_with_state(app_state.clone(), authorize_middleware));
 
-    let auth_router = Router::new().route("/login", post(auth::login_user));
+    let logout_router = Router::new()
+        .route("/logout", post(auth::logout_user))
+        .route("/logout-all", post(auth::logout_all))
+        .route_layer(from_fn_with_state(app_state.clone(), authorize_middleware));
+
+    let auth_router = Router::new()
+        .route("/register", post(auth::register_user))
+        .route("/login", post(auth::login_user))
+        .route("/refresh", post(auth::refresh_token))
+        .route("/verify-email", post(auth::verify_email))
+        .route("/confirm-email-change", post(auth::confirm_email_change))
+        .route("/revert-email-change", post(auth::revert_email_change))
+        .route("/resend-verification", post(auth::resend_verification))
+        .route("/forgot-password", post(auth::forgot_password))
+        .route("/reset-password", post(auth::reset_password))
+        .route("/magic-link", post(auth::request_magic_link))
+        .route("/magic-link/consume", post(auth::consume_magic_link))
+        .route("/mfa/verify", post(mfa::verify_mfa))
+        .route("/passkey/begin", post(passkey::begin_login))
+        .route("/passkey/finish", post(passkey::finish_login))
+        .route("/oidc/{provider}/authorize", get(oidc::authorize))
+        .route("/oidc/{provider}/callback", post(oidc::callback))
+        .route("/accept-invitation", post(invitation::accept_invitation))
+        .merge(logout_router);
+
+    // Clients authenticate on these themselves, with their ID and secret.
+    let oauth_protocol_router = Router::new()
+        .route("/token", post(oauth::token))
+        .route("/introspect", post(oauth::introspect))
+        .route("/revoke", post(oauth::revoke));
+
+    let well_known_router = Router::new().route("/jwks.json", get(well_known::jwks));
 
     let doc_router = SwaggerUi::new("/docs").url("/api-docs/openapi.json", ApiDoc::openapi());
 
@@ -35,6 +150,8 @@ async fn init_router(app_state: &AppState) -> anyhow::Result<Router> {
         .nest("/check", health_router)
         .nest("/auth", auth_router)
         .nest("/api", api_router)
+        .nest("/oauth", oauth_protocol_router)
+        .nest("/.well-known", well_known_router)
         .with_state(app_state.clone());
 
     Ok(router)
@@ -80,10 +197,14 @@ pub async fn run_server(app_state: &AppState) -> anyhow::Result<()> {
     // Initialize make service on router.
     let router = init_router(app_state).await?;
 
-    axum::serve(listener, router)
-        .with_graceful_shutdown(signal_term())
-        .await
-        .map_err(|e| anyhow::anyhow!("Error running server: {}", e))?;
+    // Connection info feeds `ClientIp`.
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(signal_term())
+    .await
+    .map_err(|e| anyhow::anyhow!("Error running server: {}", e))?;
 
     Ok(())
 }
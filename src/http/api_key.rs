@@ -0,0 +1,81 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+};
+
+use crate::{
+    http::{guard::VerifiedEmail, result::HttpResult},
+    jwt_codec::UserClaims,
+    model::api_key::{CreateApiKeyArgs, CreateApiKeyReply, ListApiKeysReply},
+    service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/me/api-keys",
+    request_body = CreateApiKeyArgs,
+    responses(
+        (status = 201, description = "API key created, the key is shown only once", body = HttpResult<CreateApiKeyReply>),
+        (status = 400, description = "Unknown scope"),
+        (status = 403, description = "Email address is not verified, or not an admin of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "API Key"
+)]
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    VerifiedEmail { claims }: VerifiedEmail,
+    Json(payload): Json<CreateApiKeyArgs>,
+) -> HttpResult<CreateApiKeyReply> {
+    service::api_key::create_api_key(&claims, payload, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/api-keys",
+    responses(
+        (status = 200, description = "API keys of the caller", body = HttpResult<ListApiKeysReply>)
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "API Key"
+)]
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<ListApiKeysReply> {
+    service::api_key::list_api_keys(&claims, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/api-keys/{api_key_id}",
+    responses(
+        (status = 200, description = "API key revoked"),
+        (status = 404, description = "API key not found")
+    ),
+    params(
+        ("api_key_id" = String, Path, description = "The ID of the API key")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "API Key"
+)]
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Path(api_key_id): Path<String>,
+) -> HttpResult<()> {
+    service::api_key::revoke_api_key(&claims, &api_key_id, state.repo())
+        .await
+        .into()
+}
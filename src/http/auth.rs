@@ -1,26 +1,511 @@
-use axum::{Json, extract::State};
+use axum::{
+    Extension, Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse as _, Response},
+};
 
 use crate::{
-    http::result::HttpResult,
-    model::user::{LoginUserArgs, LoginUserReply},
+    http::{
+        client_ip::ClientIp,
+        cookie_session::{clear_session, session_token, with_session},
+        result::HttpResult,
+    },
+    jwt_codec::UserClaims,
+    model::user::{
+        ConfirmEmailChangeArgs, ConsumeMagicLinkArgs, ForgotPasswordArgs, LoginUserArgs,
+        LoginUserReply, LogoutUserArgs, MagicLinkArgs, RefreshTokenArgs, RefreshTokenReply,
+        RegisterUserArgs, RegisterUserReply, ResendVerificationArgs, ResetPasswordArgs,
+        RevertEmailChangeArgs, VerifyEmailArgs,
+    },
+    result_trace::ResultTrace as _,
     service,
     state::AppState,
 };
 
+#[utoipa::path(
+    post,
+    path = "/register",
+    request_body = RegisterUserArgs,
+    responses(
+        (status = 201, description = "User registration successful, a verification mail is sent", body = HttpResult<RegisterUserReply>),
+        (status = 400, description = "Password violates the password policy, broken rules are listed in `errors`"),
+        (status = 409, description = "Email already registered")
+    ),
+    tag = "Auth"
+)]
+pub async fn register_user(
+    State(state): State<AppState>,
+    Json(payload): Json<RegisterUserArgs>,
+) -> Response {
+    let result = service::auth::register_user(
+        payload,
+        state.config(),
+        state.password_policy(),
+        state.jwt_codec(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+        state.mailer(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
 #[utoipa::path(
     post,
     path = "/login",
     request_body = LoginUserArgs,
     responses(
-        (status = 200, description = "User login successful", body = HttpResult<LoginUserReply>)
+        (status = 200, description = "User login successful", body = HttpResult<LoginUserReply>),
+        (status = 401, description = "Invalid email or password"),
+        (status = 403, description = "Email address is not verified"),
+        (status = 423, description = "Account temporarily locked, see Retry-After"),
+        (status = 429, description = "Too many failed logins, see Retry-After")
     ),
     tag = "Auth"
 )]
 pub async fn login_user(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
     Json(payload): Json<LoginUserArgs>,
-) -> HttpResult<LoginUserReply> {
-    service::auth::login_user(payload, state.config(), state.jwt_codec(), state.repo())
+) -> Response {
+    let result = service::auth::login_user(
+        payload,
+        client_ip,
+        state.config(),
+        state.jwt_codec(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/refresh",
+    request_body = RefreshTokenArgs,
+    responses(
+        (status = 200, description = "Token refresh successful", body = HttpResult<RefreshTokenReply>),
+        (status = 401, description = "Invalid, expired or reused refresh token")
+    ),
+    tag = "Auth"
+)]
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshTokenArgs>,
+) -> HttpResult<RefreshTokenReply> {
+    service::auth::refresh_token(
+        payload,
+        state.config(),
+        state.jwt_codec(),
+        state.repo(),
+        state.cache(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    request_body = Option<LogoutUserArgs>,
+    responses(
+        (status = 200, description = "The access token and the given refresh token are revoked, a session cookie is cleared")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Auth"
+)]
+pub async fn logout_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    headers: HeaderMap,
+    payload: Option<Json<LogoutUserArgs>>,
+) -> Response {
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+
+    let result = service::auth::logout_user(payload, &claims, state.cache()).await;
+
+    end_cookie_session(&state, &headers, result).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout-all",
+    responses(
+        (status = 200, description = "Every token issued to the user so far is revoked, a session cookie is cleared")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Auth"
+)]
+pub async fn logout_all(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    headers: HeaderMap,
+) -> Response {
+    let result = service::auth::logout_all(&claims, state.config(), state.cache()).await;
+
+    end_cookie_session(&state, &headers, result).await
+}
+
+/// Drop the session cookie a logout came with, if any, and tell the browser to forget it.
+async fn end_cookie_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    result: service::result::ServiceResult<()>,
+) -> Response {
+    let token = session_token(state.config(), headers);
+
+    if let (Ok(_), Some(token)) = (&result, &token) {
+        let _ = service::cookie_session::end_cookie_session(token, state.cache())
+            .await
+            .trace_warn();
+    }
+
+    let mut response = HttpResult::from(result).into_response();
+
+    if token.is_some() {
+        clear_session(state.config(), &mut response);
+    }
+
+    response
+}
+
+#[utoipa::path(
+    post,
+    path = "/verify-email",
+    request_body = VerifyEmailArgs,
+    responses(
+        (status = 200, description = "Email verified"),
+        (status = 400, description = "Invalid or expired verification token")
+    ),
+    tag = "Auth"
+)]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(payload): Json<VerifyEmailArgs>,
+) -> HttpResult<()> {
+    service::verification::verify_email(payload, state.repo(), state.cache())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/confirm-email-change",
+    request_body = ConfirmEmailChangeArgs,
+    responses(
+        (status = 200, description = "The account now uses the new email"),
+        (status = 400, description = "Invalid or expired confirmation token"),
+        (status = 409, description = "The new email was registered by another account meanwhile")
+    ),
+    tag = "Auth"
+)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Json(payload): Json<ConfirmEmailChangeArgs>,
+) -> HttpResult<()> {
+    service::email_change::confirm_email_change(payload, state.repo(), state.cache())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/revert-email-change",
+    request_body = RevertEmailChangeArgs,
+    responses(
+        (status = 200, description = "The account is back on its former email, every existing session is revoked"),
+        (status = 400, description = "Invalid or expired revert token"),
+        (status = 409, description = "The former email was registered by another account meanwhile")
+    ),
+    tag = "Auth"
+)]
+pub async fn revert_email_change(
+    State(state): State<AppState>,
+    Json(payload): Json<RevertEmailChangeArgs>,
+) -> HttpResult<()> {
+    service::email_change::revert_email_change(payload, state.config(), state.repo(), state.cache())
         .await
         .into()
 }
+
+#[utoipa::path(
+    post,
+    path = "/resend-verification",
+    request_body = ResendVerificationArgs,
+    responses(
+        (status = 200, description = "A verification mail is sent if the account needs one"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Auth"
+)]
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    Json(payload): Json<ResendVerificationArgs>,
+) -> HttpResult<()> {
+    service::verification::resend_verification(
+        payload,
+        state.config(),
+        state.repo(),
+        state.cache(),
+        state.mailer(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/forgot-password",
+    request_body = ForgotPasswordArgs,
+    responses(
+        (status = 200, description = "A reset link is mailed if the email is registered"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Auth"
+)]
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<ForgotPasswordArgs>,
+) -> HttpResult<()> {
+    service::password::forgot_password(
+        payload,
+        client_ip,
+        state.config(),
+        state.repo(),
+        state.cache(),
+        state.mailer(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/reset-password",
+    request_body = ResetPasswordArgs,
+    responses(
+        (status = 200, description = "Password reset, every existing session is revoked"),
+        (status = 400, description = "Invalid or expired reset token, or the password violates the password policy")
+    ),
+    tag = "Auth"
+)]
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordArgs>,
+) -> HttpResult<()> {
+    service::password::reset_password(
+        payload,
+        state.config(),
+        state.password_policy(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/magic-link",
+    request_body = MagicLinkArgs,
+    responses(
+        (status = 200, description = "A login link is mailed if the email is registered"),
+        (status = 404, description = "Magic link login is disabled"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Auth"
+)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<MagicLinkArgs>,
+) -> HttpResult<()> {
+    service::magic_link::request_magic_link(
+        payload,
+        client_ip,
+        state.config(),
+        state.repo(),
+        state.cache(),
+        state.mailer(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/magic-link/consume",
+    request_body = ConsumeMagicLinkArgs,
+    responses(
+        (status = 200, description = "User login successful", body = HttpResult<LoginUserReply>),
+        (status = 400, description = "Invalid or expired login link"),
+        (status = 404, description = "Magic link login is disabled")
+    ),
+    tag = "Auth"
+)]
+pub async fn consume_magic_link(
+    State(state): State<AppState>,
+    Json(payload): Json<ConsumeMagicLinkArgs>,
+) -> Response {
+    let result = service::magic_link::consume_magic_link(
+        payload,
+        state.config(),
+        state.jwt_codec(),
+        state.repo(),
+        state.cache(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use redis::AsyncTypedCommands as _;
+    use serde_json::json;
+
+    use crate::{
+        http::test_support::{self, PASSWORD, send, unique_email},
+        opaque_token,
+    };
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn concurrent_registrations_of_one_email_conflict() {
+        let state = test_support::app_state(test_support::config()).await;
+        let router = test_support::router(&state).await;
+
+        let body = serde_json::json!({
+            "email": unique_email(),
+            "nickname": "tester",
+            "password": PASSWORD,
+        });
+
+        let register = || {
+            send(
+                &router,
+                Method::POST,
+                "/auth/register",
+                None,
+                Some(body.clone()),
+            )
+        };
+
+        let ((first, _), (second, _)) = tokio::join!(register(), register());
+
+        let mut statuses = [first, second];
+        statuses.sort();
+
+        assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn reset_link_survives_a_rejected_password_only() {
+        let state = test_support::app_state(test_support::config()).await;
+        let router = test_support::router(&state).await;
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/auth/register",
+            None,
+            Some(json!({
+                "email": unique_email(),
+                "nickname": "tester",
+                "password": PASSWORD,
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        let user_id = body["data"]["user_id"].as_str().unwrap();
+
+        // What the mailed link would carry.
+        let token = opaque_token::generate();
+
+        state
+            .cache()
+            .conn()
+            .set_ex(
+                format!("reset:password:{}", opaque_token::digest(&token)),
+                user_id,
+                60,
+            )
+            .await
+            .unwrap();
+
+        let reset = |password: &str| {
+            send(
+                &router,
+                Method::POST,
+                "/auth/reset-password",
+                None,
+                Some(json!({ "token": token, "password": password })),
+            )
+        };
+
+        let (status, _) = reset("password").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = reset("another-Horse-battery-7-staple").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let (status, _) = reset("yet-another-Horse-battery-5").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn forgot_password_is_throttled_per_email_in_any_case() {
+        let mut config = test_support::config();
+
+        // Every test request comes from the same address, across runs too.
+        config.password_reset_ip_limit = u64::MAX;
+
+        let state = test_support::app_state(config).await;
+        let router = test_support::router(&state).await;
+
+        let email = unique_email();
+        test_support::register(&router, &email).await;
+
+        let limit = state.config().password_reset_email_limit;
+
+        for attempt in 0..=limit {
+            // Varying the case must not open a fresh bucket.
+            let email = match attempt % 2 {
+                0 => email.clone(),
+                _ => email.to_uppercase(),
+            };
+
+            let (status, _) = send(
+                &router,
+                Method::POST,
+                "/auth/forgot-password",
+                None,
+                Some(json!({ "email": email })),
+            )
+            .await;
+
+            let expected = match attempt < limit {
+                true => StatusCode::OK,
+                false => StatusCode::TOO_MANY_REQUESTS,
+            };
+
+            assert_eq!(status, expected, "attempt {}", attempt);
+        }
+    }
+}
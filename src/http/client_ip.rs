@@ -0,0 +1,42 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{StatusCode, request::Parts};
+
+use crate::{http::result::HttpResult, state::AppState};
+
+/// Address of the caller, for per-client throttling and auditing.
+///
+/// Behind a reverse proxy (`trust_forwarded_for`) it is the last `X-Forwarded-For` entry,
+/// the one the proxy appended; earlier entries are supplied by the client and not trusted.
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = HttpResult<()>;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if state.config().trust_forwarded_for {
+            let forwarded = parts
+                .headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .next_back()
+                .and_then(|ip| ip.trim().parse().ok());
+
+            if let Some(ip) = forwarded {
+                return Ok(Self(ip));
+            }
+        }
+
+        let ConnectInfo(addr) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None))?;
+
+        Ok(Self(addr.ip()))
+    }
+}
@@ -0,0 +1,184 @@
+use axum::{
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::headers::{Cookie, HeaderMapExt as _};
+use serde::Serialize;
+
+use crate::{
+    config::{AppConfig, SessionMode},
+    http::result::HttpResult,
+    model::{
+        invitation::AcceptInvitationReply,
+        organization::SwitchOrgReply,
+        user::{ChangePasswordReply, LoginUserReply, RegisterUserReply},
+    },
+    service::{self, result::ServiceResult},
+    state::AppState,
+};
+
+/// Replies handing out a token pair, which cookie mode keeps away from the browser.
+pub trait TokenPairReply {
+    /// Strip the tokens from the reply, returning the refresh token if there was one.
+    fn take_refresh_token(&mut self) -> Option<String>;
+}
+
+impl TokenPairReply for LoginUserReply {
+    fn take_refresh_token(&mut self) -> Option<String> {
+        self.token = None;
+        self.expires_in = None;
+
+        self.refresh_token.take()
+    }
+}
+
+impl TokenPairReply for RegisterUserReply {
+    fn take_refresh_token(&mut self) -> Option<String> {
+        self.token = None;
+        self.expires_in = None;
+
+        self.refresh_token.take()
+    }
+}
+
+impl TokenPairReply for AcceptInvitationReply {
+    fn take_refresh_token(&mut self) -> Option<String> {
+        self.token = None;
+        self.expires_in = None;
+
+        self.refresh_token.take()
+    }
+}
+
+impl TokenPairReply for ChangePasswordReply {
+    fn take_refresh_token(&mut self) -> Option<String> {
+        self.token = None;
+        self.expires_in = None;
+
+        self.refresh_token.take()
+    }
+}
+
+impl TokenPairReply for SwitchOrgReply {
+    fn take_refresh_token(&mut self) -> Option<String> {
+        self.token = None;
+        self.expires_in = None;
+
+        self.refresh_token.take()
+    }
+}
+
+fn cookie(config: &AppConfig, name: &str, value: &str, max_age: u64, http_only: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; Path=/; Max-Age={}; SameSite={}",
+        name,
+        value,
+        max_age,
+        config.session_cookie_same_site.as_str()
+    );
+
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+
+    if config.session_cookie_secure {
+        cookie.push_str("; Secure");
+    }
+
+    cookie
+}
+
+fn append_cookie(headers: &mut HeaderMap, cookie: String) {
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        headers.append(header::SET_COOKIE, value);
+    }
+}
+
+/// The session cookie of a request, if any.
+pub fn session_token(config: &AppConfig, headers: &HeaderMap) -> Option<String> {
+    headers
+        .typed_get::<Cookie>()
+        .and_then(|cookie| cookie.get(&config.session_cookie_name).map(str::to_string))
+}
+
+/// The CSRF token of a request as found in its cookie and in its header.
+pub fn csrf_tokens(config: &AppConfig, headers: &HeaderMap) -> Option<(String, String)> {
+    let cookie = headers
+        .typed_get::<Cookie>()
+        .and_then(|cookie| cookie.get(&config.csrf_cookie_name).map(str::to_string))?;
+
+    let header = headers
+        .get(config.csrf_header_name.as_str())
+        .and_then(|v| v.to_str().ok())?;
+
+    Some((cookie, header.to_string()))
+}
+
+/// Answer a request that issued a token pair. In cookie mode the pair is moved into
+/// a cookie session, and the reply only carries the session and CSRF cookies.
+pub async fn with_session<T>(state: &AppState, result: ServiceResult<T>) -> Response
+where
+    T: Serialize + TokenPairReply,
+{
+    let config = state.config();
+
+    let mut value = match result {
+        Ok(value) if config.session_mode == SessionMode::Cookie => value,
+        result => return HttpResult::from(result).into_response(),
+    };
+
+    let refresh_token = value.data.as_mut().and_then(T::take_refresh_token);
+
+    let Some(refresh_token) = refresh_token else {
+        return HttpResult::from(Ok(value)).into_response();
+    };
+
+    let tokens =
+        match service::cookie_session::start_cookie_session(&refresh_token, config, state.cache())
+            .await
+        {
+            Ok(tokens) => tokens,
+            Err(err) => return HttpResult::<T>::from(err).into_response(),
+        };
+
+    let mut response = HttpResult::from(Ok(value)).into_response();
+
+    let max_age = config.refresh_token_exp_seconds;
+
+    append_cookie(
+        response.headers_mut(),
+        cookie(
+            config,
+            &config.session_cookie_name,
+            &tokens.session_token,
+            max_age,
+            true,
+        ),
+    );
+    // Scripts read this one to send it back in the CSRF header.
+    append_cookie(
+        response.headers_mut(),
+        cookie(
+            config,
+            &config.csrf_cookie_name,
+            &tokens.csrf_token,
+            max_age,
+            false,
+        ),
+    );
+
+    response
+}
+
+/// Expire the session and CSRF cookies.
+pub fn clear_session(config: &AppConfig, response: &mut Response) {
+    for (name, http_only) in [
+        (&config.session_cookie_name, true),
+        (&config.csrf_cookie_name, false),
+    ] {
+        append_cookie(
+            response.headers_mut(),
+            cookie(config, name, "", 0, http_only),
+        );
+    }
+}
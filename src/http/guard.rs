@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use axum::extract::{FromRequestParts, Path};
+use axum::http::{StatusCode, request::Parts};
+
+use crate::{
+    config::EmailVerificationPolicy, http::result::HttpResult, jwt_codec::UserClaims,
+    model::organization::OrgRole, permission::Permission, service, state::AppState,
+};
+
+/// Claims inserted by `authorize_middleware`; guards only work on routes behind it.
+fn caller_claims(parts: &Parts) -> Result<UserClaims, HttpResult<()>> {
+    parts
+        .extensions
+        .get::<UserClaims>()
+        .cloned()
+        .ok_or_else(|| HttpResult::new(StatusCode::UNAUTHORIZED, None, None))
+}
+
+fn forbidden(claims: &UserClaims, permission: &str) -> HttpResult<()> {
+    tracing::debug!("User {} lacks permission {}", claims.sub, permission);
+
+    HttpResult::new(
+        StatusCode::FORBIDDEN,
+        Some("Permission denied".to_string()),
+        None,
+    )
+}
+
+/// Extractor letting through callers holding the permission `P`.
+pub struct Require<P> {
+    pub claims: UserClaims,
+    _permission: PhantomData<P>,
+}
+
+impl<S, P> FromRequestParts<S> for Require<P>
+where
+    S: Send + Sync,
+    P: Permission,
+{
+    type Rejection = HttpResult<()>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let claims = caller_claims(parts)?;
+
+        if !claims.has_permission(P::NAME) {
+            return Err(forbidden(&claims, P::NAME));
+        }
+
+        Ok(Self {
+            claims,
+            _permission: PhantomData,
+        })
+    }
+}
+
+/// Extractor letting through the user named by the `{user_id}` path segment,
+/// or callers holding the permission `P` (typically administrators).
+pub struct OwnerOr<P> {
+    pub user_id: String,
+    _permission: PhantomData<P>,
+}
+
+impl<S, P> FromRequestParts<S> for OwnerOr<P>
+where
+    S: Send + Sync,
+    P: Permission,
+{
+    type Rejection = HttpResult<()>;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = caller_claims(parts)?;
+
+        let Path(mut params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| HttpResult::new(StatusCode::BAD_REQUEST, None, None))?;
+
+        let user_id = params
+            .remove("user_id")
+            .ok_or_else(|| HttpResult::new(StatusCode::BAD_REQUEST, None, None))?;
+
+        if claims.sub != user_id && !claims.has_permission(P::NAME) {
+            return Err(forbidden(&claims, P::NAME));
+        }
+
+        Ok(Self {
+            user_id,
+            _permission: PhantomData,
+        })
+    }
+}
+
+/// Extractor letting through callers whose token says their email is verified,
+/// or every caller when the policy does not block routes.
+/// A freshly verified email shows up in tokens issued from the next refresh on.
+pub struct VerifiedEmail {
+    pub claims: UserClaims,
+}
+
+impl FromRequestParts<AppState> for VerifiedEmail {
+    type Rejection = HttpResult<()>;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let claims = caller_claims(parts)?;
+
+        if state.config().email_verification_policy == EmailVerificationPolicy::BlockRoutes
+            && !claims.email_verified
+        {
+            tracing::debug!("User {} has not verified their email", claims.sub);
+
+            return Err(HttpResult::new(
+                StatusCode::FORBIDDEN,
+                Some("Email address is not verified".to_string()),
+                None,
+            ));
+        }
+
+        Ok(Self { claims })
+    }
+}
+
+/// Extractor resolving the organization a request acts on: the `{org_id}` path segment,
+/// or the active organization of the token on routes without one.
+/// Callers that are not members of that organization are rejected.
+pub struct Tenant {
+    pub claims: UserClaims,
+    pub org_id: String,
+    pub role: OrgRole,
+}
+
+impl FromRequestParts<AppState> for Tenant {
+    type Rejection = HttpResult<()>;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let claims = caller_claims(parts)?;
+
+        let path_org_id = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Path(mut params)| params.remove("org_id"));
+
+        let Some(org_id) = path_org_id.or_else(|| claims.org_id.clone()) else {
+            return Err(HttpResult::new(
+                StatusCode::BAD_REQUEST,
+                Some("No active organization".to_string()),
+                None,
+            ));
+        };
+
+        let role =
+            service::organization::select_member_role(state.repo().pool(), &org_id, &claims.sub)
+                .await
+                .map_err(HttpResult::from)?;
+
+        let Some(role) = role else {
+            tracing::debug!("User {} is not a member of {}", claims.sub, org_id);
+
+            return Err(HttpResult::new(
+                StatusCode::FORBIDDEN,
+                Some("Not a member of this organization".to_string()),
+                None,
+            ));
+        };
+
+        Ok(Self {
+            claims,
+            org_id,
+            role,
+        })
+    }
+}
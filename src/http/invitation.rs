@@ -0,0 +1,147 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::Response,
+};
+
+use crate::{
+    http::{
+        client_ip::ClientIp,
+        cookie_session::with_session,
+        guard::{Tenant, VerifiedEmail},
+        result::HttpResult,
+    },
+    model::invitation::{
+        AcceptInvitationArgs, AcceptInvitationReply, CreateInvitationArgs, InvitationReply,
+        ListInvitationsReply,
+    },
+    service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/{org_id}/invitations",
+    request_body = CreateInvitationArgs,
+    responses(
+        (status = 201, description = "Invitation created and mailed", body = HttpResult<InvitationReply>),
+        (status = 403, description = "Not an admin of this organization, or email address not verified"),
+        (status = 409, description = "Already a member of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Invitation"
+)]
+pub async fn create_invitation(
+    State(state): State<AppState>,
+    _verified: VerifiedEmail,
+    tenant: Tenant,
+    Json(payload): Json<CreateInvitationArgs>,
+) -> HttpResult<InvitationReply> {
+    service::invitation::create_invitation(
+        &tenant.org_id,
+        &tenant.claims.sub,
+        tenant.role,
+        payload,
+        state.config(),
+        state.repo(),
+        state.mailer(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/{org_id}/invitations",
+    responses(
+        (status = 200, description = "Pending invitations of the organization", body = HttpResult<ListInvitationsReply>),
+        (status = 403, description = "Not an admin of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Invitation"
+)]
+pub async fn list_invitations(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> HttpResult<ListInvitationsReply> {
+    service::invitation::list_invitations(&tenant.org_id, tenant.role, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{org_id}/invitations/{invitation_id}",
+    responses(
+        (status = 200, description = "Invitation revoked"),
+        (status = 403, description = "Not an admin of this organization"),
+        (status = 404, description = "No pending invitation with this ID")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization"),
+        ("invitation_id" = String, Path, description = "The ID of the invitation")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Invitation"
+)]
+pub async fn revoke_invitation(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path((_, invitation_id)): Path<(String, String)>,
+) -> HttpResult<()> {
+    service::invitation::revoke_invitation(
+        &tenant.org_id,
+        tenant.role,
+        &invitation_id,
+        state.repo(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/accept-invitation",
+    request_body = AcceptInvitationArgs,
+    responses(
+        (status = 200, description = "Invitation accepted, tokens with the organization active, or an MFA token", body = HttpResult<AcceptInvitationReply>),
+        (status = 400, description = "Password for the new account violates the password policy"),
+        (status = 401, description = "Wrong password for the existing account, or the account has been deleted"),
+        (status = 403, description = "Email address of the existing account is not verified"),
+        (status = 404, description = "Invalid, used, revoked or expired invitation"),
+        (status = 423, description = "Account temporarily locked, see Retry-After"),
+        (status = 429, description = "Too many failed logins, see Retry-After")
+    ),
+    tag = "Invitation"
+)]
+pub async fn accept_invitation(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<AcceptInvitationArgs>,
+) -> Response {
+    let result = service::invitation::accept_invitation(
+        payload,
+        client_ip,
+        state.config(),
+        state.password_policy(),
+        state.jwt_codec(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
@@ -0,0 +1,117 @@
+use axum::{Extension, Json, extract::State, response::Response};
+
+use crate::{
+    http::cookie_session::with_session,
+    http::result::HttpResult,
+    jwt_codec::UserClaims,
+    model::{
+        mfa::{ConfirmTotpArgs, ConfirmTotpReply, DisableTotpArgs, EnrollTotpReply, VerifyMfaArgs},
+        user::LoginUserReply,
+    },
+    service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/me/mfa/totp",
+    responses(
+        (status = 200, description = "TOTP secret generated, pending confirmation", body = HttpResult<EnrollTotpReply>),
+        (status = 409, description = "Two-factor authentication is already enabled")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "MFA"
+)]
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<EnrollTotpReply> {
+    service::mfa::enroll_totp(&claims, state.config(), state.repo(), state.secret_box())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/mfa/totp/confirm",
+    request_body = ConfirmTotpArgs,
+    responses(
+        (status = 200, description = "Two-factor authentication enabled", body = HttpResult<ConfirmTotpReply>),
+        (status = 400, description = "Invalid code"),
+        (status = 404, description = "No pending enrollment"),
+        (status = 409, description = "Two-factor authentication is already enabled")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "MFA"
+)]
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<ConfirmTotpArgs>,
+) -> HttpResult<ConfirmTotpReply> {
+    service::mfa::confirm_totp(
+        &claims,
+        payload,
+        state.config(),
+        state.repo(),
+        state.secret_box(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/mfa/totp/disable",
+    request_body = DisableTotpArgs,
+    responses(
+        (status = 200, description = "Two-factor authentication disabled, recovery codes dropped"),
+        (status = 401, description = "Password is incorrect"),
+        (status = 409, description = "Password changed by another request meanwhile")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "MFA"
+)]
+pub async fn disable_totp(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<DisableTotpArgs>,
+) -> HttpResult<()> {
+    service::mfa::disable_totp(&claims, payload, state.password_hasher(), state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/mfa/verify",
+    request_body = VerifyMfaArgs,
+    responses(
+        (status = 200, description = "Second factor accepted", body = HttpResult<LoginUserReply>),
+        (status = 401, description = "Invalid code or expired MFA token"),
+        (status = 429, description = "Too many attempts")
+    ),
+    tag = "MFA"
+)]
+pub async fn verify_mfa(
+    State(state): State<AppState>,
+    Json(payload): Json<VerifyMfaArgs>,
+) -> Response {
+    let result = service::mfa::verify_mfa(
+        payload,
+        state.config(),
+        state.jwt_codec(),
+        state.repo(),
+        state.cache(),
+        state.secret_box(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
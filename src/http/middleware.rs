@@ -1,30 +1,264 @@
 pub mod authorization {
     use axum::{
-        extract::{Request, State},
-        http::StatusCode,
+        RequestExt as _,
+        extract::{MatchedPath, RawPathParams, Request, State},
+        http::{StatusCode, header},
         middleware::Next,
         response::Response,
     };
-    use axum_extra::{
-        extract::TypedHeader,
-        headers::{Authorization, authorization::Bearer},
+    use axum_extra::headers::{Authorization, HeaderMapExt as _, authorization::Bearer};
+
+    use std::time::Duration;
+
+    use time::OffsetDateTime;
+
+    use crate::{
+        config::{FailurePolicy, SessionMode},
+        http::{client_ip::ClientIp, cookie_session},
+        jwt_codec::{DecodeError, UserClaims},
+        result_trace::ResultTrace as _,
+        scope,
+        service::{self, auth::TokenStatus, result::ServiceError},
+        state::AppState,
     };
 
-    use crate::{result_trace::ResultTrace as _, state::AppState};
+    /// Reject tokens revoked by a logout. Redis is only given a short time to answer,
+    /// after which the configured failure policy decides whether to let the request through.
+    /// Returns the token's status, `None` when Redis did not tell.
+    async fn check_revocation(
+        state: &AppState,
+        claims: &UserClaims,
+    ) -> Result<Option<TokenStatus>, StatusCode> {
+        let config = state.config();
+
+        let timeout = Duration::from_millis(config.revocation_check_timeout_ms);
+
+        let failure =
+            match tokio::time::timeout(timeout, service::auth::token_status(claims, state.cache()))
+                .await
+            {
+                Ok(Ok(status)) if status.revoked => return Err(StatusCode::UNAUTHORIZED),
+                Ok(Ok(status)) => return Ok(Some(status)),
+                Ok(Err(err)) => err.to_string(),
+                Err(_) => format!("no answer within {} ms", config.revocation_check_timeout_ms),
+            };
+
+        tracing::warn!(
+            "Revocation check failed for token {}: {}",
+            claims.jti,
+            failure
+        );
+
+        match config.revocation_failure_policy {
+            FailurePolicy::FailOpen => Ok(None),
+            FailurePolicy::FailClosed => Err(StatusCode::SERVICE_UNAVAILABLE),
+        }
+    }
+
+    /// Tokens issued to OAuth clients and API keys only reach routes their scopes cover.
+    fn check_scope(claims: &UserClaims, request: &Request) -> Result<(), StatusCode> {
+        if claims.scope.is_none() {
+            return Ok(());
+        }
+
+        let required = request
+            .extensions()
+            .get::<MatchedPath>()
+            .and_then(|path| scope::required_scope(request.method(), path.as_str()));
+
+        match required {
+            Some(required) if claims.has_scope(required) => Ok(()),
+            _ => {
+                tracing::debug!(
+                    "Token {} of client {:?} lacks the scope for {} {}",
+                    claims.jti,
+                    claims.client_id,
+                    request.method(),
+                    request.uri().path()
+                );
+
+                Err(StatusCode::FORBIDDEN)
+            }
+        }
+    }
+
+    /// Header carrying an API key, for clients that keep `Authorization` for something else.
+    const API_KEY_HEADER: &str = "x-api-key";
+
+    fn decode_token(state: &AppState, token: &str) -> Result<UserClaims, StatusCode> {
+        let decoded = state.jwt_codec().decode(token);
+
+        // Expired tokens are part of the normal refresh cycle, anything else is suspicious.
+        let decoded = match decoded {
+            Err(DecodeError::Expired) => decoded.trace_debug(),
+            _ => decoded.trace_warn(),
+        };
+
+        decoded.map_err(|_| StatusCode::UNAUTHORIZED)
+    }
+
+    /// Resolve an API key. An org-scoped key is refused on routes naming another organization.
+    async fn authenticate_api_key(
+        state: &AppState,
+        key: &str,
+        request: &mut Request,
+    ) -> Result<UserClaims, StatusCode> {
+        let claims = service::api_key::authenticate_api_key(key, state.config(), state.repo())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        if let Some(org_id) = &claims.org_id
+            && let Ok(params) = request.extract_parts::<RawPathParams>().await
+            && params.iter().any(|(k, v)| k == "org_id" && v != org_id)
+        {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(claims)
+    }
+
+    /// Resolve a session cookie, enforcing the CSRF header on unsafe methods.
+    async fn authenticate_cookie(
+        state: &AppState,
+        token: &str,
+        csrf: Option<(String, String)>,
+        unsafe_method: bool,
+    ) -> Result<UserClaims, StatusCode> {
+        let config = state.config();
 
+        service::cookie_session::authenticate_cookie_session(
+            token,
+            csrf.as_ref().map(|(c, h)| (c.as_str(), h.as_str())),
+            unsafe_method,
+            config,
+            state.repo(),
+            state.cache(),
+        )
+        .await
+        .map_err(|err| match err {
+            ServiceError::Generic { code, .. } => {
+                StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?
+        .ok_or(StatusCode::UNAUTHORIZED)
+    }
+
+    /// Record the device behind a session token, when the session record read by the
+    /// revocation check is stale. Runs detached, so that bookkeeping never slows a request
+    /// down or fails it.
+    async fn touch_session(
+        state: &AppState,
+        claims: &UserClaims,
+        status: Option<TokenStatus>,
+        request: &mut Request,
+    ) {
+        // Without an answer from Redis, writing to it is not worth a try.
+        let Some(status) = status else {
+            return;
+        };
+
+        if claims.sid.is_none() {
+            return;
+        }
+
+        let user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let Ok(ClientIp(ip)) = request.extract_parts_with_state::<ClientIp, _>(state).await else {
+            return;
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        if !service::session::needs_touch(
+            status.session_record.as_deref(),
+            user_agent.as_deref(),
+            ip,
+            now,
+        ) {
+            return;
+        }
+
+        let state = state.clone();
+        let claims = claims.clone();
+
+        tokio::spawn(async move {
+            let _ = service::session::touch_session(
+                &claims,
+                user_agent.as_deref(),
+                ip,
+                state.config(),
+                state.cache(),
+            )
+            .await
+            .trace_warn();
+        });
+    }
+
+    /// Authenticate with a JWT or an API key, sent as `Authorization: Bearer` or
+    /// `X-API-Key`, or with the session cookie when running in cookie mode, and insert
+    /// the resulting `UserClaims` for handlers and guards.
     pub async fn authorize_middleware(
         State(state): State<AppState>,
-        TypedHeader(auth_header): TypedHeader<Authorization<Bearer>>,
         mut request: Request,
         next: Next,
     ) -> Result<Response, StatusCode> {
-        let token_str = auth_header.token();
+        let bearer = request
+            .headers()
+            .typed_get::<Authorization<Bearer>>()
+            .map(|auth| auth.token().to_string());
+
+        let api_key = request
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let claims = match (bearer, api_key) {
+            (Some(token), _) if service::api_key::is_api_key(&token) => {
+                authenticate_api_key(&state, &token, &mut request).await?
+            }
+            (Some(token), _) => {
+                let claims = decode_token(&state, &token)?;
+
+                let status = check_revocation(&state, &claims).await?;
+
+                touch_session(&state, &claims, status, &mut request).await;
+
+                claims
+            }
+            (None, Some(key)) => authenticate_api_key(&state, &key, &mut request).await?,
+            (None, None) => {
+                let token = match state.config().session_mode {
+                    SessionMode::Cookie => {
+                        cookie_session::session_token(state.config(), request.headers())
+                    }
+                    SessionMode::Bearer => None,
+                };
+
+                let Some(token) = token else {
+                    return Err(StatusCode::UNAUTHORIZED);
+                };
+
+                let csrf = cookie_session::csrf_tokens(state.config(), request.headers());
+                let unsafe_method = !request.method().is_safe();
+
+                let claims = authenticate_cookie(&state, &token, csrf, unsafe_method).await?;
+
+                let status = check_revocation(&state, &claims).await?;
+
+                touch_session(&state, &claims, status, &mut request).await;
+
+                claims
+            }
+        };
 
-        let claims = state
-            .jwt_codec()
-            .decode(token_str)
-            .trace_warn()
-            .map_err(|_| StatusCode::UNAUTHORIZED)?;
+        check_scope(&claims, &request)?;
 
         request.extensions_mut().insert(claims);
 
@@ -0,0 +1,363 @@
+use axum::{
+    Extension, Form, Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    extract::TypedHeader,
+    headers::{Authorization, authorization::Basic},
+};
+use serde::Serialize;
+
+use crate::{
+    http::{guard::VerifiedEmail, result::HttpResult},
+    jwt_codec::UserClaims,
+    model::oauth::{
+        CreateOAuthClientArgs, CreateOAuthClientReply, ListOAuthClientsReply, OAuthAuthorizeArgs,
+        OAuthAuthorizeReply, OAuthClientCredentials, OAuthConsentArgs, OAuthConsentReply,
+        OAuthErrorReply, OAuthIntrospectReply, OAuthTokenArgs, OAuthTokenReply, OAuthTokenTypeArgs,
+    },
+    service::{
+        self,
+        result::{ServiceError, ServiceResult},
+    },
+    state::AppState,
+};
+
+/// Undo the form encoding RFC 6749 section 2.3.1 applies to the client ID and secret
+/// before they go into the `Basic` header. `None` for a malformed escape or text that
+/// is not UTF-8.
+fn form_urldecode(value: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' => decoded.extend(hex::decode([bytes.next()?, bytes.next()?]).ok()?),
+            byte => decoded.push(byte),
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Client authentication from the `Authorization: Basic` header, or else the form.
+/// Malformed header credentials count as none.
+fn client_credentials(
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Option<OAuthClientCredentials> {
+    match basic {
+        Some(TypedHeader(Authorization(basic))) => Some(OAuthClientCredentials {
+            client_id: form_urldecode(basic.username())?,
+            client_secret: Some(form_urldecode(basic.password())?),
+        }),
+        None => client_id.map(|client_id| OAuthClientCredentials {
+            client_id,
+            client_secret,
+        }),
+    }
+}
+
+/// Protocol endpoints answer with bare RFC 6749 bodies instead of an `HttpResult`,
+/// as OAuth client libraries expect.
+fn protocol_response<T: Serialize>(result: ServiceResult<T>) -> Response {
+    let no_store = [(header::CACHE_CONTROL, "no-store")];
+
+    match result {
+        Ok(value) => match value.data {
+            Some(data) => (no_store, Json(data)).into_response(),
+            None => (no_store, StatusCode::OK).into_response(),
+        },
+        Err(ServiceError::Generic { code, message }) => {
+            let status = StatusCode::from_u16(code).unwrap_or(StatusCode::BAD_REQUEST);
+
+            let reply = Json(OAuthErrorReply { error: message });
+
+            if status == StatusCode::UNAUTHORIZED {
+                let challenge = [(header::WWW_AUTHENTICATE, "Basic realm=\"oauth\"")];
+
+                return (status, no_store, challenge, reply).into_response();
+            }
+
+            (status, no_store, reply).into_response()
+        }
+        Err(err) => {
+            tracing::trace!("ServiceError encountered: {}", err);
+
+            let reply = Json(OAuthErrorReply {
+                error: "server_error".to_string(),
+            });
+
+            (StatusCode::INTERNAL_SERVER_ERROR, no_store, reply).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/clients",
+    request_body = CreateOAuthClientArgs,
+    responses(
+        (status = 201, description = "Client registered, the secret is shown only once", body = HttpResult<CreateOAuthClientReply>),
+        (status = 400, description = "Invalid redirect URI or scope"),
+        (status = 403, description = "Email address is not verified")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "OAuth"
+)]
+pub async fn create_client(
+    State(state): State<AppState>,
+    VerifiedEmail { claims }: VerifiedEmail,
+    Json(payload): Json<CreateOAuthClientArgs>,
+) -> HttpResult<CreateOAuthClientReply> {
+    service::oauth::create_client(&claims, payload, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/clients",
+    responses(
+        (status = 200, description = "Clients registered by the caller", body = HttpResult<ListOAuthClientsReply>)
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "OAuth"
+)]
+pub async fn list_clients(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<ListOAuthClientsReply> {
+    service::oauth::list_clients(&claims, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/clients/{client_id}",
+    responses(
+        (status = 200, description = "Client deleted"),
+        (status = 404, description = "Client not found")
+    ),
+    params(
+        ("client_id" = String, Path, description = "The ID of the client")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "OAuth"
+)]
+pub async fn delete_client(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Path(client_id): Path<String>,
+) -> HttpResult<()> {
+    service::oauth::delete_client(&claims, &client_id, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/authorize",
+    params(OAuthAuthorizeArgs),
+    responses(
+        (status = 200, description = "Client and scopes to show on the consent page", body = HttpResult<OAuthAuthorizeReply>),
+        (status = 400, description = "Invalid authorization request")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "OAuth"
+)]
+pub async fn authorize(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Query(params): Query<OAuthAuthorizeArgs>,
+) -> HttpResult<OAuthAuthorizeReply> {
+    service::oauth::authorize(&claims, params, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/consent",
+    request_body = OAuthConsentArgs,
+    responses(
+        (status = 200, description = "Redirect back to the client, with a code when approved", body = HttpResult<OAuthConsentReply>),
+        (status = 400, description = "Invalid authorization request")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "OAuth"
+)]
+pub async fn consent(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<OAuthConsentArgs>,
+) -> HttpResult<OAuthConsentReply> {
+    service::oauth::consent(
+        &claims,
+        payload,
+        state.config(),
+        state.repo(),
+        state.cache(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/token",
+    request_body(content = OAuthTokenArgs, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Access token issued", body = OAuthTokenReply),
+        (status = 400, description = "Invalid grant, scope or request", body = OAuthErrorReply),
+        (status = 401, description = "Client authentication failed", body = OAuthErrorReply)
+    ),
+    tag = "OAuth"
+)]
+pub async fn token(
+    State(state): State<AppState>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(payload): Form<OAuthTokenArgs>,
+) -> Response {
+    let credentials = client_credentials(
+        basic,
+        payload.client_id.clone(),
+        payload.client_secret.clone(),
+    );
+
+    protocol_response(
+        service::oauth::token(
+            credentials,
+            payload,
+            state.config(),
+            state.jwt_codec(),
+            state.repo(),
+            state.cache(),
+        )
+        .await,
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/introspect",
+    request_body(content = OAuthTokenTypeArgs, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token state, inactive for unknown tokens", body = OAuthIntrospectReply),
+        (status = 401, description = "Client authentication failed", body = OAuthErrorReply)
+    ),
+    tag = "OAuth"
+)]
+pub async fn introspect(
+    State(state): State<AppState>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(payload): Form<OAuthTokenTypeArgs>,
+) -> Response {
+    let credentials = client_credentials(
+        basic,
+        payload.client_id.clone(),
+        payload.client_secret.clone(),
+    );
+
+    protocol_response(
+        service::oauth::introspect(
+            credentials,
+            payload,
+            state.jwt_codec(),
+            state.repo(),
+            state.cache(),
+        )
+        .await,
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/revoke",
+    request_body(content = OAuthTokenTypeArgs, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token revoked, or unknown"),
+        (status = 401, description = "Client authentication failed", body = OAuthErrorReply)
+    ),
+    tag = "OAuth"
+)]
+pub async fn revoke(
+    State(state): State<AppState>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(payload): Form<OAuthTokenTypeArgs>,
+) -> Response {
+    let credentials = client_credentials(
+        basic,
+        payload.client_id.clone(),
+        payload.client_secret.clone(),
+    );
+
+    protocol_response(
+        service::oauth::revoke(
+            credentials,
+            payload,
+            state.jwt_codec(),
+            state.repo(),
+            state.cache(),
+        )
+        .await,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(client_id: &str, client_secret: &str) -> Option<OAuthClientCredentials> {
+        let header = TypedHeader(Authorization::basic(client_id, client_secret));
+
+        client_credentials(Some(header), None, None)
+    }
+
+    #[test]
+    fn decodes_form_encoded_basic_credentials() {
+        let credentials = basic("my%20app", "a+b%2Fc%3A%25d%C3%A9").unwrap();
+
+        assert_eq!(credentials.client_id, "my app");
+        assert_eq!(credentials.client_secret.as_deref(), Some("a b/c:%dé"));
+    }
+
+    #[test]
+    fn keeps_unreserved_basic_credentials() {
+        let credentials = basic("client-1", "s3cr3t_~.").unwrap();
+
+        assert_eq!(credentials.client_id, "client-1");
+        assert_eq!(credentials.client_secret.as_deref(), Some("s3cr3t_~."));
+    }
+
+    #[test]
+    fn refuses_malformed_escapes() {
+        assert!(basic("client", "abc%2").is_none());
+        assert!(basic("client", "abc%zz").is_none());
+        assert!(basic("client%FF", "secret").is_none());
+    }
+
+    #[test]
+    fn leaves_form_credentials_alone() {
+        let credentials =
+            client_credentials(None, Some("client".to_string()), Some("a+b".to_string())).unwrap();
+
+        // The form body was decoded already when it was parsed.
+        assert_eq!(credentials.client_secret.as_deref(), Some("a+b"));
+    }
+}
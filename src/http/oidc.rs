@@ -0,0 +1,182 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::Response,
+};
+
+use crate::{
+    http::cookie_session::with_session,
+    http::result::HttpResult,
+    model::{
+        oidc::{OidcAuthorizeReply, OidcCallbackArgs},
+        user::LoginUserReply,
+    },
+    service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/oidc/{provider}/authorize",
+    responses(
+        (status = 200, description = "Authorization URL at the provider", body = HttpResult<OidcAuthorizeReply>),
+        (status = 404, description = "Unknown identity provider"),
+        (status = 502, description = "Provider discovery failed")
+    ),
+    params(
+        ("provider" = String, Path, description = "The name of the configured provider")
+    ),
+    tag = "OIDC"
+)]
+pub async fn authorize(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+) -> HttpResult<OidcAuthorizeReply> {
+    service::oidc::authorize(
+        &provider,
+        state.config(),
+        state.cache(),
+        state.oidc_client(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/oidc/{provider}/callback",
+    request_body = OidcCallbackArgs,
+    responses(
+        (status = 200, description = "Signed in with the provider", body = HttpResult<LoginUserReply>),
+        (status = 400, description = "Invalid or expired state"),
+        (status = 403, description = "Email address is not verified"),
+        (status = 404, description = "Unknown identity provider"),
+        (status = 409, description = "Email already registered"),
+        (status = 502, description = "Code exchange or ID token validation failed")
+    ),
+    params(
+        ("provider" = String, Path, description = "The name of the configured provider")
+    ),
+    tag = "OIDC"
+)]
+pub async fn callback(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Json(payload): Json<OidcCallbackArgs>,
+) -> Response {
+    let result = service::oidc::callback(
+        &provider,
+        payload,
+        state.config(),
+        state.jwt_codec(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+        state.oidc_client(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use reqwest::Url;
+    use serde_json::json;
+    use time::OffsetDateTime;
+
+    use crate::{
+        config::EmailVerificationPolicy,
+        http::test_support::{self, PASSWORD, send, unique_email},
+        oidc_client::mock::{self, CLIENT_ID, jwk, sign, signing_key},
+    };
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn linking_an_unverified_account_locks_out_its_registrant() {
+        let key = signing_key(1);
+        let served = mock::serve(json!({ "keys": [jwk(&key, "k1")] })).await;
+
+        let mut config = test_support::config();
+        config.email_verification_policy = EmailVerificationPolicy::Optional;
+        config.oidc_providers = vec![served.provider_config(true)];
+
+        let state = test_support::app_state(config).await;
+        let router = test_support::router(&state).await;
+
+        // Somebody registers the address before its owner, who never verifies it.
+        let email = unique_email();
+        let squatter_token = test_support::register(&router, &email).await;
+
+        let (status, body) = send(
+            &router,
+            Method::GET,
+            "/auth/oidc/mock/authorize",
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let url = Url::parse(body["data"]["authorization_url"].as_str().unwrap()).unwrap();
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+                .unwrap()
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        *served.id_token.lock().unwrap() = sign(
+            &key,
+            "k1",
+            &json!({
+                "iss": served.issuer,
+                "aud": CLIENT_ID,
+                "sub": uuid::Uuid::now_v7().to_string(),
+                "email": email,
+                "email_verified": true,
+                "iat": now,
+                "exp": now + 300,
+                "nonce": param("nonce"),
+            }),
+        );
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/auth/oidc/mock/callback",
+            None,
+            Some(json!({ "code": "code", "state": param("state") })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let owner_token = body["data"]["token"].as_str().unwrap();
+
+        let (status, _) = send(&router, Method::GET, "/api/orgs", Some(owner_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(
+            &router,
+            Method::GET,
+            "/api/orgs",
+            Some(&squatter_token),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(
+            &router,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "email": email, "password": PASSWORD })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
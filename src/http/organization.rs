@@ -0,0 +1,341 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    response::Response,
+};
+
+use crate::{
+    http::{
+        cookie_session::with_session,
+        guard::{Tenant, VerifiedEmail},
+        result::HttpResult,
+    },
+    jwt_codec::UserClaims,
+    model::organization::{
+        CreateOrgArgs, ListMembersReply, ListOrgsReply, OrgReply, SetMemberRoleArgs,
+        SwitchOrgReply, UpdateOrgArgs,
+    },
+    service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "",
+    request_body = CreateOrgArgs,
+    responses(
+        (status = 201, description = "Organization created, owned by the caller", body = HttpResult<OrgReply>),
+        (status = 403, description = "Email address is not verified")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn create_org(
+    State(state): State<AppState>,
+    verified: VerifiedEmail,
+    Json(payload): Json<CreateOrgArgs>,
+) -> HttpResult<OrgReply> {
+    service::organization::create_org(&verified.claims.sub, payload, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    responses(
+        (status = 200, description = "Organizations the caller is a member of", body = HttpResult<ListOrgsReply>)
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn list_orgs(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<ListOrgsReply> {
+    service::organization::list_orgs(&claims.sub, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/{org_id}",
+    responses(
+        (status = 200, description = "Get organization successful", body = HttpResult<OrgReply>),
+        (status = 403, description = "Not a member of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn get_org(State(state): State<AppState>, tenant: Tenant) -> HttpResult<OrgReply> {
+    service::organization::get_org(&tenant.org_id, &tenant.claims.sub, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    patch,
+    path = "/{org_id}",
+    request_body = UpdateOrgArgs,
+    responses(
+        (status = 200, description = "Organization updated", body = HttpResult<OrgReply>),
+        (status = 403, description = "Not an admin of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn update_org(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Json(payload): Json<UpdateOrgArgs>,
+) -> HttpResult<OrgReply> {
+    service::organization::update_org(
+        &tenant.org_id,
+        &tenant.claims.sub,
+        tenant.role,
+        payload,
+        state.repo(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{org_id}",
+    responses(
+        (status = 200, description = "Organization deleted"),
+        (status = 403, description = "Not an owner of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn delete_org(State(state): State<AppState>, tenant: Tenant) -> HttpResult<()> {
+    service::organization::delete_org(&tenant.org_id, tenant.role, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/{org_id}/members",
+    responses(
+        (status = 200, description = "Members of the organization", body = HttpResult<ListMembersReply>),
+        (status = 403, description = "Not a member of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn list_members(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> HttpResult<ListMembersReply> {
+    service::organization::list_members(&tenant.org_id, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    put,
+    path = "/{org_id}/members/{user_id}",
+    request_body = SetMemberRoleArgs,
+    responses(
+        (status = 200, description = "Member role updated"),
+        (status = 403, description = "Not allowed to change this role"),
+        (status = 404, description = "Member not found"),
+        (status = 409, description = "The last owner cannot be demoted")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization"),
+        ("user_id" = String, Path, description = "The ID of the member")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn set_member_role(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path((_, user_id)): Path<(String, String)>,
+    Json(payload): Json<SetMemberRoleArgs>,
+) -> HttpResult<()> {
+    service::organization::set_member_role(
+        &tenant.org_id,
+        tenant.role,
+        &user_id,
+        payload,
+        state.repo(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/{org_id}/members/{user_id}",
+    responses(
+        (status = 200, description = "Member removed"),
+        (status = 403, description = "Not allowed to remove this member"),
+        (status = 404, description = "Member not found"),
+        (status = 409, description = "The last owner cannot leave")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization"),
+        ("user_id" = String, Path, description = "The ID of the member, the caller's own to leave")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn remove_member(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path((_, user_id)): Path<(String, String)>,
+) -> HttpResult<()> {
+    service::organization::remove_member(
+        &tenant.org_id,
+        &tenant.claims.sub,
+        tenant.role,
+        &user_id,
+        state.repo(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/{org_id}/switch",
+    responses(
+        (status = 200, description = "New tokens with the organization active, replacing the session of the calling token", body = HttpResult<SwitchOrgReply>),
+        (status = 403, description = "Not a member of this organization")
+    ),
+    params(
+        ("org_id" = String, Path, description = "The ID of the organization to make active")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Organization"
+)]
+pub async fn switch_org(State(state): State<AppState>, tenant: Tenant) -> Response {
+    let result = service::auth::switch_org(
+        &tenant.org_id,
+        &tenant.claims,
+        state.config(),
+        state.jwt_codec(),
+        state.repo(),
+        state.cache(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        config::EmailVerificationPolicy,
+        http::test_support::{self, PASSWORD, send, unique_email},
+    };
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn switch_org_ends_the_previous_session() {
+        let mut config = test_support::config();
+        config.email_verification_policy = EmailVerificationPolicy::Optional;
+
+        let state = test_support::app_state(config).await;
+        let router = test_support::router(&state).await;
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/auth/register",
+            None,
+            Some(json!({
+                "email": unique_email(),
+                "nickname": "tester",
+                "password": PASSWORD,
+            })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        let old_token = body["data"]["token"].as_str().unwrap().to_string();
+        let old_refresh_token = body["data"]["refresh_token"].as_str().unwrap().to_string();
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/api/orgs",
+            Some(&old_token),
+            Some(json!({ "name": "Acme" })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        let org_id = body["data"]["org_id"].as_str().unwrap();
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            &format!("/api/orgs/{}/switch", org_id),
+            Some(&old_token),
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let new_token = body["data"]["token"].as_str().unwrap();
+
+        let (status, _) = send(&router, Method::GET, "/api/orgs", Some(new_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(&router, Method::GET, "/api/orgs", Some(&old_token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send(
+            &router,
+            Method::POST,
+            "/auth/refresh",
+            None,
+            Some(json!({ "refresh_token": old_refresh_token })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
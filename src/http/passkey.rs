@@ -0,0 +1,333 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    response::Response,
+};
+
+use crate::{
+    http::cookie_session::with_session,
+    http::result::HttpResult,
+    jwt_codec::UserClaims,
+    model::{
+        passkey::{
+            BeginPasskeyLoginArgs, FinishPasskeyLoginArgs, FinishPasskeyRegistrationArgs,
+            ListPasskeysReply, PasskeyCreationOptions, PasskeyReply, PasskeyRequestOptions,
+            RenamePasskeyArgs,
+        },
+        user::LoginUserReply,
+    },
+    service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/me/passkeys/register/begin",
+    responses(
+        (status = 200, description = "Options for navigator.credentials.create", body = HttpResult<PasskeyCreationOptions>)
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Passkey"
+)]
+pub async fn begin_registration(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<PasskeyCreationOptions> {
+    service::passkey::begin_registration(&claims, state.config(), state.repo(), state.cache())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/passkeys/register/finish",
+    request_body = FinishPasskeyRegistrationArgs,
+    responses(
+        (status = 201, description = "Passkey registered", body = HttpResult<PasskeyReply>),
+        (status = 400, description = "Invalid challenge or credential, or invalid name listed in `errors`"),
+        (status = 409, description = "Passkey already registered")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Passkey"
+)]
+pub async fn finish_registration(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<FinishPasskeyRegistrationArgs>,
+) -> HttpResult<PasskeyReply> {
+    service::passkey::finish_registration(
+        &claims,
+        payload,
+        state.config(),
+        state.repo(),
+        state.cache(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/passkeys",
+    responses(
+        (status = 200, description = "Passkeys of the caller", body = HttpResult<ListPasskeysReply>)
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Passkey"
+)]
+pub async fn list_passkeys(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<ListPasskeysReply> {
+    service::passkey::list_passkeys(&claims, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    patch,
+    path = "/me/passkeys/{passkey_id}",
+    request_body = RenamePasskeyArgs,
+    responses(
+        (status = 200, description = "Passkey renamed", body = HttpResult<PasskeyReply>),
+        (status = 400, description = "Invalid name, listed in `errors`"),
+        (status = 404, description = "Passkey not found")
+    ),
+    params(
+        ("passkey_id" = String, Path, description = "The credential ID of the passkey")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Passkey"
+)]
+pub async fn rename_passkey(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Path(passkey_id): Path<String>,
+    Json(payload): Json<RenamePasskeyArgs>,
+) -> HttpResult<PasskeyReply> {
+    service::passkey::rename_passkey(&claims, &passkey_id, payload, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/passkeys/{passkey_id}",
+    responses(
+        (status = 200, description = "Passkey deleted"),
+        (status = 404, description = "Passkey not found")
+    ),
+    params(
+        ("passkey_id" = String, Path, description = "The credential ID of the passkey")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Passkey"
+)]
+pub async fn delete_passkey(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Path(passkey_id): Path<String>,
+) -> HttpResult<()> {
+    service::passkey::delete_passkey(&claims, &passkey_id, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkey/begin",
+    request_body = BeginPasskeyLoginArgs,
+    responses(
+        (status = 200, description = "Options for navigator.credentials.get", body = HttpResult<PasskeyRequestOptions>)
+    ),
+    tag = "Passkey"
+)]
+pub async fn begin_login(
+    State(state): State<AppState>,
+    Json(payload): Json<BeginPasskeyLoginArgs>,
+) -> HttpResult<PasskeyRequestOptions> {
+    service::passkey::begin_login(payload, state.config(), state.repo(), state.cache())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkey/finish",
+    request_body = FinishPasskeyLoginArgs,
+    responses(
+        (status = 200, description = "Passkey login successful", body = HttpResult<LoginUserReply>),
+        (status = 400, description = "Invalid challenge or assertion"),
+        (status = 401, description = "Unknown passkey or failed verification"),
+        (status = 403, description = "Email address is not verified")
+    ),
+    tag = "Passkey"
+)]
+pub async fn finish_login(
+    State(state): State<AppState>,
+    Json(payload): Json<FinishPasskeyLoginArgs>,
+) -> Response {
+    let result = service::passkey::finish_login(
+        payload,
+        state.config(),
+        state.jwt_codec(),
+        state.repo(),
+        state.cache(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::{Value, json};
+
+    use crate::{
+        config::EmailVerificationPolicy,
+        http::test_support::{self, register, send, unique_email},
+        webauthn::{
+            encode_base64url,
+            soft::{SoftAuthenticator, client_data},
+        },
+    };
+
+    /// Run a login ceremony, returning the reply to its final step.
+    async fn login(
+        router: &axum::Router,
+        authenticator: &mut SoftAuthenticator,
+        email: &str,
+        origin: &str,
+    ) -> (StatusCode, Value) {
+        let (status, body) = send(
+            router,
+            Method::POST,
+            "/auth/passkey/begin",
+            None,
+            Some(json!({ "email": email })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let challenge = body["data"]["challenge"].as_str().expect("challenge");
+
+        let json = client_data("webauthn.get", challenge, origin);
+        let (auth_data, signature) = authenticator.assert("localhost", &json);
+
+        send(
+            router,
+            Method::POST,
+            "/auth/passkey/finish",
+            None,
+            Some(json!({
+                "credential": {
+                    "id": encode_base64url(&authenticator.credential_id),
+                    "response": {
+                        "clientDataJSON": encode_base64url(&json),
+                        "authenticatorData": encode_base64url(&auth_data),
+                        "signature": encode_base64url(&signature),
+                        "userHandle": null,
+                    },
+                },
+            })),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn register_then_log_in_with_a_passkey() {
+        let mut config = test_support::config();
+        config.email_verification_policy = EmailVerificationPolicy::Optional;
+        config.webauthn_rp_id = "localhost".to_string();
+        config.webauthn_origin = "http://localhost:3000".to_string();
+
+        let origin = config.webauthn_origin.clone();
+
+        let state = test_support::app_state(config).await;
+        let router = test_support::router(&state).await;
+
+        let email = unique_email();
+        let token = register(&router, &email).await;
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/api/users/me/passkeys/register/begin",
+            Some(&token),
+            None,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let challenge = body["data"]["challenge"].as_str().expect("challenge");
+
+        let mut authenticator = SoftAuthenticator::new();
+
+        let json = client_data("webauthn.create", challenge, &origin);
+
+        let credential = json!({
+            "id": encode_base64url(&authenticator.credential_id),
+            "response": {
+                "clientDataJSON": encode_base64url(&json),
+                "attestationObject":
+                    encode_base64url(&authenticator.attestation_object("localhost")),
+            },
+        });
+
+        let finish = |name: &str| {
+            send(
+                &router,
+                Method::POST,
+                "/api/users/me/passkeys/register/finish",
+                Some(&token),
+                Some(json!({ "name": name, "credential": credential })),
+            )
+        };
+
+        let (status, body) = finish("  ").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(body["errors"][0]["code"], "blank", "{}", body);
+
+        let (status, body) = finish(&"x".repeat(65)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(body["errors"][0]["code"], "too_long", "{}", body);
+
+        // The rejected names left the challenge for a corrected attempt.
+        let (status, body) = finish(" laptop ").await;
+
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["data"]["name"], "laptop", "{}", body);
+
+        let (status, body) = login(&router, &mut authenticator, &email, &origin).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["data"]["token"].is_string(), "{}", body);
+
+        // A signature from another origin is refused.
+        let (status, _) = login(&router, &mut authenticator, &email, "https://evil.example").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // A counter that does not move forward hints at a cloned authenticator,
+        // here it repeats the count of the first login.
+        authenticator.sign_count = 0;
+
+        let (status, _) = login(&router, &mut authenticator, &email, &origin).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,9 +1,15 @@
 use axum::response::IntoResponse;
-use axum::{Json, http::StatusCode};
+use axum::{
+    Json,
+    http::{StatusCode, header},
+};
 use serde::Serialize;
 use utoipa::ToSchema;
 
-use crate::service::result::{ServiceError, ServiceResult};
+use crate::{
+    model::validation::FieldError,
+    service::result::{ServiceError, ServiceResult},
+};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct HttpResult<T>
@@ -15,6 +21,12 @@ where
     pub message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<T>,
+    /// The invalid fields of a rejected request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+    /// Seconds sent in the `Retry-After` header.
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
 }
 
 impl<T> HttpResult<T>
@@ -26,6 +38,8 @@ where
             code: code.into(),
             message,
             data,
+            errors: None,
+            retry_after: None,
         }
     }
 }
@@ -45,6 +59,30 @@ where
                 Some(message),
                 None,
             ),
+            ServiceError::RetryAfter {
+                code,
+                message,
+                retry_after,
+            } => HttpResult {
+                retry_after: Some(retry_after),
+                ..HttpResult::new(
+                    StatusCode::from_u16(code).unwrap_or(StatusCode::TOO_MANY_REQUESTS),
+                    Some(message),
+                    None,
+                )
+            },
+            ServiceError::Fields {
+                code,
+                message,
+                errors,
+            } => HttpResult {
+                errors: Some(errors),
+                ..HttpResult::new(
+                    StatusCode::from_u16(code).unwrap_or(StatusCode::BAD_REQUEST),
+                    Some(message),
+                    None,
+                )
+            },
             ServiceError::JwtCodec(_) => {
                 HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None)
             }
@@ -57,6 +95,16 @@ where
             ServiceError::Database(_) => {
                 HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None)
             }
+            ServiceError::Serde(_) => {
+                HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None)
+            }
+            ServiceError::Mail(_) => HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None),
+            ServiceError::SecretBox(_) => {
+                HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None)
+            }
+            ServiceError::Totp(_) => HttpResult::new(StatusCode::INTERNAL_SERVER_ERROR, None, None),
+            // The identity provider failed or sent something that does not validate.
+            ServiceError::Oidc(_) => HttpResult::new(StatusCode::BAD_GATEWAY, None, None),
         }
     }
 }
@@ -72,6 +120,8 @@ where
                 code: value.code,
                 message: value.message,
                 data: value.data,
+                errors: None,
+                retry_after: None,
             },
             Err(err) => Self::from(err),
         }
@@ -85,8 +135,16 @@ where
     fn into_response(self) -> axum::response::Response {
         let status = StatusCode::from_u16(self.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
 
-        let body = Json(self);
+        let retry_after = self.retry_after;
+
+        let mut response = (status, Json(self)).into_response();
+
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, seconds.into());
+        }
 
-        (status, body).into_response()
+        response
     }
 }
@@ -0,0 +1,54 @@
+use axum::{
+    Extension,
+    extract::{Path, State},
+};
+
+use crate::{
+    http::result::HttpResult, jwt_codec::UserClaims, model::session::ListSessionsReply, service,
+    state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/me/sessions",
+    responses(
+        (status = 200, description = "Sessions of the caller, most recently active first", body = HttpResult<ListSessionsReply>)
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Session"
+)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+) -> HttpResult<ListSessionsReply> {
+    service::session::list_sessions(&claims, state.cache())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/sessions/{session_id}",
+    responses(
+        (status = 200, description = "Session terminated, its tokens stop working"),
+        (status = 404, description = "Session not found")
+    ),
+    params(
+        ("session_id" = String, Path, description = "The ID of the session")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Session"
+)]
+pub async fn terminate_session(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Path(session_id): Path<String>,
+) -> HttpResult<()> {
+    service::session::terminate_session(&claims, &session_id, state.cache())
+        .await
+        .into()
+}
@@ -0,0 +1,199 @@
+//! Application wired to a real Postgres and Redis for tests driving the router, given
+//! as `TEST_DATABASE_URL` and `TEST_REDIS_URL`. Such tests are ignored by default, run
+//! them with `cargo test -- --ignored`.
+
+use std::net::SocketAddr;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::connect_info::MockConnectInfo,
+    http::{Method, Request, StatusCode, header},
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use ed25519_dalek::{
+    SigningKey,
+    pkcs8::{EncodePrivateKey as _, EncodePublicKey as _, spki::der::pem::LineEnding},
+};
+use jsonwebtoken::Algorithm;
+use serde_json::Value;
+use tower::ServiceExt as _;
+
+use crate::{
+    cache::Cache,
+    config::{AppConfig, JwtKeyConfig, MailTransportConfig},
+    http::init_router,
+    jwt_codec::JwtCodec,
+    mailer::Mailer,
+    oidc_client::OidcClient,
+    password_hasher::PasswordHasher,
+    password_policy::PasswordPolicy,
+    repo::Repo,
+    secret_box::SecretBox,
+    state::AppState,
+};
+
+/// A password the policy accepts.
+pub const PASSWORD: &str = "correct-Horse-battery-9-staple";
+
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+
+    OsRng.fill_bytes(&mut bytes);
+
+    bytes
+}
+
+/// Write a fresh Ed25519 signing key pair, returning the config pointing at it.
+fn jwt_key() -> JwtKeyConfig {
+    let dir = std::env::temp_dir().join(format!("saas-test-{}", uuid::Uuid::now_v7()));
+
+    std::fs::create_dir_all(&dir).expect("create key dir");
+
+    let signing_key = SigningKey::from_bytes(&random_bytes());
+
+    let private_key_path = dir.join("test.pem");
+    let public_key_path = dir.join("test.pub.pem");
+
+    std::fs::write(
+        &private_key_path,
+        signing_key
+            .to_pkcs8_pem(LineEnding::LF)
+            .expect("encode key")
+            .as_bytes(),
+    )
+    .expect("write key");
+
+    std::fs::write(
+        &public_key_path,
+        signing_key
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .expect("encode key"),
+    )
+    .expect("write key");
+
+    JwtKeyConfig {
+        kid: "test".to_string(),
+        algorithm: Algorithm::EdDSA,
+        public_key_path: public_key_path.to_string_lossy().into_owned(),
+        private_key_path: Some(private_key_path.to_string_lossy().into_owned()),
+    }
+}
+
+/// The checked-in configuration, signing with a throwaway key and mailing to the console.
+pub fn config() -> AppConfig {
+    let mut config = AppConfig::try_from_file(None).expect("load app_config.json");
+
+    config.jwt_keys = vec![jwt_key()];
+    config.jwt_active_kid = "test".to_string();
+    config.mail_transport = MailTransportConfig::Console;
+
+    config
+}
+
+/// State over the test database, migrated, and the test Redis.
+pub async fn app_state(config: AppConfig) -> AppState {
+    let database_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL");
+    let redis_url = std::env::var("TEST_REDIS_URL").expect("TEST_REDIS_URL");
+
+    let repo = Repo::connect(&database_url).await.expect("connect repo");
+
+    sqlx::migrate!()
+        .run(repo.pool())
+        .await
+        .expect("migrate test database");
+
+    let cache = Cache::open(&redis_url).await.expect("open cache");
+
+    let jwt_codec = JwtCodec::new(&config).expect("JWT codec");
+    let mailer = Mailer::new(&config).expect("mailer");
+    let secret_box = SecretBox::from_base64(&STANDARD.encode(random_bytes())).expect("secret box");
+    let oidc_client = OidcClient::new(&config).expect("OIDC client");
+    let password_hasher = PasswordHasher::new(&config).expect("password hasher");
+    let password_policy = PasswordPolicy::new(&config).expect("password policy");
+
+    AppState::new(
+        config,
+        repo,
+        cache,
+        jwt_codec,
+        mailer,
+        secret_box,
+        oidc_client,
+        password_hasher,
+        password_policy,
+    )
+}
+
+/// The full router, as if every request came from localhost.
+pub async fn router(state: &AppState) -> Router {
+    init_router(state)
+        .await
+        .expect("init router")
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))))
+}
+
+/// An address nobody registered yet.
+pub fn unique_email() -> String {
+    format!("{}@example.com", uuid::Uuid::now_v7())
+}
+
+/// Send a JSON request, returning the status and the parsed reply.
+pub async fn send(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .expect("build request");
+
+    let response = router.clone().oneshot(request).await.expect("send request");
+
+    let status = response.status();
+
+    let bytes = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("read body");
+
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+
+    (status, body)
+}
+
+/// Register an account, returning its access token.
+pub async fn register(router: &Router, email: &str) -> String {
+    let (status, body) = send(
+        router,
+        Method::POST,
+        "/auth/register",
+        None,
+        Some(serde_json::json!({
+            "email": email,
+            "nickname": "tester",
+            "password": PASSWORD,
+        })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+    body["data"]["token"]
+        .as_str()
+        .expect("token in register reply")
+        .to_string()
+}
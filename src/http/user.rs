@@ -1,8 +1,22 @@
-use axum::extract::{Path, State};
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    response::Response,
+};
 
 use crate::{
-    http::result::HttpResult,
-    model::user::{GetUserArgs, GetUserReply},
+    http::{
+        cookie_session::with_session,
+        guard::{OwnerOr, Require},
+        result::HttpResult,
+    },
+    jwt_codec::UserClaims,
+    model::user::{
+        ChangeEmailArgs, ChangePasswordArgs, ChangePasswordReply, DeleteAccountArgs,
+        DeleteAccountReply, GetUserArgs, GetUserReply, SetUserRolesArgs, SetUserRolesReply,
+        UpdateUserArgs,
+    },
+    permission::{RoleAssign, UserReadAny, UserUnlock, UserUpdateAny},
     service,
     state::AppState,
 };
@@ -11,18 +25,357 @@ use crate::{
     get,
     path = "/{user_id}",
     responses(
-        (status = 200, description = "Get user profile successful", body = HttpResult<GetUserReply>)
+        (status = 200, description = "Get user profile successful", body = HttpResult<GetUserReply>),
+        (status = 403, description = "Neither the user themself nor allowed to read any user")
     ),
     params(
         ("user_id" = String, Path, description = "The ID of the user to retrieve")
     ),
+    security(
+        ("bearer_auth" = []),
+        ("bearer_auth" = ["user:read:any"])
+    ),
     tag = "User"
 )]
 pub async fn get_user(
     State(state): State<AppState>,
-    Path(user_id): Path<String>,
+    guard: OwnerOr<UserReadAny>,
+) -> HttpResult<GetUserReply> {
+    service::user::get_user(
+        GetUserArgs {
+            user_id: guard.user_id,
+        },
+        state.repo(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    patch,
+    path = "/me",
+    request_body(content = UpdateUserArgs, content_type = "application/merge-patch+json"),
+    responses(
+        (status = 200, description = "Profile updated", body = HttpResult<GetUserReply>),
+        (status = 400, description = "Invalid fields, listed in `errors`"),
+        (status = 409, description = "Profile changed since the given version, read it again and retry")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "User"
+)]
+pub async fn update_me(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<UpdateUserArgs>,
 ) -> HttpResult<GetUserReply> {
-    service::user::get_user(GetUserArgs { user_id }, state.repo())
+    service::user::update_user(&claims.sub, payload, state.repo())
         .await
         .into()
 }
+
+#[utoipa::path(
+    delete,
+    path = "/me",
+    request_body = DeleteAccountArgs,
+    responses(
+        (status = 200, description = "Account scheduled for deletion, every session, API key and OAuth grant revoked, logging in before `purge_after` cancels it", body = HttpResult<DeleteAccountReply>),
+        (status = 401, description = "Password is incorrect, accounts created through an identity provider set one through a password reset first"),
+        (status = 409, description = "Last owner of an organization with other members, or the password changed meanwhile")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "User"
+)]
+pub async fn delete_me(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<DeleteAccountArgs>,
+) -> HttpResult<DeleteAccountReply> {
+    service::account::delete_account(
+        &claims,
+        payload,
+        state.config(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+        state.mailer(),
+    )
+    .await
+    .into()
+}
+
+#[utoipa::path(
+    patch,
+    path = "/{user_id}",
+    request_body(content = UpdateUserArgs, content_type = "application/merge-patch+json"),
+    responses(
+        (status = 200, description = "Profile updated", body = HttpResult<GetUserReply>),
+        (status = 400, description = "Invalid fields, listed in `errors`"),
+        (status = 403, description = "Neither the user themself nor allowed to edit any user"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Profile changed since the given version, read it again and retry")
+    ),
+    params(
+        ("user_id" = String, Path, description = "The ID of the user to update")
+    ),
+    security(
+        ("bearer_auth" = []),
+        ("bearer_auth" = ["user:update:any"])
+    ),
+    tag = "User"
+)]
+pub async fn update_user(
+    State(state): State<AppState>,
+    guard: OwnerOr<UserUpdateAny>,
+    Json(payload): Json<UpdateUserArgs>,
+) -> HttpResult<GetUserReply> {
+    service::user::update_user(&guard.user_id, payload, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    put,
+    path = "/{user_id}/roles",
+    request_body = SetUserRolesArgs,
+    responses(
+        (status = 200, description = "User roles replaced", body = HttpResult<SetUserRolesReply>),
+        (status = 400, description = "Unknown role"),
+        (status = 403, description = "Not allowed to assign roles"),
+        (status = 404, description = "User not found")
+    ),
+    params(
+        ("user_id" = String, Path, description = "The ID of the user whose roles are replaced")
+    ),
+    security(
+        ("bearer_auth" = ["role:assign"])
+    ),
+    tag = "User"
+)]
+pub async fn set_user_roles(
+    State(state): State<AppState>,
+    guard: Require<RoleAssign>,
+    Path(user_id): Path<String>,
+    Json(payload): Json<SetUserRolesArgs>,
+) -> HttpResult<SetUserRolesReply> {
+    tracing::info!(
+        "User {} replaces roles of user {} with {:?}",
+        guard.claims.sub,
+        user_id,
+        payload.roles
+    );
+
+    service::user::set_user_roles(user_id, payload, state.repo())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/{user_id}/unlock",
+    responses(
+        (status = 200, description = "Login lockout and failure count cleared"),
+        (status = 403, description = "Not allowed to unlock users"),
+        (status = 404, description = "User not found")
+    ),
+    params(
+        ("user_id" = String, Path, description = "The ID of the user to unlock")
+    ),
+    security(
+        ("bearer_auth" = ["user:unlock"])
+    ),
+    tag = "User"
+)]
+pub async fn unlock_user(
+    State(state): State<AppState>,
+    guard: Require<UserUnlock>,
+    Path(user_id): Path<String>,
+) -> HttpResult<()> {
+    tracing::info!("User {} unlocks user {}", guard.claims.sub, user_id);
+
+    service::lockout::unlock_account(&guard.claims, &user_id, state.repo(), state.cache())
+        .await
+        .into()
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/password",
+    request_body = ChangePasswordArgs,
+    responses(
+        (status = 200, description = "Password changed", body = HttpResult<ChangePasswordReply>),
+        (status = 400, description = "New password violates the password policy, broken rules are listed in `errors`"),
+        (status = 401, description = "Current password is incorrect"),
+        (status = 409, description = "Password changed by another request meanwhile")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "User"
+)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<ChangePasswordArgs>,
+) -> Response {
+    let result = service::password::change_password(
+        &claims,
+        payload,
+        state.config(),
+        state.password_policy(),
+        state.jwt_codec(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+    )
+    .await;
+
+    with_session(&state, result).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/email",
+    request_body = ChangeEmailArgs,
+    responses(
+        (status = 200, description = "A confirmation link is mailed to the new email, a notice with a revert link to the current one"),
+        (status = 400, description = "Invalid email, listed in `errors`, or the account's current one"),
+        (status = 401, description = "Password is incorrect"),
+        (status = 409, description = "Email already registered"),
+        (status = 429, description = "Too many requests")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "User"
+)]
+pub async fn change_email(
+    State(state): State<AppState>,
+    Extension(claims): Extension<UserClaims>,
+    Json(payload): Json<ChangeEmailArgs>,
+) -> HttpResult<()> {
+    service::email_change::request_email_change(
+        &claims,
+        payload,
+        state.config(),
+        state.password_hasher(),
+        state.repo(),
+        state.cache(),
+        state.mailer(),
+    )
+    .await
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use crate::{
+        config::EmailVerificationPolicy,
+        http::test_support::{self, PASSWORD, register, send, unique_email},
+    };
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn change_password_signing_out_others_keeps_the_caller_signed_in() {
+        let state = test_support::app_state(test_support::config()).await;
+        let router = test_support::router(&state).await;
+
+        let old_token = register(&router, &unique_email()).await;
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/api/users/me/password",
+            Some(&old_token),
+            Some(serde_json::json!({
+                "current_password": PASSWORD,
+                "new_password": "another-Correct-horse-7-staple",
+                "sign_out_other_sessions": true,
+            })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let new_token = body["data"]["token"].as_str().expect("replacement token");
+
+        // Issued within the same second as the revocation, which must not cover it.
+        let (status, _) = send(
+            &router,
+            Method::GET,
+            "/api/users/me/sessions",
+            Some(new_token),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send(
+            &router,
+            Method::GET,
+            "/api/users/me/sessions",
+            Some(&old_token),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+    async fn delete_me_revokes_api_keys() {
+        let mut config = test_support::config();
+        config.email_verification_policy = EmailVerificationPolicy::Optional;
+
+        let state = test_support::app_state(config).await;
+        let router = test_support::router(&state).await;
+
+        let token = register(&router, &unique_email()).await;
+
+        let (status, body) = send(
+            &router,
+            Method::POST,
+            "/api/users/me/api-keys",
+            Some(&token),
+            Some(serde_json::json!({ "name": "ci" })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        let key = body["data"]["key"].as_str().expect("API key").to_string();
+
+        let (status, _) = send(&router, Method::GET, "/api/orgs", Some(&key), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(
+            &router,
+            Method::DELETE,
+            "/api/users/me",
+            Some(&token),
+            Some(serde_json::json!({ "password": PASSWORD })),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let (status, _) = send(&router, Method::GET, "/api/orgs", Some(&key), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Gone for good, not merely refused while the deletion is pending.
+        let user_id = state.jwt_codec().decode(&token).expect("token").sub;
+
+        let keys: i64 = sqlx::query_scalar("SELECT count(*) FROM t_api_key WHERE f_user_id = $1")
+            .bind(&user_id)
+            .fetch_one(state.repo().pool())
+            .await
+            .expect("count API keys");
+
+        assert_eq!(keys, 0);
+    }
+}
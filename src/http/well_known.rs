@@ -0,0 +1,25 @@
+use axum::{
+    Json,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use crate::state::AppState;
+
+#[utoipa::path(
+    get,
+    path = "/jwks.json",
+    responses(
+        (status = 200, description = "Public keys for verifying issued tokens, in JWK Set format", content_type = "application/json")
+    ),
+    tag = "Well-Known"
+)]
+pub async fn jwks(State(state): State<AppState>) -> Response {
+    // Served as a bare JWK Set instead of an `HttpResult`, as standard JWT libraries expect.
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(state.jwt_codec().jwks().clone()),
+    )
+        .into_response()
+}
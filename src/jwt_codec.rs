@@ -1,9 +1,57 @@
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use std::collections::HashMap;
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use ed25519_dalek::pkcs8::DecodePublicKey as _;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+    errors::ErrorKind,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use rsa::traits::PublicKeyParts as _;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::config::{AppConfig, JwtKeyConfig};
 
 pub type CodecResult<T> = Result<T, jsonwebtoken::errors::Error>;
 
+/// Why a token was refused by `JwtCodec::decode`.
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("token has expired")]
+    Expired,
+    #[error("token is not valid yet")]
+    NotYetValid,
+    #[error("token was issued by another issuer")]
+    WrongIssuer,
+    #[error("token is meant for another audience")]
+    WrongAudience,
+    #[error("token signature is invalid")]
+    InvalidSignature,
+    #[error("token was signed with an unknown key")]
+    UnknownKey,
+    #[error("malformed token: {0}")]
+    Malformed(jsonwebtoken::errors::Error),
+}
+
+impl From<jsonwebtoken::errors::Error> for DecodeError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        match err.kind() {
+            ErrorKind::ExpiredSignature => Self::Expired,
+            ErrorKind::ImmatureSignature => Self::NotYetValid,
+            ErrorKind::InvalidIssuer => Self::WrongIssuer,
+            ErrorKind::InvalidAudience => Self::WrongAudience,
+            ErrorKind::InvalidSignature => Self::InvalidSignature,
+            _ => Self::Malformed(err),
+        }
+    }
+}
+
 /// JWT payload for user tokens.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserClaims {
@@ -11,64 +59,371 @@ pub struct UserClaims {
     pub sub: String,
     /// Issued-at (seconds since epoch).
     pub iat: i64,
+    /// Issued-at in milliseconds, telling tokens issued right after a logout-all from those
+    /// it revoked within the same second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
     /// Expiration (seconds since epoch).
     pub exp: i64,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub extra: Option<serde_json::Value>,
+    /// Not-before (seconds since epoch).
+    pub nbf: i64,
+    /// Issuer, the deployment that signed the token.
+    pub iss: String,
+    /// Audience, the deployment the token is meant for.
+    pub aud: String,
+    /// Token id, used to revoke a single token before it expires.
+    pub jti: String,
+    /// Roles of the subject at the time the token was issued.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Permissions granted by `roles`.
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// Whether the subject had verified their email when the token was issued.
+    #[serde(default)]
+    pub email_verified: bool,
+    /// Active organization, the tenant that tenant-scoped routes act on by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    /// Space-delimited scopes of an OAuth client token or an API key, absent on first-party tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// OAuth client the token was issued to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Session, i.e. refresh token family, the token belongs to; ending it rejects the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
 }
 
 impl UserClaims {
-    /// Create new claims for `sub` with a TTL in seconds from now.
-    pub fn with_exp(sub: impl Into<String>, ttl_seconds: usize) -> Self {
-        let now = OffsetDateTime::now_utc().unix_timestamp();
+    /// Create new claims for `sub`, valid from now on for a TTL in seconds.
+    pub fn with_exp(sub: impl Into<String>, config: &AppConfig, ttl_seconds: usize) -> Self {
+        let now_utc = OffsetDateTime::now_utc();
+        let now = now_utc.unix_timestamp();
 
         Self {
             sub: sub.into(),
             iat: now,
+            iat_ms: Some(unix_millis(now_utc)),
             exp: now + ttl_seconds as i64,
-            extra: None,
+            nbf: now,
+            iss: config.jwt_issuer.clone(),
+            aud: config.jwt_audience.clone(),
+            jti: Uuid::now_v7().to_string(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+            email_verified: false,
+            org_id: None,
+            scope: None,
+            client_id: None,
+            sid: None,
         }
     }
+
+    pub fn with_email_verified(mut self, email_verified: bool) -> Self {
+        self.email_verified = email_verified;
+
+        self
+    }
+
+    pub fn with_org(mut self, org_id: Option<String>) -> Self {
+        self.org_id = org_id;
+
+        self
+    }
+
+    pub fn with_session(mut self, sid: impl Into<String>) -> Self {
+        self.sid = Some(sid.into());
+
+        self
+    }
+
+    /// Attach the roles of the subject and the permissions they grant.
+    pub fn with_grants(mut self, roles: Vec<String>, permissions: Vec<String>) -> Self {
+        self.roles = roles;
+        self.permissions = permissions;
+
+        self
+    }
+
+    /// Restrict the token to `scopes`, as for API keys.
+    pub fn with_scopes(mut self, scopes: &[String]) -> Self {
+        self.scope = Some(scopes.join(" "));
+
+        self
+    }
+
+    /// Restrict the token to `scopes` granted to an OAuth client.
+    pub fn with_client(mut self, client_id: impl Into<String>, scopes: &[String]) -> Self {
+        self.client_id = Some(client_id.into());
+
+        self.with_scopes(scopes)
+    }
+
+    /// First-party tokens are not restricted by scopes.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scope {
+            Some(granted) => granted.split_whitespace().any(|s| s == scope),
+            None => true,
+        }
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+
+    /// Whether the token was issued strictly before `cutoff_ms`. Tokens without `iat_ms`
+    /// count as issued at the start of their second.
+    pub fn issued_before(&self, cutoff_ms: i64) -> bool {
+        self.iat_ms.unwrap_or(self.iat * 1000) < cutoff_ms
+    }
 }
 
-/// JWT encoder/decoder using HMAC HS256.
+/// Milliseconds since epoch.
+pub fn unix_millis(at: OffsetDateTime) -> i64 {
+    (at.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// The key tokens are currently signed with.
+struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+}
+
+/// A key tokens are accepted from, either the active one or a retired one.
+struct VerifyingKey {
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+}
+
+/// JWT encoder/decoder using asymmetric keys (RSA or Ed25519) identified by `kid`.
 pub struct JwtCodec {
-    pub encoding_key: EncodingKey,
-    pub decoding_key: DecodingKey,
+    signing_key: SigningKey,
+    verifying_keys: HashMap<String, VerifyingKey>,
+    jwks: JwkSet,
+    issuer: String,
+    audience: String,
+    leeway_seconds: u64,
 }
 
 impl JwtCodec {
-    /// Create a new codec from a shared secret (HMAC HS256).
-    pub fn new() -> anyhow::Result<Self> {
-        let secret = std::env::var("JWT_SECRET_KEY")?;
+    /// Create a new codec from PEM key files.
+    ///
+    /// Tokens are signed with the key `jwt_active_kid`, which must come with its private key.
+    /// Every configured key, active or retired, is accepted for verification and published
+    /// in the JWKS, so a rotated key keeps working until the tokens it signed expire.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let active_kid = config.jwt_active_kid.as_str();
+        let keys: &[JwtKeyConfig] = &config.jwt_keys;
+
+        let mut signing_key = None;
+        let mut verifying_keys = HashMap::new();
+        let mut jwks = JwkSet { keys: Vec::new() };
+
+        for key in keys {
+            let public_pem = std::fs::read_to_string(&key.public_key_path).map_err(|e| {
+                anyhow::anyhow!(
+                    "Error when reading public key {}: {}",
+                    key.public_key_path,
+                    e
+                )
+            })?;
+
+            let (decoding_key, params) = load_public_key(key.algorithm, &public_pem)
+                .map_err(|e| anyhow::anyhow!("Error when loading key '{}': {}", key.kid, e))?;
+
+            if key.kid == active_kid {
+                let private_key_path = key.private_key_path.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("Active key '{}' has no private key configured", key.kid)
+                })?;
 
-        let secret = secret.as_ref();
-        let encoding = EncodingKey::from_secret(secret);
-        let decoding = DecodingKey::from_secret(secret);
+                let private_pem = std::fs::read(private_key_path).map_err(|e| {
+                    anyhow::anyhow!("Error when reading private key {}: {}", private_key_path, e)
+                })?;
+
+                let encoding_key = load_private_key(key.algorithm, &private_pem)
+                    .map_err(|e| anyhow::anyhow!("Error when loading key '{}': {}", key.kid, e))?;
+
+                signing_key = Some(SigningKey {
+                    kid: key.kid.clone(),
+                    algorithm: key.algorithm,
+                    encoding_key,
+                });
+            }
+
+            jwks.keys.push(Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(key_algorithm(key.algorithm)?),
+                    key_id: Some(key.kid.clone()),
+                    ..Default::default()
+                },
+                algorithm: params,
+            });
+
+            let previous = verifying_keys.insert(
+                key.kid.clone(),
+                VerifyingKey {
+                    algorithm: key.algorithm,
+                    decoding_key,
+                },
+            );
+
+            if previous.is_some() {
+                anyhow::bail!("Duplicate key id '{}'", key.kid);
+            }
+        }
+
+        let signing_key = signing_key
+            .ok_or_else(|| anyhow::anyhow!("Active key '{}' is not configured", active_kid))?;
 
         Ok(Self {
-            encoding_key: encoding,
-            decoding_key: decoding,
+            signing_key,
+            verifying_keys,
+            jwks,
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            leeway_seconds: config.jwt_leeway_seconds,
         })
     }
 
-    /// Encode claims into a compact JWT string. Uses HS256 by default.
+    /// Public keys of every key tokens are accepted from, in JWK Set format.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    /// Encode claims into a compact JWT string, signed with the active key.
     pub fn encode(&self, claims: &UserClaims) -> CodecResult<String> {
-        let header = Header::new(Algorithm::HS256);
+        let mut header = Header::new(self.signing_key.algorithm);
 
-        encode(&header, claims, &self.encoding_key)
+        header.kid = Some(self.signing_key.kid.clone());
+
+        encode(&header, claims, &self.signing_key.encoding_key)
     }
 
     /// Decode and validate a JWT string, returning the contained `UserClaims`.
-    /// Validation uses HS256 and checks expiration by default.
-    pub fn decode(&self, token: &str) -> CodecResult<UserClaims> {
-        let mut validation = Validation::new(Algorithm::HS256);
+    /// The key is picked by the `kid` header and must match the algorithm it is configured with.
+    /// Expiration, not-before, issuer and audience are checked, allowing for the configured
+    /// clock skew.
+    pub fn decode(&self, token: &str) -> Result<UserClaims, DecodeError> {
+        let header = decode_header(token)?;
+
+        let key = header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.verifying_keys.get(kid))
+            .ok_or(DecodeError::UnknownKey)?;
+
+        let mut validation = Validation::new(key.algorithm);
 
         validation.validate_exp = true;
+        validation.validate_nbf = true;
+        validation.leeway = self.leeway_seconds;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
 
         let token_data: jsonwebtoken::TokenData<UserClaims> =
-            decode(token, &self.decoding_key, &validation)?;
+            decode(token, &key.decoding_key, &validation)?;
 
         Ok(token_data.claims)
     }
 }
+
+fn key_algorithm(algorithm: Algorithm) -> anyhow::Result<KeyAlgorithm> {
+    match algorithm {
+        Algorithm::RS256 => Ok(KeyAlgorithm::RS256),
+        Algorithm::RS384 => Ok(KeyAlgorithm::RS384),
+        Algorithm::RS512 => Ok(KeyAlgorithm::RS512),
+        Algorithm::PS256 => Ok(KeyAlgorithm::PS256),
+        Algorithm::PS384 => Ok(KeyAlgorithm::PS384),
+        Algorithm::PS512 => Ok(KeyAlgorithm::PS512),
+        Algorithm::EdDSA => Ok(KeyAlgorithm::EdDSA),
+        other => anyhow::bail!("Unsupported signing algorithm {:?}", other),
+    }
+}
+
+fn load_private_key(algorithm: Algorithm, pem: &[u8]) -> anyhow::Result<EncodingKey> {
+    let key = match algorithm {
+        Algorithm::EdDSA => EncodingKey::from_ed_pem(pem)?,
+        _ => EncodingKey::from_rsa_pem(pem)?,
+    };
+
+    Ok(key)
+}
+
+/// Load a public key for verification along with its JWK parameters.
+fn load_public_key(
+    algorithm: Algorithm,
+    pem: &str,
+) -> anyhow::Result<(DecodingKey, AlgorithmParameters)> {
+    let decoding_key = match algorithm {
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(pem.as_bytes())?,
+        _ => DecodingKey::from_rsa_pem(pem.as_bytes())?,
+    };
+
+    let params = match algorithm {
+        Algorithm::EdDSA => {
+            let public_key = ed25519_dalek::VerifyingKey::from_public_key_pem(pem)?;
+
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(public_key.as_bytes()),
+            })
+        }
+        _ => {
+            let public_key = rsa::RsaPublicKey::from_public_key_pem(pem)?;
+
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+                e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+            })
+        }
+    };
+
+    Ok((decoding_key, params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> UserClaims {
+        let config = AppConfig::try_from_file(None).expect("app_config.json");
+
+        UserClaims::with_exp("user", &config, 60)
+    }
+
+    #[test]
+    fn token_issued_after_cutoff_in_same_second_is_not_revoked() {
+        let cutoff_ms = unix_millis(OffsetDateTime::now_utc());
+
+        let claims = claims();
+
+        assert_eq!(claims.iat_ms.map(|ms| ms / 1000), Some(claims.iat));
+        assert!(!claims.issued_before(cutoff_ms));
+    }
+
+    #[test]
+    fn token_issued_before_cutoff_is_revoked() {
+        let claims = claims();
+
+        let cutoff_ms = claims.iat_ms.unwrap() + 1;
+
+        assert!(claims.issued_before(cutoff_ms));
+        assert!(!claims.issued_before(claims.iat_ms.unwrap()));
+    }
+
+    #[test]
+    fn token_without_millis_counts_from_start_of_its_second() {
+        let mut claims = claims();
+        claims.iat_ms = None;
+
+        assert!(claims.issued_before(claims.iat * 1000 + 1));
+        assert!(!claims.issued_before(claims.iat * 1000));
+    }
+}
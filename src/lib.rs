@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use tracing_subscriber::EnvFilter;
 
 mod apidoc;
@@ -5,13 +7,26 @@ mod cache;
 mod config;
 mod http;
 mod jwt_codec;
+mod mailer;
 mod model;
+mod oidc_client;
+mod opaque_token;
+mod password_hasher;
+mod password_policy;
+mod permission;
 mod repo;
 mod result_trace;
+mod scope;
+mod secret_box;
 mod service;
 mod state;
+mod webauthn;
 
-use crate::{cache::Cache, config::AppConfig, jwt_codec::JwtCodec, repo::Repo, state::AppState};
+use crate::{
+    cache::Cache, config::AppConfig, jwt_codec::JwtCodec, mailer::Mailer, oidc_client::OidcClient,
+    password_hasher::PasswordHasher, password_policy::PasswordPolicy, repo::Repo,
+    result_trace::ResultTrace as _, secret_box::SecretBox, state::AppState,
+};
 
 async fn init_env() -> anyhow::Result<()> {
     dotenvy::dotenv().map_err(|e| anyhow::anyhow!("Error when loading env: {}", e))?;
@@ -38,15 +53,60 @@ async fn init_config() -> anyhow::Result<AppConfig> {
     Ok(config)
 }
 
-fn init_jwt_codec() -> anyhow::Result<JwtCodec> {
-    let jwt_codec =
-        JwtCodec::new().map_err(|e| anyhow::anyhow!("Error when initializing JWT codec: {}", e))?;
+fn init_jwt_codec(config: &AppConfig) -> anyhow::Result<JwtCodec> {
+    let jwt_codec = JwtCodec::new(config)
+        .map_err(|e| anyhow::anyhow!("Error when initializing JWT codec: {}", e))?;
 
     tracing::debug!("JWT codec initialized");
 
     Ok(jwt_codec)
 }
 
+fn init_mailer(config: &AppConfig) -> anyhow::Result<Mailer> {
+    let mailer = Mailer::new(config)
+        .map_err(|e| anyhow::anyhow!("Error when initializing mailer: {}", e))?;
+
+    tracing::debug!("Mailer initialized");
+
+    Ok(mailer)
+}
+
+fn init_oidc_client(config: &AppConfig) -> anyhow::Result<OidcClient> {
+    let oidc_client = OidcClient::new(config)
+        .map_err(|e| anyhow::anyhow!("Error when initializing OIDC client: {}", e))?;
+
+    tracing::debug!("OIDC client initialized");
+
+    Ok(oidc_client)
+}
+
+fn init_password_hasher(config: &AppConfig) -> anyhow::Result<PasswordHasher> {
+    let password_hasher = PasswordHasher::new(config)
+        .map_err(|e| anyhow::anyhow!("Error when initializing password hasher: {}", e))?;
+
+    tracing::debug!("Password hasher initialized");
+
+    Ok(password_hasher)
+}
+
+fn init_password_policy(config: &AppConfig) -> anyhow::Result<PasswordPolicy> {
+    let password_policy = PasswordPolicy::new(config)
+        .map_err(|e| anyhow::anyhow!("Error when initializing password policy: {}", e))?;
+
+    tracing::debug!("Password policy loaded");
+
+    Ok(password_policy)
+}
+
+fn init_secret_box() -> anyhow::Result<SecretBox> {
+    let secret_box = SecretBox::new()
+        .map_err(|e| anyhow::anyhow!("Error when initializing secret box: {}", e))?;
+
+    tracing::debug!("Secret box initialized");
+
+    Ok(secret_box)
+}
+
 async fn init_repo() -> anyhow::Result<Repo> {
     let database = Repo::new()
         .await
@@ -63,8 +123,9 @@ async fn init_repo() -> anyhow::Result<Repo> {
 }
 
 async fn init_cache() -> anyhow::Result<Cache> {
-    let cache =
-        Cache::new().map_err(|e| anyhow::anyhow!("Error when initializing cache: {}", e))?;
+    let cache = Cache::new()
+        .await
+        .map_err(|e| anyhow::anyhow!("Error when initializing cache: {}", e))?;
 
     // Test the Redis with an initial PING command to ensure connectivity
     cache
@@ -77,6 +138,28 @@ async fn init_cache() -> anyhow::Result<Cache> {
     Ok(cache)
 }
 
+/// Erase accounts past their deletion grace period, in the background for as long as
+/// the server runs.
+fn spawn_account_purge(app_state: &AppState) {
+    let app_state = app_state.clone();
+
+    let period = Duration::from_secs(app_state.config().account_purge_interval_seconds);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            let _ = service::account::purge_deleted_accounts(app_state.config(), app_state.repo())
+                .await
+                .trace_warn();
+        }
+    });
+
+    tracing::debug!("Account purge scheduled every {:?}", period);
+}
+
 pub async fn run() -> anyhow::Result<()> {
     init_env().await?;
 
@@ -84,13 +167,35 @@ pub async fn run() -> anyhow::Result<()> {
 
     let config = init_config().await?;
 
-    let jwt_codec = init_jwt_codec()?;
+    let jwt_codec = init_jwt_codec(&config)?;
+
+    let mailer = init_mailer(&config)?;
+
+    let secret_box = init_secret_box()?;
+
+    let oidc_client = init_oidc_client(&config)?;
+
+    let password_hasher = init_password_hasher(&config)?;
+
+    let password_policy = init_password_policy(&config)?;
 
     let cache = init_cache().await?;
 
     let repo = init_repo().await?;
 
-    let app_state = AppState::new(config, repo, cache, jwt_codec);
+    let app_state = AppState::new(
+        config,
+        repo,
+        cache,
+        jwt_codec,
+        mailer,
+        secret_box,
+        oidc_client,
+        password_hasher,
+        password_policy,
+    );
+
+    spawn_account_purge(&app_state);
 
     http::run_server(&app_state).await?;
 
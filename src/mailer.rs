@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::config::{AppConfig, MailTransportConfig};
+
+pub type MailResult<T> = Result<T, MailError>;
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Line break in the {0} header")]
+    HeaderInjection(&'static str),
+}
+
+/// A plain-text mail.
+#[derive(Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+enum Transport {
+    /// Log mails instead of sending them.
+    Console,
+    /// Write every mail as an `.eml` file into a directory.
+    File(PathBuf),
+}
+
+/// Delivers outgoing mail through the transport selected in `AppConfig`.
+///
+/// The console and file transports are meant for local development and tests,
+/// where mails (and the tokens in them) can be read back from the log or the outbox.
+#[derive(Debug)]
+pub struct Mailer {
+    from: String,
+    transport: Transport,
+}
+
+impl Mailer {
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let transport = match &config.mail_transport {
+            MailTransportConfig::Console => Transport::Console,
+            MailTransportConfig::File { dir } => {
+                std::fs::create_dir_all(dir).map_err(|e| {
+                    anyhow::anyhow!("Error when creating mail directory {}: {}", dir, e)
+                })?;
+
+                Transport::File(PathBuf::from(dir))
+            }
+        };
+
+        Ok(Self {
+            from: config.mail_from.clone(),
+            transport,
+        })
+    }
+
+    pub async fn send(&self, mail: Mail) -> MailResult<()> {
+        // Addresses and subjects may come from users, a line break would start a header
+        // of their own.
+        for (name, value) in [("To", &mail.to), ("Subject", &mail.subject)] {
+            if value.contains(['\r', '\n']) {
+                return Err(MailError::HeaderInjection(name));
+            }
+        }
+
+        match &self.transport {
+            Transport::Console => {
+                tracing::info!(
+                    "Mail from {} to {} - {}\n{}",
+                    self.from,
+                    mail.to,
+                    mail.subject,
+                    mail.body
+                );
+            }
+            Transport::File(dir) => {
+                let content = format!(
+                    "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\r\n{}\r\n",
+                    self.from,
+                    mail.to,
+                    mail.subject,
+                    OffsetDateTime::now_utc(),
+                    mail.body
+                );
+
+                // UUIDv7 file names keep the outbox sorted by sending time.
+                let path = dir.join(format!("{}.eml", Uuid::now_v7()));
+
+                tokio::fs::write(&path, content).await?;
+
+                tracing::debug!("Mail to {} written to {}", mail.to, path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_mailer() -> (Mailer, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("outbox-test-{}", Uuid::now_v7()));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mailer = Mailer {
+            from: "noreply@example.com".to_string(),
+            transport: Transport::File(dir.clone()),
+        };
+
+        (mailer, dir)
+    }
+
+    fn mail(to: &str, subject: &str) -> Mail {
+        Mail {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body: "Body".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_headers_and_body() {
+        let (mailer, dir) = file_mailer();
+
+        mailer
+            .send(mail("user@example.com", "Hello"))
+            .await
+            .unwrap();
+
+        let entry = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let content = std::fs::read_to_string(entry.path()).unwrap();
+
+        assert!(content.starts_with(
+            "From: noreply@example.com\r\nTo: user@example.com\r\nSubject: Hello\r\n"
+        ));
+        assert!(content.ends_with("\r\n\r\nBody\r\n"));
+    }
+
+    #[tokio::test]
+    async fn rejects_line_breaks_in_headers() {
+        let (mailer, dir) = file_mailer();
+
+        for (to, subject) in [
+            ("user@example.com\r\nBcc: victim@example.com", "Hello"),
+            ("user@example.com\nBcc: victim@example.com", "Hello"),
+            ("user@example.com", "Hello\rBcc: victim@example.com"),
+        ] {
+            let result = mailer.send(mail(to, subject)).await;
+
+            assert!(
+                matches!(result, Err(MailError::HeaderInjection(_))),
+                "{:?}",
+                to
+            );
+        }
+
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+}
@@ -1,2 +1,12 @@
+pub mod api_key;
 pub mod health;
+pub mod invitation;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod organization;
+pub mod passkey;
+pub mod patch;
+pub mod session;
 pub mod user;
+pub mod validation;
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateApiKeyArgs {
+    pub name: String,
+    /// Bind the key to an organization the caller administers.
+    pub org_id: Option<String>,
+    /// Scopes the key is restricted to, every scope when left out.
+    pub scopes: Option<Vec<String>>,
+    /// Leave out for a key that does not expire.
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyReply {
+    pub api_key_id: String,
+    pub name: String,
+    /// Leading characters of the key, to tell keys apart.
+    pub prefix: String,
+    pub org_id: Option<String>,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<OffsetDateTime>,
+    pub last_used_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateApiKeyReply {
+    #[serde(flatten)]
+    pub api_key: ApiKeyReply,
+    /// Shown only once, send it as `Authorization: Bearer` or `X-API-Key`.
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListApiKeysReply {
+    pub api_keys: Vec<ApiKeyReply>,
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::model::organization::OrgRole;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateInvitationArgs {
+    pub email: String,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct InvitationReply {
+    pub invitation_id: String,
+    pub email: String,
+    pub role: OrgRole,
+    pub invited_by: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListInvitationsReply {
+    pub invitations: Vec<InvitationReply>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AcceptInvitationArgs {
+    pub token: String,
+    /// Password of the existing account of the invited email, or of the account to create.
+    pub password: String,
+    /// Required when no account exists for the invited email yet.
+    pub nickname: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AcceptInvitationReply {
+    pub user_id: String,
+    pub org_id: String,
+    /// Tokens are withheld while a second factor is pending.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<usize>,
+    /// Short-lived token to exchange at `/auth/mfa/verify` along with a second factor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mfa_token: Option<String>,
+}
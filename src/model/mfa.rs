@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EnrollTotpReply {
+    /// Base32 secret for authenticator apps that cannot scan the URI.
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConfirmTotpArgs {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConfirmTotpReply {
+    /// Single-use codes replacing the authenticator, shown only once.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DisableTotpArgs {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VerifyMfaArgs {
+    pub mfa_token: String,
+    /// A current TOTP code or an unused recovery code.
+    pub code: String,
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateOAuthClientArgs {
+    pub name: String,
+    /// Exact URIs authorization codes may be sent back to.
+    pub redirect_uris: Vec<String>,
+    /// Scopes the client may ask users, or itself with client credentials, for.
+    pub scopes: Vec<String>,
+    /// Confidential clients get a secret and may use the client credentials grant,
+    /// public clients (mobile and single-page apps) rely on PKCE alone.
+    pub confidential: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthClientReply {
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub confidential: bool,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateOAuthClientReply {
+    #[serde(flatten)]
+    pub client: OAuthClientReply,
+    /// Shown only once, confidential clients only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListOAuthClientsReply {
+    pub clients: Vec<OAuthClientReply>,
+}
+
+/// An authorization request, as the client sent it to the consent page.
+#[derive(Debug, Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OAuthAuthorizeArgs {
+    /// Only `code` is supported.
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    /// Space-delimited scopes.
+    pub scope: String,
+    pub state: Option<String>,
+    pub code_challenge: String,
+    /// Only `S256` is supported.
+    pub code_challenge_method: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthScopeReply {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthAuthorizeReply {
+    pub client_id: String,
+    pub client_name: String,
+    pub scopes: Vec<OAuthScopeReply>,
+    /// False when the user already granted every requested scope,
+    /// the consent can then be submitted without asking.
+    pub consent_required: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OAuthConsentArgs {
+    #[serde(flatten)]
+    pub request: OAuthAuthorizeArgs,
+    pub approved: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthConsentReply {
+    /// Where to send the browser: the client's redirect URI with a code, or an error.
+    pub redirect_to: String,
+}
+
+/// Client authentication of RFC 6749 section 2.3.1, from the `Authorization` header
+/// or the request body.
+#[derive(Debug)]
+pub struct OAuthClientCredentials {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+}
+
+/// Form parameters of the token endpoint, for every supported grant.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OAuthTokenArgs {
+    /// `authorization_code`, `refresh_token` or `client_credentials`.
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Successful token response, RFC 6749 section 5.1.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthTokenReply {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
+}
+
+/// Error response of the protocol endpoints, RFC 6749 section 5.2.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OAuthErrorReply {
+    pub error: String,
+}
+
+/// Form parameters of the introspection and revocation endpoints.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OAuthTokenTypeArgs {
+    pub token: String,
+    /// `access_token` or `refresh_token`, both are tried either way.
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Introspection response, RFC 7662 section 2.2. Inactive tokens only carry `active`.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct OAuthIntrospectReply {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+}
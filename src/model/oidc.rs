@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OidcAuthorizeReply {
+    /// Where to send the browser to sign in at the provider.
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OidcCallbackArgs {
+    /// Query parameters the provider redirected back with.
+    pub code: String,
+    pub state: String,
+}
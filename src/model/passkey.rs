@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::model::validation::FieldError;
+
+/// Longest label of a passkey, in characters.
+const PASSKEY_NAME_MAX_LENGTH: usize = 64;
+
+/// Errors of a passkey label, empty when it is acceptable.
+fn validate_passkey_name(name: &str) -> Vec<FieldError> {
+    let (code, message) = if name.trim().is_empty() {
+        ("blank", "Name cannot be blank".to_string())
+    } else if name.trim().chars().count() > PASSKEY_NAME_MAX_LENGTH {
+        (
+            "too_long",
+            format!(
+                "Name must be at most {} characters",
+                PASSKEY_NAME_MAX_LENGTH
+            ),
+        )
+    } else {
+        return Vec::new();
+    };
+
+    vec![FieldError {
+        field: "name".to_string(),
+        code: code.to_string(),
+        message,
+    }]
+}
+
+// Structures named after the WebAuthn specification are serialized the way
+// `navigator.credentials` produces and consumes them, in camelCase.
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUserEntity {
+    /// Base64url user handle.
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialParameter {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Base64url credential ID.
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+/// `PublicKeyCredentialCreationOptions` for `navigator.credentials.create`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: PasskeyUserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameter>,
+    /// Milliseconds.
+    pub timeout: u64,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+/// A `PublicKeyCredential` returned by `navigator.credentials.create`, in its JSON form.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FinishPasskeyRegistrationArgs {
+    /// Label shown in the passkey list, e.g. the device it lives on.
+    pub name: String,
+    pub credential: RegistrationCredential,
+}
+
+impl FinishPasskeyRegistrationArgs {
+    pub fn validate(&self) -> Vec<FieldError> {
+        validate_passkey_name(&self.name)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PasskeyReply {
+    pub passkey_id: String,
+    pub name: String,
+    pub created_at: OffsetDateTime,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListPasskeysReply {
+    pub passkeys: Vec<PasskeyReply>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RenamePasskeyArgs {
+    pub name: String,
+}
+
+impl RenamePasskeyArgs {
+    pub fn validate(&self) -> Vec<FieldError> {
+        validate_passkey_name(&self.name)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BeginPasskeyLoginArgs {
+    /// Restrict the ceremony to the passkeys of this account,
+    /// leave out for discoverable credentials.
+    pub email: Option<String>,
+}
+
+/// `PublicKeyCredentialRequestOptions` for `navigator.credentials.get`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    /// Milliseconds.
+    pub timeout: u64,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+/// A `PublicKeyCredential` returned by `navigator.credentials.get`, in its JSON form.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AssertionCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FinishPasskeyLoginArgs {
+    pub credential: AssertionCredential,
+}
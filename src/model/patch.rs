@@ -0,0 +1,27 @@
+use serde::{Deserialize, Deserializer};
+
+/// A field of a JSON merge patch (RFC 7396): left out, set to `null`, or set to a value.
+///
+/// Fields must be marked `#[serde(default)]` so that a missing key reads as `Missing`.
+#[derive(Debug, Default)]
+pub enum Patch<T> {
+    #[default]
+    Missing,
+    Null,
+    Value(T),
+}
+
+impl<'de, T> Deserialize<'de> for Patch<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            None => Patch::Null,
+            Some(value) => Patch::Value(value),
+        })
+    }
+}
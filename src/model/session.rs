@@ -0,0 +1,22 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionReply {
+    pub session_id: String,
+    /// User agent of the latest request, absent until the session is first used.
+    pub user_agent: Option<String>,
+    /// Client address of the latest request.
+    pub ip: Option<String>,
+    pub created_at: OffsetDateTime,
+    /// Accurate to about a minute.
+    pub last_seen_at: Option<OffsetDateTime>,
+    /// Whether this is the session of the token making the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListSessionsReply {
+    pub sessions: Vec<SessionReply>,
+}
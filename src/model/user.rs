@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use utoipa::ToSchema;
 
+use crate::model::patch::Patch;
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct GetUserArgs {
     pub user_id: String,
@@ -12,18 +14,182 @@ pub struct GetUserReply {
     pub user_id: String,
     pub email: String,
     pub nickname: String,
+    pub avatar_url: Option<String>,
+    /// BCP 47 language tag, e.g. `en-US`.
+    pub locale: Option<String>,
+    /// IANA time zone name, e.g. `Europe/Paris`.
+    pub timezone: Option<String>,
     pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    /// Current version of the profile, to send back with the next update.
+    pub version: i64,
+}
+
+/// JSON merge patch of a profile: left out fields are kept, `null` clears a field.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateUserArgs {
+    /// Version the edit is based on, the update is refused if the profile changed since.
+    pub version: i64,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub nickname: Patch<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub avatar_url: Patch<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub locale: Patch<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub timezone: Patch<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
-pub struct LoginUserArgs {
+pub struct SetUserRolesArgs {
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SetUserRolesReply {
+    pub user_id: String,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RegisterUserArgs {
     pub email: String,
     pub nickname: String,
     pub password: String,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RegisterUserReply {
+    pub user_id: String,
+    /// Tokens are withheld when login requires a verified email.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoginUserArgs {
+    pub email: String,
+    pub password: String,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct LoginUserReply {
     pub user_id: String,
+    /// Tokens are withheld while a second factor is pending.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<usize>,
+    /// Short-lived token to exchange at `/auth/mfa/verify` along with a second factor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RefreshTokenArgs {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RefreshTokenReply {
+    pub user_id: String,
+    pub token: String,
+    pub refresh_token: String,
+    pub expires_in: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct LogoutUserArgs {
+    /// Refresh token of the session to end, so it cannot be exchanged anymore.
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VerifyEmailArgs {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ResendVerificationArgs {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ForgotPasswordArgs {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ResetPasswordArgs {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MagicLinkArgs {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConsumeMagicLinkArgs {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ChangeEmailArgs {
+    pub new_email: String,
+    /// Current password, an open session alone cannot move the account to another address.
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConfirmEmailChangeArgs {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RevertEmailChangeArgs {
     pub token: String,
 }
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DeleteAccountArgs {
+    /// Accounts created through an identity provider get one through a password reset.
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeleteAccountReply {
+    /// Logging in before this time cancels the deletion, afterwards the account is erased.
+    pub purge_after: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ChangePasswordArgs {
+    pub current_password: String,
+    pub new_password: String,
+    /// Revoke every other session and token, the caller gets a fresh token pair.
+    #[serde(default)]
+    pub sign_out_other_sessions: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ChangePasswordReply {
+    /// Replacement tokens, only present when other sessions were signed out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<usize>,
+}
@@ -0,0 +1,12 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// One invalid field of a request, returned in `errors` next to a 400 or 422.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Name of the offending field as sent by the client.
+    pub field: String,
+    /// Stable identifier of the broken rule, e.g. `too_short`.
+    pub code: String,
+    pub message: String,
+}
@@ -0,0 +1,541 @@
+use std::{collections::HashMap, sync::Arc};
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use reqwest::Url;
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+use crate::config::{AppConfig, OidcProviderConfig};
+
+pub type OidcResult<T> = Result<T, OidcError>;
+
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("Unknown provider {0}")]
+    UnknownProvider(String),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid authorization endpoint: {0}")]
+    InvalidEndpoint(String),
+    #[error("Discovery document names issuer {0}")]
+    IssuerMismatch(String),
+    #[error("Token endpoint refused the code: {0}")]
+    TokenEndpoint(String),
+    #[error("Token response has no ID token")]
+    MissingIdToken,
+    #[error("ID token is signed with an unsupported algorithm")]
+    UnsupportedAlgorithm,
+    #[error("ID token is signed with an unknown key")]
+    UnknownKey,
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(#[from] jsonwebtoken::errors::Error),
+    #[error("ID token nonce does not match")]
+    NonceMismatch,
+}
+
+/// The part of the discovery document this client relies on.
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug)]
+struct Discovered {
+    metadata: ProviderMetadata,
+    jwks: JwkSet,
+}
+
+struct Provider {
+    config: OidcProviderConfig,
+    client_secret: Option<String>,
+    /// Discovery runs on first use, so an unreachable provider does not block startup.
+    discovered: RwLock<Option<Arc<Discovered>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// Claims of a validated ID token.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+/// Relying party side of OpenID Connect for the providers listed in `AppConfig`.
+pub struct OidcClient {
+    http: reqwest::Client,
+    providers: HashMap<String, Provider>,
+    leeway_seconds: u64,
+}
+
+impl OidcClient {
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| anyhow::anyhow!("Error when building HTTP client: {}", e))?;
+
+        let mut providers = HashMap::new();
+
+        for provider in &config.oidc_providers {
+            let client_secret = match &provider.client_secret_env {
+                Some(var) => Some(std::env::var(var).map_err(|e| {
+                    anyhow::anyhow!("Error when acquiring {} for {}: {}", var, provider.name, e)
+                })?),
+                None => None,
+            };
+
+            providers.insert(
+                provider.name.clone(),
+                Provider {
+                    config: provider.clone(),
+                    client_secret,
+                    discovered: RwLock::new(None),
+                },
+            );
+        }
+
+        Ok(Self {
+            http,
+            providers,
+            leeway_seconds: config.jwt_leeway_seconds,
+        })
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&OidcProviderConfig> {
+        self.providers.get(name).map(|p| &p.config)
+    }
+
+    fn lookup(&self, name: &str) -> OidcResult<&Provider> {
+        self.providers
+            .get(name)
+            .ok_or_else(|| OidcError::UnknownProvider(name.to_string()))
+    }
+
+    async fn discover(&self, provider: &Provider) -> OidcResult<Arc<Discovered>> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            provider.config.issuer.trim_end_matches('/')
+        );
+
+        let metadata: ProviderMetadata = self
+            .http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // OpenID Connect Discovery 1.0, section 4.3.
+        if metadata.issuer != provider.config.issuer {
+            return Err(OidcError::IssuerMismatch(metadata.issuer));
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let discovered = Arc::new(Discovered { metadata, jwks });
+
+        *provider.discovered.write().await = Some(discovered.clone());
+
+        Ok(discovered)
+    }
+
+    async fn discovered(&self, provider: &Provider) -> OidcResult<Arc<Discovered>> {
+        if let Some(discovered) = provider.discovered.read().await.as_ref() {
+            return Ok(discovered.clone());
+        }
+
+        self.discover(provider).await
+    }
+
+    /// URL of the provider's authorization endpoint for an authorization code flow with PKCE.
+    pub async fn authorization_url(
+        &self,
+        name: &str,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> OidcResult<String> {
+        let provider = self.lookup(name)?;
+
+        let discovered = self.discovered(provider).await?;
+
+        let scope = provider.config.scopes.join(" ");
+
+        let url = Url::parse_with_params(
+            &discovered.metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.config.client_id.as_str()),
+                ("redirect_uri", provider.config.redirect_uri.as_str()),
+                ("scope", scope.as_str()),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| OidcError::InvalidEndpoint(e.to_string()))?;
+
+        Ok(url.into())
+    }
+
+    /// Redeem an authorization code and validate the ID token it yields.
+    pub async fn exchange_code(
+        &self,
+        name: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> OidcResult<IdTokenClaims> {
+        let provider = self.lookup(name)?;
+
+        let discovered = self.discovered(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", provider.config.redirect_uri.as_str()),
+            ("client_id", provider.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self
+            .http
+            .post(&discovered.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(OidcError::TokenEndpoint(response.text().await?));
+        }
+
+        let token: TokenResponse = response.json().await?;
+
+        let id_token = token.id_token.ok_or(OidcError::MissingIdToken)?;
+
+        let claims = self
+            .validate_id_token(provider, discovered, &id_token)
+            .await?;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::NonceMismatch);
+        }
+
+        Ok(claims)
+    }
+
+    async fn validate_id_token(
+        &self,
+        provider: &Provider,
+        mut discovered: Arc<Discovered>,
+        id_token: &str,
+    ) -> OidcResult<IdTokenClaims> {
+        let header = decode_header(id_token)?;
+
+        // Symmetric algorithms would make the client secret a signing key.
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(OidcError::UnsupportedAlgorithm);
+        }
+
+        // Providers rotate keys, a kid missing from the cached set triggers one refresh.
+        if let Some(kid) = header.kid.as_deref()
+            && discovered.jwks.find(kid).is_none()
+        {
+            discovered = self.discover(provider).await?;
+        }
+
+        let jwk = match header.kid.as_deref() {
+            Some(kid) => discovered.jwks.find(kid),
+            None if discovered.jwks.keys.len() == 1 => discovered.jwks.keys.first(),
+            None => None,
+        }
+        .ok_or(OidcError::UnknownKey)?;
+
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovered.metadata.issuer]);
+        validation.set_audience(&[&provider.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+        validation.leeway = self.leeway_seconds;
+
+        let data = decode::<IdTokenClaims>(id_token, &key, &validation)?;
+
+        Ok(data.claims)
+    }
+}
+
+/// An OpenID provider served on a local port, handing out whatever ID token a test set.
+#[cfg(test)]
+pub mod mock {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Json, Router, extract::State, routing::get, routing::post};
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+    use ed25519_dalek::{
+        SigningKey,
+        pkcs8::{EncodePrivateKey as _, spki::der::pem::LineEnding},
+    };
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+    use serde_json::{Value, json};
+
+    use crate::config::OidcProviderConfig;
+
+    pub const CLIENT_ID: &str = "saas";
+
+    /// What the mock provider serves, changed by tests between requests.
+    pub struct Served {
+        pub issuer: String,
+        pub jwks: Mutex<Value>,
+        pub id_token: Mutex<String>,
+    }
+
+    impl Served {
+        /// Configuration of a provider named `mock` pointing at this one.
+        pub fn provider_config(&self, trust_email: bool) -> OidcProviderConfig {
+            OidcProviderConfig {
+                name: "mock".to_string(),
+                issuer: self.issuer.clone(),
+                client_id: CLIENT_ID.to_string(),
+                client_secret_env: None,
+                redirect_uri: "http://127.0.0.1:3000/callback".to_string(),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+                trust_email,
+            }
+        }
+    }
+
+    pub fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    pub fn jwk(key: &SigningKey, kid: &str) -> Value {
+        json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "alg": "EdDSA",
+            "use": "sig",
+            "kid": kid,
+            "x": URL_SAFE_NO_PAD.encode(key.verifying_key().to_bytes()),
+        })
+    }
+
+    pub fn sign(key: &SigningKey, kid: &str, claims: &Value) -> String {
+        let pem = key.to_pkcs8_pem(LineEnding::LF).unwrap();
+
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(kid.to_string());
+
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_ed_pem(pem.as_bytes()).unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Serve discovery, JWKS and token endpoints on a local port.
+    pub async fn serve(jwks: Value) -> Arc<Served> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let served = Arc::new(Served {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            jwks: Mutex::new(jwks),
+            id_token: Mutex::new(String::new()),
+        });
+
+        let router = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(|State(served): State<Arc<Served>>| async move {
+                    Json(json!({
+                        "issuer": served.issuer,
+                        "authorization_endpoint": format!("{}/authorize", served.issuer),
+                        "token_endpoint": format!("{}/token", served.issuer),
+                        "jwks_uri": format!("{}/jwks", served.issuer),
+                    }))
+                }),
+            )
+            .route(
+                "/jwks",
+                get(|State(served): State<Arc<Served>>| async move {
+                    Json(served.jwks.lock().unwrap().clone())
+                }),
+            )
+            .route(
+                "/token",
+                post(|State(served): State<Arc<Served>>| async move {
+                    Json(json!({
+                        "access_token": "opaque",
+                        "token_type": "Bearer",
+                        "id_token": served.id_token.lock().unwrap().clone(),
+                    }))
+                }),
+            )
+            .with_state(served.clone());
+
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        served
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::{Value, json};
+    use time::OffsetDateTime;
+
+    use super::{
+        mock::{CLIENT_ID, Served, jwk, sign, signing_key},
+        *,
+    };
+
+    const NONCE: &str = "nonce-1";
+
+    fn claims(issuer: &str, aud: &str, nonce: &str) -> Value {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        json!({
+            "iss": issuer,
+            "aud": aud,
+            "sub": "provider-user",
+            "email": "user@example.com",
+            "email_verified": true,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce,
+        })
+    }
+
+    /// Serve a provider, returning the client pointed at it.
+    async fn mock_provider(jwks: Value) -> (OidcClient, Arc<Served>) {
+        let served = mock::serve(jwks).await;
+
+        let mut config = AppConfig::try_from_file(None).expect("app_config.json");
+
+        config.oidc_providers = vec![served.provider_config(false)];
+
+        (OidcClient::new(&config).unwrap(), served)
+    }
+
+    async fn exchange(
+        client: &OidcClient,
+        served: &Served,
+        id_token: String,
+    ) -> OidcResult<IdTokenClaims> {
+        *served.id_token.lock().unwrap() = id_token;
+
+        client
+            .exchange_code("mock", "code", "verifier", NONCE)
+            .await
+    }
+
+    #[tokio::test]
+    async fn accepts_a_valid_id_token() {
+        let key = signing_key(1);
+        let (client, served) = mock_provider(json!({ "keys": [jwk(&key, "k1")] })).await;
+
+        let id_token = sign(&key, "k1", &claims(&served.issuer, CLIENT_ID, NONCE));
+
+        let claims = exchange(&client, &served, id_token).await.unwrap();
+
+        assert_eq!(claims.sub, "provider-user");
+        assert_eq!(claims.email.as_deref(), Some("user@example.com"));
+    }
+
+    #[tokio::test]
+    async fn rejects_another_nonce() {
+        let key = signing_key(1);
+        let (client, served) = mock_provider(json!({ "keys": [jwk(&key, "k1")] })).await;
+
+        let id_token = sign(&key, "k1", &claims(&served.issuer, CLIENT_ID, "replayed"));
+
+        let result = exchange(&client, &served, id_token).await;
+
+        assert!(matches!(result, Err(OidcError::NonceMismatch)));
+    }
+
+    #[tokio::test]
+    async fn rejects_another_audience() {
+        let key = signing_key(1);
+        let (client, served) = mock_provider(json!({ "keys": [jwk(&key, "k1")] })).await;
+
+        let id_token = sign(&key, "k1", &claims(&served.issuer, "other-client", NONCE));
+
+        let result = exchange(&client, &served, id_token).await;
+
+        assert!(matches!(result, Err(OidcError::InvalidIdToken(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_symmetric_algorithm() {
+        let key = signing_key(1);
+        let (client, served) = mock_provider(json!({ "keys": [jwk(&key, "k1")] })).await;
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("k1".to_string());
+
+        // Signed with what a public client would hold, nothing secret.
+        let id_token = encode(
+            &header,
+            &claims(&served.issuer, CLIENT_ID, NONCE),
+            &EncodingKey::from_secret(CLIENT_ID.as_bytes()),
+        )
+        .unwrap();
+
+        let result = exchange(&client, &served, id_token).await;
+
+        assert!(matches!(result, Err(OidcError::UnsupportedAlgorithm)));
+    }
+
+    #[tokio::test]
+    async fn refreshes_keys_once_for_an_unknown_kid() {
+        let old_key = signing_key(1);
+        let (client, served) = mock_provider(json!({ "keys": [jwk(&old_key, "k1")] })).await;
+
+        let id_token = sign(&old_key, "k1", &claims(&served.issuer, CLIENT_ID, NONCE));
+        exchange(&client, &served, id_token).await.unwrap();
+
+        // The provider rotates, the cached set only knows the old key.
+        let new_key = signing_key(2);
+        *served.jwks.lock().unwrap() = json!({ "keys": [jwk(&new_key, "k2")] });
+
+        let id_token = sign(&new_key, "k2", &claims(&served.issuer, CLIENT_ID, NONCE));
+        exchange(&client, &served, id_token).await.unwrap();
+
+        // A kid the provider does not publish even after the refresh.
+        let id_token = sign(&new_key, "k3", &claims(&served.issuer, CLIENT_ID, NONCE));
+        let result = exchange(&client, &served, id_token).await;
+
+        assert!(matches!(result, Err(OidcError::UnknownKey)));
+    }
+}
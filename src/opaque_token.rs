@@ -0,0 +1,20 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use sha2::{Digest as _, Sha256};
+
+/// Number of random bytes in a generated token (256 bits of entropy).
+const TOKEN_BYTES: usize = 32;
+
+/// Generate a random, URL-safe opaque token.
+pub fn generate() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+
+    OsRng.fill_bytes(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Digest a token for storage, so that a leaked store never yields usable tokens.
+pub fn digest(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+
+use argon2::{
+    Algorithm, Argon2, KeyId, Params, PasswordHash, PasswordHasher as _, PasswordVerifier as _,
+    Version,
+    password_hash::{
+        Error as HashError, SaltString,
+        rand_core::{OsRng, RngCore as _},
+    },
+};
+use hmac::{Hmac, Mac as _};
+use sha2::{Digest as _, Sha256};
+use thiserror::Error;
+
+use crate::config::AppConfig;
+
+pub type PasswordHashResult<T> = Result<T, PasswordHashError>;
+
+#[derive(Debug, Error)]
+pub enum PasswordHashError {
+    #[error("Argon2 error: {0}")]
+    Hash(#[from] HashError),
+    #[error("Argon2 parameter error: {0}")]
+    Params(#[from] argon2::Error),
+    #[error("Hashing task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+    #[error("Hash is peppered with an unknown secret")]
+    UnknownPepper,
+}
+
+/// Argon2id hashing with the cost set in `AppConfig` and an optional pepper.
+///
+/// Peppered hashes carry a `keyid` derived from the pepper, so that hashes made
+/// before the pepper was introduced still verify and are spotted as outdated.
+/// Hashes tagged with the older SHA-256 key id still verify and are rehashed.
+/// Argon2 is slow on purpose, every call runs on the blocking thread pool.
+#[derive(Clone)]
+pub struct PasswordHasher {
+    params: Params,
+    pepper: Option<Arc<[u8]>>,
+    legacy_keyid: Option<KeyId>,
+    dummy_hash: Arc<str>,
+}
+
+impl PasswordHasher {
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let pepper = match &config.password_pepper_env {
+            Some(var) => {
+                let pepper = std::env::var(var)
+                    .map_err(|e| anyhow::anyhow!("Error when acquiring {}: {}", var, e))?;
+
+                if pepper.len() < 16 {
+                    anyhow::bail!("{} must be at least 16 bytes", var);
+                }
+
+                Some(Arc::from(pepper.into_bytes()))
+            }
+            None => None,
+        };
+
+        let mut builder = argon2::ParamsBuilder::new();
+
+        builder
+            .m_cost(config.argon2_memory_kib)
+            .t_cost(config.argon2_iterations)
+            .p_cost(config.argon2_parallelism);
+
+        let mut legacy_keyid = None;
+
+        if let Some(pepper) = &pepper {
+            builder.keyid(pepper_id(pepper)?);
+            legacy_keyid = Some(legacy_pepper_id(pepper)?);
+        }
+
+        let params = builder
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+
+        let mut hasher = Self {
+            params,
+            pepper,
+            legacy_keyid,
+            dummy_hash: Arc::from(""),
+        };
+
+        let mut password = [0u8; 32];
+        OsRng.fill_bytes(&mut password);
+
+        let dummy_hash = hasher
+            .argon2()
+            .and_then(|argon2| {
+                Ok(argon2
+                    .hash_password(&password, &SaltString::generate(&mut OsRng))?
+                    .to_string())
+            })
+            .map_err(|e| anyhow::anyhow!("Error when hashing the dummy password: {}", e))?;
+
+        hasher.dummy_hash = Arc::from(dummy_hash);
+
+        Ok(hasher)
+    }
+
+    /// Hash of a password nobody knows, made with the configured cost. Verifying against it
+    /// for unknown accounts makes them take as long to reject as wrong passwords.
+    pub fn dummy_hash(&self) -> &str {
+        &self.dummy_hash
+    }
+
+    fn argon2(&self) -> PasswordHashResult<Argon2<'_>> {
+        let argon2 = match &self.pepper {
+            Some(pepper) => Argon2::new_with_secret(
+                pepper,
+                Algorithm::Argon2id,
+                Version::V0x13,
+                self.params.clone(),
+            )?,
+            None => Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone()),
+        };
+
+        Ok(argon2)
+    }
+
+    pub async fn hash(&self, password: &str) -> PasswordHashResult<String> {
+        let hasher = self.clone();
+        let password = password.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+
+            let hash = hasher
+                .argon2()?
+                .hash_password(password.as_bytes(), &salt)?
+                .to_string();
+
+            Ok(hash)
+        })
+        .await?
+    }
+
+    pub async fn verify(&self, password: &str, hash: &str) -> PasswordHashResult<bool> {
+        let hasher = self.clone();
+        let password = password.to_string();
+        let hash = hash.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let parsed = PasswordHash::new(&hash)?;
+
+            let keyid = Params::try_from(&parsed)?.keyid().to_vec();
+
+            // The secret is only mixed in for hashes made with it, the cost
+            // parameters always come from the hash itself.
+            let result = if keyid.is_empty() {
+                Argon2::default().verify_password(password.as_bytes(), &parsed)
+            } else if hasher.pepper.is_some() && hasher.knows_keyid(&keyid) {
+                hasher
+                    .argon2()?
+                    .verify_password(password.as_bytes(), &parsed)
+            } else {
+                return Err(PasswordHashError::UnknownPepper);
+            };
+
+            match result {
+                Ok(()) => Ok(true),
+                Err(HashError::Password) => Ok(false),
+                Err(err) => Err(err.into()),
+            }
+        })
+        .await?
+    }
+
+    fn knows_keyid(&self, keyid: &[u8]) -> bool {
+        keyid == self.params.keyid()
+            || self
+                .legacy_keyid
+                .as_ref()
+                .is_some_and(|legacy| keyid == legacy.as_bytes())
+    }
+
+    /// Whether `hash` was made with another algorithm, cost or pepper than configured,
+    /// and should be replaced by a fresh hash the next time the password is known.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return true;
+        };
+
+        let Ok(params) = Params::try_from(&parsed) else {
+            return true;
+        };
+
+        parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+            || params.keyid() != self.params.keyid()
+    }
+}
+
+const PEPPER_ID_LABEL: &[u8] = b"password-hasher/pepper-id";
+
+/// Short tag naming a pepper in the hashes it went into. It is a MAC of a fixed label keyed
+/// by the pepper, so it cannot be checked against guesses without knowing the pepper already.
+fn pepper_id(pepper: &[u8]) -> anyhow::Result<KeyId> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(pepper)
+        .map_err(|e| anyhow::anyhow!("Invalid pepper: {}", e))?;
+    mac.update(PEPPER_ID_LABEL);
+
+    KeyId::new(&mac.finalize().into_bytes()[..4])
+        .map_err(|e| anyhow::anyhow!("Invalid pepper key id: {}", e))
+}
+
+/// Tag the first peppered hashes were made with, a plain digest of the pepper.
+fn legacy_pepper_id(pepper: &[u8]) -> anyhow::Result<KeyId> {
+    let digest = Sha256::digest(pepper);
+
+    KeyId::new(&digest[..4]).map_err(|e| anyhow::anyhow!("Invalid pepper key id: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEPPER: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn peppered_hasher(keyid: KeyId) -> PasswordHasher {
+        PasswordHasher {
+            params: argon2::ParamsBuilder::new()
+                .m_cost(8)
+                .t_cost(1)
+                .p_cost(1)
+                .keyid(keyid)
+                .build()
+                .unwrap(),
+            pepper: Some(Arc::from(PEPPER)),
+            legacy_keyid: Some(legacy_pepper_id(PEPPER).unwrap()),
+            dummy_hash: Arc::from(""),
+        }
+    }
+
+    #[test]
+    fn pepper_id_is_not_a_digest_of_the_pepper() {
+        let id = pepper_id(PEPPER).unwrap();
+
+        assert_eq!(id.as_bytes().len(), 4);
+        assert_ne!(id.as_bytes(), &Sha256::digest(PEPPER)[..4]);
+        assert_ne!(id, pepper_id(b"fedcba9876543210fedcba9876543210").unwrap());
+    }
+
+    #[tokio::test]
+    async fn hashes_tagged_with_the_legacy_id_verify_and_need_rehash() {
+        let hasher = peppered_hasher(pepper_id(PEPPER).unwrap());
+        let legacy = peppered_hasher(legacy_pepper_id(PEPPER).unwrap());
+        let legacy_hash = legacy.hash("correct horse").await.unwrap();
+
+        assert!(hasher.verify("correct horse", &legacy_hash).await.unwrap());
+        assert!(!hasher.verify("wrong horse", &legacy_hash).await.unwrap());
+        assert!(hasher.needs_rehash(&legacy_hash));
+
+        let hash = hasher.hash("correct horse").await.unwrap();
+
+        assert!(hasher.verify("correct horse", &hash).await.unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+    }
+}
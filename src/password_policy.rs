@@ -0,0 +1,430 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use sha1::{Digest as _, Sha1};
+
+use crate::config::AppConfig;
+
+/// Keyboard rows, neighbours on a row are as cheap to guess as a sequence.
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Shortest substring matched against the banned list and the user's own details.
+const MIN_WORD_LEN: usize = 4;
+
+/// Years from 1900 to 2099 are a favourite suffix, worth a couple hundred guesses.
+const YEAR_GUESSES: f64 = 2.3;
+
+/// Lowest number of guesses, as a power of ten, for scores 1 to 4 (zxcvbn's thresholds).
+const SCORE_THRESHOLDS: [f64; 4] = [3.0, 6.0, 8.0, 10.0];
+
+/// A rule a password breaks, with a stable `code` clients can switch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyViolation {
+    TooShort(usize),
+    TooLong(usize),
+    TooWeak(u8),
+    Banned,
+    Breached,
+}
+
+impl PolicyViolation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TooShort(_) => "too_short",
+            Self::TooLong(_) => "too_long",
+            Self::TooWeak(_) => "too_weak",
+            Self::Banned => "banned",
+            Self::Breached => "breached",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::TooShort(min) => format!("Password must be at least {} characters long", min),
+            Self::TooLong(max) => format!("Password must be at most {} characters long", max),
+            Self::TooWeak(min) => format!(
+                "Password is too easy to guess, it must reach a strength of {} out of 4",
+                min
+            ),
+            Self::Banned => "Password is too common".to_string(),
+            Self::Breached => "Password has appeared in a data breach".to_string(),
+        }
+    }
+}
+
+/// Rules new passwords must satisfy, loaded once from the files named in `AppConfig`.
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    min_score: u8,
+    banned: HashSet<String>,
+    hibp_dir: Option<PathBuf>,
+}
+
+impl PasswordPolicy {
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        if config.password_min_length == 0
+            || config.password_min_length > config.password_max_length
+        {
+            anyhow::bail!("Password length bounds are inconsistent");
+        }
+
+        if config.password_min_score > 4 {
+            anyhow::bail!("Password score ranges from 0 to 4");
+        }
+
+        let banned = match &config.password_banned_list_path {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Error when reading banned list {}: {}", path, e))?
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        let hibp_dir = match &config.password_hibp_dir {
+            Some(dir) if !std::path::Path::new(dir).is_dir() => {
+                anyhow::bail!("HIBP dump {} is not a directory", dir)
+            }
+            dir => dir.as_ref().map(PathBuf::from),
+        };
+
+        Ok(Self {
+            min_length: config.password_min_length,
+            max_length: config.password_max_length,
+            min_score: config.password_min_score,
+            banned,
+            hibp_dir,
+        })
+    }
+
+    /// Every rule `password` breaks, empty when it is acceptable. `user_inputs` are
+    /// details of the account, such as its email, a password built on them is weak.
+    pub async fn check(&self, password: &str, user_inputs: &[&str]) -> Vec<PolicyViolation> {
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            return vec![PolicyViolation::TooShort(self.min_length)];
+        }
+
+        // Hashing cost grows with the length, an upper bound keeps it in check.
+        if length > self.max_length {
+            return vec![PolicyViolation::TooLong(self.max_length)];
+        }
+
+        let mut violations = Vec::new();
+
+        if self.banned.contains(&password.to_lowercase()) {
+            violations.push(PolicyViolation::Banned);
+        } else if self.score(password, user_inputs) < self.min_score {
+            violations.push(PolicyViolation::TooWeak(self.min_score));
+        }
+
+        if self.is_breached(password).await {
+            violations.push(PolicyViolation::Breached);
+        }
+
+        violations
+    }
+
+    /// Strength from 0 to 4 as zxcvbn reports it, from a rough estimate of the guesses
+    /// an attacker needs. Common words, the user's details, repeats, sequences and
+    /// keyboard walks cost next to nothing; other characters cost the alphabet in use.
+    pub fn score(&self, password: &str, user_inputs: &[&str]) -> u8 {
+        let chars: Vec<char> = password.chars().collect();
+
+        let lower: Vec<char> = chars.iter().map(char::to_ascii_lowercase).collect();
+
+        // Words are matched with the substitutions undone, patterns on the characters typed.
+        let plain: Vec<char> = lower.iter().copied().map(unleet).collect();
+
+        let user_words: Vec<Vec<char>> = user_inputs
+            .iter()
+            .flat_map(|input| input.split(|c: char| !c.is_alphanumeric()))
+            .filter(|word| word.chars().count() >= MIN_WORD_LEN)
+            .map(|word| word.to_lowercase().chars().map(unleet).collect())
+            .collect();
+
+        let word_guesses = (self.banned.len().max(10) as f64).log10();
+
+        let mut guesses = 0.0;
+        let mut i = 0;
+
+        while i < plain.len() {
+            if let Some(len) = self.word_at(&plain[i..], &user_words) {
+                guesses += word_guesses;
+                i += len;
+                continue;
+            }
+
+            if is_year(&chars[i..]) {
+                guesses += YEAR_GUESSES;
+                i += 4;
+                continue;
+            }
+
+            guesses += if i > 0 && is_predictable(&lower, i) {
+                2f64.log10()
+            } else {
+                alphabet_size(chars[i]).log10()
+            };
+
+            i += 1;
+        }
+
+        SCORE_THRESHOLDS
+            .iter()
+            .filter(|threshold| guesses >= **threshold)
+            .count() as u8
+    }
+
+    /// Length of the longest banned word or user detail `rest` starts with.
+    fn word_at(&self, rest: &[char], user_words: &[Vec<char>]) -> Option<usize> {
+        let user_match = user_words
+            .iter()
+            .filter(|word| rest.starts_with(word))
+            .map(|word| word.len())
+            .max();
+
+        let banned_match = (MIN_WORD_LEN..=rest.len()).rev().find(|&len| {
+            self.banned
+                .contains(&rest[..len].iter().collect::<String>())
+        });
+
+        user_match.max(banned_match)
+    }
+
+    /// k-anonymity lookup in a local copy of the Pwned Passwords range files: only the
+    /// first five hex digits of the SHA-1 pick the file `{PREFIX}.txt`, whose lines read
+    /// `SUFFIX:COUNT`. A missing range file counts as not breached.
+    async fn is_breached(&self, password: &str) -> bool {
+        let Some(dir) = &self.hibp_dir else {
+            return false;
+        };
+
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        let path = dir.join(format!("{}.txt", prefix));
+
+        let range = match tokio::fs::read_to_string(&path).await {
+            Ok(range) => range,
+            Err(err) => {
+                tracing::warn!("Error when reading HIBP range {}: {}", path.display(), err);
+
+                return false;
+            }
+        };
+
+        range_lists(&range, suffix)
+    }
+}
+
+/// Whether a range file lists `suffix` with a nonzero count. Padded downloads add
+/// entries counted `0` that stand for no password.
+fn range_lists(range: &str, suffix: &str) -> bool {
+    range.lines().any(|line| {
+        line.split_once(':').is_some_and(|(candidate, count)| {
+            candidate.eq_ignore_ascii_case(suffix) && count.trim() != "0"
+        })
+    })
+}
+
+/// Undo the usual letter substitutions, so that `p@ssw0rd` matches `password`.
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        c => c,
+    }
+}
+
+/// Size of the character class `c` belongs to.
+fn alphabet_size(c: char) -> f64 {
+    match c {
+        c if c.is_ascii_lowercase() || c.is_ascii_uppercase() => 26.0,
+        c if c.is_ascii_digit() => 10.0,
+        c if c.is_ascii() => 33.0,
+        _ => 100.0,
+    }
+}
+
+fn is_year(rest: &[char]) -> bool {
+    matches!(rest, ['1', '9', c, d, ..] | ['2', '0', c, d, ..] if c.is_ascii_digit() && d.is_ascii_digit())
+}
+
+/// Whether the character at `i` repeats, continues a sequence or walks the keyboard
+/// from the one before.
+fn is_predictable(chars: &[char], i: usize) -> bool {
+    let (prev, cur) = (chars[i - 1], chars[i]);
+
+    let step = cur as i64 - prev as i64;
+
+    if step.abs() <= 1 {
+        return true;
+    }
+
+    KEYBOARD_ROWS.iter().any(|row| {
+        matches!(
+            (row.find(prev), row.find(cur)),
+            (Some(a), Some(b)) if a.abs_diff(b) == 1
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A password every rule accepts.
+    const STRONG: &str = "correct-Horse-battery-9-staple";
+
+    fn policy(hibp_dir: Option<PathBuf>) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 64,
+            min_score: 3,
+            banned: ["password", "dragon", "monkey"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            hibp_dir,
+        }
+    }
+
+    /// A range directory holding the file `password`'s hash falls in, listing its
+    /// suffix with `count`.
+    fn hibp_dir(password: &str, count: u64) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hibp-test-{}", uuid::Uuid::now_v7()));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        std::fs::write(
+            dir.join(format!("{}.txt", prefix)),
+            format!(
+                "0000000000000000000000000000000000A:12\r\n{}:{}\r\n",
+                suffix, count
+            ),
+        )
+        .unwrap();
+
+        dir
+    }
+
+    #[tokio::test]
+    async fn accepts_a_strong_password() {
+        assert_eq!(policy(None).check(STRONG, &[]).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_short_password() {
+        let violations = policy(None).check("x7#Kq9!", &[]).await;
+
+        assert_eq!(violations, vec![PolicyViolation::TooShort(8)]);
+        assert_eq!(violations[0].code(), "too_short");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_long_password() {
+        let violations = policy(None).check(&"x7#Kq9!m".repeat(9), &[]).await;
+
+        assert_eq!(violations, vec![PolicyViolation::TooLong(64)]);
+        assert_eq!(violations[0].code(), "too_long");
+    }
+
+    #[tokio::test]
+    async fn bounds_count_characters_not_bytes() {
+        // Eight characters, sixteen bytes.
+        assert!(
+            !policy(None)
+                .check("ÄöÜßéèçñ", &[])
+                .await
+                .contains(&PolicyViolation::TooShort(8))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_a_banned_password_in_any_case() {
+        let violations = policy(None).check("PassWord", &[]).await;
+
+        assert_eq!(violations, vec![PolicyViolation::Banned]);
+        assert_eq!(violations[0].code(), "banned");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_weak_password() {
+        for password in ["aaaaaaaaaaaa", "qwertyuiop12", "abcdefgh", "P@ssw0rd1987"] {
+            let violations = policy(None).check(password, &[]).await;
+
+            assert_eq!(
+                violations,
+                vec![PolicyViolation::TooWeak(3)],
+                "{}",
+                password
+            );
+            assert_eq!(violations[0].code(), "too_weak");
+        }
+    }
+
+    #[test]
+    fn user_inputs_lower_the_score() {
+        let policy = policy(None);
+
+        assert_eq!(policy.score("Quokka-Jonathan-42", &[]), 4);
+        assert_eq!(
+            policy.score("Quokka-Jonathan-42", &["jonathan.quokka@example.com"]),
+            2
+        );
+        assert_eq!(
+            policy.score("jonathanquokka", &["jonathan@example.com", "Quokka"]),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_a_weak_password_built_on_user_inputs() {
+        let violations = policy(None)
+            .check("Quokka-Jonathan-42", &["jonathan.quokka@example.com"])
+            .await;
+
+        assert_eq!(violations, vec![PolicyViolation::TooWeak(3)]);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_breached_password() {
+        let policy = policy(Some(hibp_dir(STRONG, 3)));
+
+        let violations = policy.check(STRONG, &[]).await;
+
+        assert_eq!(violations, vec![PolicyViolation::Breached]);
+        assert_eq!(violations[0].code(), "breached");
+    }
+
+    #[tokio::test]
+    async fn ignores_padding_and_missing_ranges() {
+        let policy = policy(Some(hibp_dir(STRONG, 0)));
+
+        assert_eq!(policy.check(STRONG, &[]).await, vec![]);
+
+        // Falls in another range file, which the directory lacks.
+        assert_eq!(policy.check("x7#Kq9!mZ", &[]).await, vec![]);
+    }
+
+    #[test]
+    fn parses_range_lines() {
+        let range = "0018A45C4D1DEF81644B54AB7F969B88D65:1\r\n00D4F6E8FA6EECAD2A3AA415EEC418D38EC:2\n011053FD0102E94D6AE2F8B83D76FAF94F6:0\n";
+
+        assert!(range_lists(range, "0018A45C4D1DEF81644B54AB7F969B88D65"));
+        assert!(range_lists(range, "00d4f6e8fa6eecad2a3aa415eec418d38ec"));
+        assert!(!range_lists(range, "011053FD0102E94D6AE2F8B83D76FAF94F6"));
+        assert!(!range_lists(range, "0018A45C4D1DEF81644B54AB7F969B88D6"));
+        assert!(!range_lists("not a range line\n", "not a range line"));
+    }
+}
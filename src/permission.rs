@@ -0,0 +1,38 @@
+/// Role granted to every new account.
+pub const DEFAULT_ROLE: &str = "user";
+
+/// A permission granted to roles in `t_role_permission` and carried in `UserClaims`.
+///
+/// Permissions are types so that guards can name them in handler signatures,
+/// e.g. `Require<UserReadAny>`.
+pub trait Permission: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+/// Read the profile of any user, not only one's own.
+pub struct UserReadAny;
+
+impl Permission for UserReadAny {
+    const NAME: &'static str = "user:read:any";
+}
+
+/// Edit the profile of any user, not only one's own.
+pub struct UserUpdateAny;
+
+impl Permission for UserUpdateAny {
+    const NAME: &'static str = "user:update:any";
+}
+
+/// Replace the roles of any user.
+pub struct RoleAssign;
+
+impl Permission for RoleAssign {
+    const NAME: &'static str = "role:assign";
+}
+
+/// Lift the login lockout of any user.
+pub struct UserUnlock;
+
+impl Permission for UserUnlock {
+    const NAME: &'static str = "user:unlock";
+}
@@ -1,5 +1,11 @@
 use sqlx::{PgPool, postgres::PgPoolOptions};
 
+pub mod api_key;
+pub mod invitation;
+pub mod mfa;
+pub mod oauth;
+pub mod organization;
+pub mod passkey;
 pub mod user;
 
 #[derive(Debug)]
@@ -14,9 +20,13 @@ impl Repo {
         let database_url = std::env::var("DATABASE_URL")
             .map_err(|e| anyhow::anyhow!("Error when acquiring DATABASE_URL: {}", e))?;
 
+        Self::connect(&database_url).await
+    }
+
+    pub async fn connect(database_url: &str) -> anyhow::Result<Self> {
         let pool = PgPoolOptions::new()
             .max_connections(Self::MAX_CONNECTIONS)
-            .connect(&database_url)
+            .connect(database_url)
             .await
             .map_err(|e| anyhow::anyhow!("Error when connecting to database: {}", e))?;
 
@@ -33,3 +43,9 @@ impl Repo {
         &self.pool
     }
 }
+
+/// Whether a statement failed on a unique constraint, SQLSTATE 23505.
+pub fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .is_some_and(|err| err.is_unique_violation())
+}
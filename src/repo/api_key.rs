@@ -0,0 +1,22 @@
+use time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct BaseApiKey {
+    pub f_id: String,
+    pub f_org_id: Option<String>,
+    pub f_name: String,
+    pub f_prefix: String,
+    pub f_scopes: Vec<String>,
+    pub f_expires_at: Option<OffsetDateTime>,
+    pub f_last_used_at: Option<OffsetDateTime>,
+    pub f_created_at: OffsetDateTime,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKeyGrant {
+    pub f_id: String,
+    pub f_user_id: String,
+    pub f_org_id: Option<String>,
+    pub f_scopes: Vec<String>,
+    pub f_last_used_at: Option<OffsetDateTime>,
+}
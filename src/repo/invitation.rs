@@ -0,0 +1,18 @@
+use time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct BaseInvitation {
+    pub f_id: String,
+    pub f_email: String,
+    pub f_role: String,
+    pub f_invited_by: Option<String>,
+    pub f_created_at: OffsetDateTime,
+    pub f_expires_at: OffsetDateTime,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ClaimedInvitation {
+    pub f_org_id: String,
+    pub f_email: String,
+    pub f_role: String,
+}
@@ -0,0 +1,8 @@
+use time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserTotp {
+    pub f_secret: Vec<u8>,
+    pub f_last_step: i64,
+    pub f_confirmed_at: Option<OffsetDateTime>,
+}
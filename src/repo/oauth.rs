@@ -0,0 +1,11 @@
+use time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct OAuthClient {
+    pub f_id: String,
+    pub f_name: String,
+    pub f_secret_hash: Option<String>,
+    pub f_redirect_uris: Vec<String>,
+    pub f_scopes: Vec<String>,
+    pub f_created_at: OffsetDateTime,
+}
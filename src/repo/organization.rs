@@ -0,0 +1,18 @@
+use time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct MemberOrganization {
+    pub f_id: String,
+    pub f_name: String,
+    pub f_role: String,
+    pub f_created_at: OffsetDateTime,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct OrganizationMember {
+    pub f_user_id: String,
+    pub f_email: String,
+    pub f_nickname: String,
+    pub f_role: String,
+    pub f_created_at: OffsetDateTime,
+}
@@ -0,0 +1,16 @@
+use time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct BasePasskey {
+    pub f_id: String,
+    pub f_name: String,
+    pub f_created_at: OffsetDateTime,
+    pub f_last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct PasskeyCredential {
+    pub f_user_id: String,
+    pub f_public_key: Vec<u8>,
+    pub f_sign_count: i64,
+}
@@ -5,11 +5,30 @@ pub struct BaseUser {
     pub f_id: String,
     pub f_nickname: String,
     pub f_email: String,
+    pub f_avatar_url: Option<String>,
+    pub f_locale: Option<String>,
+    pub f_timezone: Option<String>,
     pub f_created_at: OffsetDateTime,
+    pub f_updated_at: OffsetDateTime,
+    pub f_version: i64,
 }
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct UserSecrets {
     pub f_id: String,
     pub f_password_hash: String,
+    pub f_email_verified_at: Option<OffsetDateTime>,
+    pub f_deleted_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct UnverifiedUser {
+    pub f_id: String,
+    pub f_email: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserGrant {
+    pub f_role: String,
+    pub f_permission: Option<String>,
 }
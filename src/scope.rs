@@ -0,0 +1,142 @@
+use axum::http::Method;
+
+/// Read user profiles.
+pub const USERS_READ: &str = "users:read";
+/// Edit user profiles and change user roles.
+pub const USERS_WRITE: &str = "users:write";
+/// Read organizations, their members and invitations.
+pub const ORGS_READ: &str = "orgs:read";
+/// Manage organizations, their members and invitations.
+pub const ORGS_WRITE: &str = "orgs:write";
+
+/// Scopes third-party clients may ask for, with the text shown on the consent screen.
+pub const SCOPES: [(&str, &str); 4] = [
+    (USERS_READ, "Read user profiles"),
+    (
+        USERS_WRITE,
+        "Edit your profile, and the profiles and roles of users you administer",
+    ),
+    (
+        ORGS_READ,
+        "See your organizations, their members and invitations",
+    ),
+    (
+        ORGS_WRITE,
+        "Manage your organizations, their members and invitations",
+    ),
+];
+
+pub fn is_known(scope: &str) -> bool {
+    SCOPES.iter().any(|(name, _)| *name == scope)
+}
+
+pub fn describe(scope: &str) -> Option<&'static str> {
+    SCOPES
+        .iter()
+        .find(|(name, _)| *name == scope)
+        .map(|(_, description)| *description)
+}
+
+/// Split a space-delimited scope parameter, dropping duplicates.
+pub fn parse(scope: &str) -> Vec<String> {
+    let mut scopes: Vec<String> = Vec::new();
+
+    for s in scope.split_whitespace() {
+        if !scopes.iter().any(|known| known == s) {
+            scopes.push(s.to_string());
+        }
+    }
+
+    scopes
+}
+
+/// Scope a delegated token needs for a route, by matched path and method.
+///
+/// Routes missing here are reserved to first-party tokens: account security,
+/// switching organizations and the OAuth consent itself.
+pub fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    let read = method == Method::GET || method == Method::HEAD;
+
+    match path {
+        "/api/users/{user_id}" if read => Some(USERS_READ),
+        "/api/users/{user_id}" | "/api/users/me" if method == Method::PATCH => Some(USERS_WRITE),
+        "/api/users/{user_id}/roles" => Some(USERS_WRITE),
+        "/api/orgs/{org_id}/switch" => None,
+        p if p == "/api/orgs" || p.starts_with("/api/orgs/") => {
+            Some(if read { ORGS_READ } else { ORGS_WRITE })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_routes_to_scopes() {
+        let cases = [
+            (Method::GET, "/api/users/{user_id}", Some(USERS_READ)),
+            (Method::HEAD, "/api/users/{user_id}", Some(USERS_READ)),
+            (Method::PATCH, "/api/users/{user_id}", Some(USERS_WRITE)),
+            (Method::PATCH, "/api/users/me", Some(USERS_WRITE)),
+            (Method::PUT, "/api/users/{user_id}/roles", Some(USERS_WRITE)),
+            (Method::GET, "/api/orgs", Some(ORGS_READ)),
+            (Method::POST, "/api/orgs", Some(ORGS_WRITE)),
+            (Method::GET, "/api/orgs/{org_id}", Some(ORGS_READ)),
+            (Method::PATCH, "/api/orgs/{org_id}", Some(ORGS_WRITE)),
+            (Method::DELETE, "/api/orgs/{org_id}", Some(ORGS_WRITE)),
+            (Method::GET, "/api/orgs/{org_id}/members", Some(ORGS_READ)),
+            (
+                Method::PUT,
+                "/api/orgs/{org_id}/members/{user_id}",
+                Some(ORGS_WRITE),
+            ),
+            (
+                Method::POST,
+                "/api/orgs/{org_id}/invitations",
+                Some(ORGS_WRITE),
+            ),
+        ];
+
+        for (method, path, expected) in cases {
+            assert_eq!(
+                required_scope(&method, path),
+                expected,
+                "{} {}",
+                method,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn reserves_other_routes_to_first_party_tokens() {
+        let cases = [
+            (Method::POST, "/api/orgs/{org_id}/switch"),
+            (Method::DELETE, "/api/users/me"),
+            (Method::DELETE, "/api/users/{user_id}"),
+            (Method::POST, "/api/users/me/password"),
+            (Method::POST, "/api/users/me/email"),
+            (Method::POST, "/api/users/me/api-keys"),
+            (Method::GET, "/api/users/me/sessions"),
+            (Method::POST, "/api/users/me/mfa/totp/disable"),
+            (Method::POST, "/api/users/{user_id}/unlock"),
+            (Method::POST, "/api/oauth/consent"),
+            (Method::GET, "/api/orgsx"),
+        ];
+
+        for (method, path) in cases {
+            assert_eq!(required_scope(&method, path), None, "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn parses_scopes_without_duplicates() {
+        assert_eq!(
+            parse(" users:read  orgs:read users:read "),
+            vec![USERS_READ.to_string(), ORGS_READ.to_string()]
+        );
+        assert!(parse("  ").is_empty());
+    }
+}
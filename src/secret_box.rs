@@ -0,0 +1,73 @@
+use aes_gcm::{
+    Aes256Gcm, KeyInit as _, Nonce,
+    aead::{Aead as _, AeadCore as _, OsRng},
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use thiserror::Error;
+
+/// Length of the AES-GCM nonce prefixed to every sealed value.
+const NONCE_BYTES: usize = 12;
+
+pub type SecretBoxResult<T> = Result<T, SecretBoxError>;
+
+#[derive(Debug, Error)]
+pub enum SecretBoxError {
+    #[error("Sealed value is too short")]
+    Malformed,
+    #[error("Encryption or decryption failed")]
+    Cipher,
+}
+
+/// Authenticated encryption of secrets kept at rest, such as TOTP seeds.
+pub struct SecretBox {
+    cipher: Aes256Gcm,
+}
+
+impl SecretBox {
+    pub fn new() -> anyhow::Result<Self> {
+        let key = std::env::var("SECRET_BOX_KEY")
+            .map_err(|e| anyhow::anyhow!("Error when acquiring SECRET_BOX_KEY: {}", e))?;
+
+        Self::from_base64(&key)
+    }
+
+    /// Create a box from a 32-byte key in standard base64.
+    pub fn from_base64(key: &str) -> anyhow::Result<Self> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| anyhow::anyhow!("Error when decoding SECRET_BOX_KEY: {}", e))?;
+
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("SECRET_BOX_KEY must be 32 bytes"))?;
+
+        Ok(Self { cipher })
+    }
+
+    /// Encrypt `plaintext` under a fresh nonce, returned as `nonce || ciphertext`.
+    pub fn seal(&self, plaintext: &[u8]) -> SecretBoxResult<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| SecretBoxError::Cipher)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+
+        Ok(sealed)
+    }
+
+    /// Decrypt a value produced by `seal`.
+    pub fn open(&self, sealed: &[u8]) -> SecretBoxResult<Vec<u8>> {
+        if sealed.len() < NONCE_BYTES {
+            return Err(SecretBoxError::Malformed);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| SecretBoxError::Cipher)
+    }
+}
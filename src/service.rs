@@ -1,4 +1,21 @@
+pub mod account;
+pub mod api_key;
+pub mod audit;
 pub mod auth;
+pub mod cookie_session;
+pub mod email_change;
 pub mod health;
+pub mod invitation;
+pub mod lockout;
+pub mod magic_link;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod organization;
+pub mod passkey;
+pub mod password;
+pub mod rate_limit;
 pub mod result;
+pub mod session;
 pub mod user;
+pub mod verification;
@@ -0,0 +1,391 @@
+use sqlx::{Executor, Postgres, query, query_as, query_scalar};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::UserClaims,
+    mailer::{Mail, Mailer},
+    model::user::{DeleteAccountArgs, DeleteAccountReply},
+    password_hasher::PasswordHasher,
+    repo::Repo,
+    result_trace::ResultTrace as _,
+    service::{
+        audit::{
+            ACCOUNT_DELETION_CANCELLED, ACCOUNT_DELETION_REQUESTED, ACCOUNT_PURGED, AuditEntry,
+            record_audit,
+        },
+        auth::{revoke_all_tokens, verify_password},
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// Accounts erased per purge transaction, keeping locks short.
+const PURGE_BATCH_SIZE: i64 = 100;
+
+fn grace_period(config: &AppConfig) -> Duration {
+    Duration::seconds(config.account_deletion_grace_seconds as i64)
+}
+
+/// Whether an account deleted at `deleted_at` is past its grace period, and thus gone
+/// for every purpose even before the purge got to it.
+pub(super) fn is_purgeable(deleted_at: Option<OffsetDateTime>, config: &AppConfig) -> bool {
+    deleted_at.is_some_and(|at| at + grace_period(config) <= OffsetDateTime::now_utc())
+}
+
+/// Keep an account its owner just logged into. Returns whether a deletion was cancelled,
+/// and rejects accounts past their grace period like unknown ones.
+pub(super) async fn cancel_account_deletion(
+    user_id: &str,
+    config: &AppConfig,
+    repo: &Repo,
+) -> InterResult<bool> {
+    let mut trx = repo.pool().begin().await?;
+
+    let deleted_at: Option<Option<OffsetDateTime>> = query_scalar(
+        r#"
+        SELECT f_deleted_at
+        FROM t_user
+        WHERE f_id = $1
+        FOR UPDATE
+    "#,
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let deleted_at = match deleted_at {
+        None => return Err(reject(401, "Account has been deleted")),
+        Some(None) => return Ok(false),
+        Some(deleted_at) if is_purgeable(deleted_at, config) => {
+            return Err(reject(401, "Account has been deleted"));
+        }
+        Some(Some(deleted_at)) => deleted_at,
+    };
+
+    query("UPDATE t_user SET f_deleted_at = NULL WHERE f_id = $1")
+        .bind(user_id)
+        .execute(&mut *trx)
+        .await
+        .trace_error()?;
+
+    record_audit(
+        &mut *trx,
+        AuditEntry {
+            action: ACCOUNT_DELETION_CANCELLED,
+            user_id: Some(user_id),
+            actor_id: Some(user_id),
+            detail: format!("requested at {}", deleted_at),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    trx.commit().await?;
+
+    tracing::info!("Deletion of user {} cancelled by a login", user_id);
+
+    Ok(true)
+}
+
+/// Names of the organizations `user_id` is the last owner of while others are still members.
+async fn select_sole_owned_orgs<'c, E>(exec: E, user_id: &str) -> InterResult<Vec<String>>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let names: Vec<String> = query_scalar(
+        r#"
+        SELECT o.f_name
+        FROM t_membership m
+        JOIN t_organization o ON o.f_id = m.f_org_id
+        WHERE m.f_user_id = $1 AND m.f_role = 'owner'
+          AND NOT EXISTS (
+              SELECT 1 FROM t_membership x
+              WHERE x.f_org_id = m.f_org_id AND x.f_user_id <> $1 AND x.f_role = 'owner'
+          )
+          AND EXISTS (
+              SELECT 1 FROM t_membership x
+              WHERE x.f_org_id = m.f_org_id AND x.f_user_id <> $1
+          )
+        ORDER BY o.f_name
+    "#,
+    )
+    .bind(user_id)
+    .fetch_all(exec)
+    .await
+    .trace_error()?;
+
+    Ok(names)
+}
+
+/// Schedule the caller's account for deletion. Every session, API key and OAuth grant ends
+/// now, logging in again within the grace period cancels the deletion, afterwards the purge
+/// erases the account. Accounts created through an identity provider have no password
+/// anybody knows, their owners set one through a reset first.
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_account(
+    claims: &UserClaims,
+    args: DeleteAccountArgs,
+    config: &AppConfig,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> ServiceResult<DeleteAccountReply> {
+    let password_hash: Option<String> = query_scalar(
+        r#"
+        SELECT f_password_hash
+        FROM t_user
+        WHERE f_id = $1 AND f_deleted_at IS NULL
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(password_hash) = password_hash else {
+        return Err(reject(404, "User not found"));
+    };
+
+    // Verified before taking the lock, hashing is slow.
+    if !(verify_password(&args.password, &password_hash, password_hasher).await?) {
+        return Err(reject(401, "Password is incorrect"));
+    }
+
+    let mut trx = repo.pool().begin().await?;
+
+    let account: Option<(String, String)> = query_as(
+        r#"
+        SELECT f_password_hash, f_email
+        FROM t_user
+        WHERE f_id = $1 AND f_deleted_at IS NULL
+        FOR UPDATE
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let Some((locked_hash, email)) = account else {
+        return Err(reject(404, "User not found"));
+    };
+
+    if locked_hash != password_hash {
+        return Err(reject(409, "Password changed meanwhile, try again"));
+    }
+
+    // Members would be left with an organization nobody can manage.
+    let sole_owned = select_sole_owned_orgs(&mut *trx, &claims.sub).await?;
+
+    if !sole_owned.is_empty() {
+        return Err(reject(
+            409,
+            format!(
+                "Hand over ownership of these organizations first: {}",
+                sole_owned.join(", ")
+            ),
+        ));
+    }
+
+    let deleted_at: OffsetDateTime = query_scalar(
+        r#"
+        UPDATE t_user
+        SET f_deleted_at = now()
+        WHERE f_id = $1
+        RETURNING f_deleted_at
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_one(&mut *trx)
+    .await
+    .trace_error()?;
+
+    // Deleted rather than suspended, cancelling the deletion does not bring them back.
+    query("DELETE FROM t_api_key WHERE f_user_id = $1")
+        .bind(&claims.sub)
+        .execute(&mut *trx)
+        .await
+        .trace_error()?;
+
+    record_audit(
+        &mut *trx,
+        AuditEntry {
+            action: ACCOUNT_DELETION_REQUESTED,
+            user_id: Some(&claims.sub),
+            actor_id: Some(&claims.sub),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    trx.commit().await?;
+
+    // Also clears the refresh tokens OAuth clients hold for the user.
+    revoke_all_tokens(&claims.sub, config, cache).await?;
+
+    let purge_after = deleted_at + grace_period(config);
+
+    // The deletion stands either way, the mail only reminds how to undo it.
+    mailer
+        .send(Mail {
+            to: email,
+            subject: "Your account will be deleted".to_string(),
+            body: format!(
+                "Your account and its data will be erased after {}.\n\nTo keep your account, log in before then: {}/login",
+                purge_after, config.app_base_url
+            ),
+        })
+        .await
+        .trace_warn()
+        .ok();
+
+    Ok(accept()
+        .with_message("Account scheduled for deletion")
+        .with_data(DeleteAccountReply { purge_after }))
+}
+
+/// Erase one account past its grace period and what refers to it.
+///
+/// Rows owned by the user go with it through `ON DELETE CASCADE`, history kept by others
+/// (audit log, invitations sent) loses the reference and the personal details.
+async fn purge_account(
+    trx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: &str,
+    email: &str,
+) -> InterResult<()> {
+    // Organizations only the user belonged to have nobody left to use them.
+    query(
+        r#"
+        DELETE FROM t_organization o
+        WHERE EXISTS (
+            SELECT 1 FROM t_membership m WHERE m.f_org_id = o.f_id AND m.f_user_id = $1
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM t_membership m WHERE m.f_org_id = o.f_id AND m.f_user_id <> $1
+        )
+    "#,
+    )
+    .bind(user_id)
+    .execute(&mut **trx)
+    .await
+    .trace_error()?;
+
+    // Deletion is refused to last owners, but members may have joined during the grace
+    // period. The longest-standing remaining admin, or else member, takes over.
+    query(
+        r#"
+        UPDATE t_membership m
+        SET f_role = 'owner'
+        FROM (
+            SELECT DISTINCT ON (x.f_org_id) x.f_org_id, x.f_user_id
+            FROM t_membership x
+            JOIN t_membership mine ON mine.f_org_id = x.f_org_id
+                AND mine.f_user_id = $1 AND mine.f_role = 'owner'
+            WHERE x.f_user_id <> $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM t_membership o
+                  WHERE o.f_org_id = x.f_org_id AND o.f_user_id <> $1 AND o.f_role = 'owner'
+              )
+            ORDER BY x.f_org_id, x.f_role = 'admin' DESC, x.f_created_at
+        ) heir
+        WHERE m.f_org_id = heir.f_org_id AND m.f_user_id = heir.f_user_id
+    "#,
+    )
+    .bind(user_id)
+    .execute(&mut **trx)
+    .await
+    .trace_error()?;
+
+    query(
+        r#"
+        UPDATE t_audit_log
+        SET f_ip = NULL,
+            f_detail = CASE WHEN f_user_id = $1 THEN '' ELSE f_detail END
+        WHERE f_user_id = $1 OR f_actor_id = $1
+    "#,
+    )
+    .bind(user_id)
+    .execute(&mut **trx)
+    .await
+    .trace_error()?;
+
+    query("DELETE FROM t_invitation WHERE lower(f_email) = lower($1)")
+        .bind(email)
+        .execute(&mut **trx)
+        .await
+        .trace_error()?;
+
+    query("DELETE FROM t_user WHERE f_id = $1")
+        .bind(user_id)
+        .execute(&mut **trx)
+        .await
+        .trace_error()?;
+
+    // The id is random and says nothing about the person, it only ties the entries together.
+    record_audit(
+        &mut **trx,
+        AuditEntry {
+            action: ACCOUNT_PURGED,
+            detail: user_id.to_string(),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Erase every account past its grace period, returning how many were erased.
+/// Safe to run from several instances at once, each account is taken by one of them.
+pub async fn purge_deleted_accounts(config: &AppConfig, repo: &Repo) -> InterResult<u64> {
+    let cutoff = OffsetDateTime::now_utc() - grace_period(config);
+
+    let mut purged = 0;
+
+    loop {
+        let mut trx = repo.pool().begin().await?;
+
+        let batch: Vec<(String, String)> = query_as(
+            r#"
+            SELECT f_id, f_email
+            FROM t_user
+            WHERE f_deleted_at <= $1
+            ORDER BY f_deleted_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        "#,
+        )
+        .bind(cutoff)
+        .bind(PURGE_BATCH_SIZE)
+        .fetch_all(&mut *trx)
+        .await
+        .trace_error()?;
+
+        if batch.is_empty() {
+            break;
+        }
+
+        let count = batch.len() as u64;
+
+        for (user_id, email) in &batch {
+            purge_account(&mut trx, user_id, email).await?;
+        }
+
+        trx.commit().await?;
+
+        purged += count;
+
+        if count < PURGE_BATCH_SIZE as u64 {
+            break;
+        }
+    }
+
+    if purged > 0 {
+        tracing::info!("Purged {} deleted accounts", purged);
+    }
+
+    Ok(purged)
+}
@@ -0,0 +1,214 @@
+use sqlx::{query, query_as};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    config::AppConfig,
+    jwt_codec::UserClaims,
+    model::{
+        api_key::{ApiKeyReply, CreateApiKeyArgs, CreateApiKeyReply, ListApiKeysReply},
+        organization::OrgRole,
+    },
+    opaque_token,
+    repo::{
+        Repo,
+        api_key::{ApiKeyGrant, BaseApiKey},
+    },
+    result_trace::ResultTrace as _,
+    scope,
+    service::{
+        auth::user_claims,
+        organization::select_member_role,
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// Leads every API key, so that keys are easy to spot in code and logs
+/// and are told apart from JWTs in the `Authorization` header.
+pub const API_KEY_PREFIX: &str = "sak_";
+
+/// Characters of a key kept in clear to recognize it in a list.
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// Last use is recorded at most this often, sparing a write on every request.
+const LAST_USED_RESOLUTION: Duration = Duration::minutes(1);
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+fn api_key_reply(key: BaseApiKey) -> ApiKeyReply {
+    ApiKeyReply {
+        api_key_id: key.f_id,
+        name: key.f_name,
+        prefix: key.f_prefix,
+        org_id: key.f_org_id,
+        scopes: key.f_scopes,
+        expires_at: key.f_expires_at,
+        last_used_at: key.f_last_used_at,
+        created_at: key.f_created_at,
+    }
+}
+
+pub async fn create_api_key(
+    claims: &UserClaims,
+    args: CreateApiKeyArgs,
+    repo: &Repo,
+) -> ServiceResult<CreateApiKeyReply> {
+    if args.name.trim().is_empty() {
+        return Err(reject(400, "API key name is required"));
+    }
+
+    let mut scopes = match args.scopes {
+        Some(scopes) => scopes,
+        None => scope::SCOPES.iter().map(|(s, _)| s.to_string()).collect(),
+    };
+
+    scopes.sort();
+    scopes.dedup();
+
+    if scopes.is_empty() || !scopes.iter().all(|s| scope::is_known(s)) {
+        return Err(reject(400, "Unknown scope"));
+    }
+
+    if let Some(org_id) = &args.org_id {
+        let role = select_member_role(repo.pool(), org_id, &claims.sub).await?;
+
+        if role.is_none_or(|role| role < OrgRole::Admin) {
+            return Err(reject(
+                403,
+                "Only organization admins can create org-scoped keys",
+            ));
+        }
+    }
+
+    let expires_at = args
+        .expires_in_days
+        .map(|days| OffsetDateTime::now_utc() + Duration::days(days.into()));
+
+    let key = format!("{}{}", API_KEY_PREFIX, opaque_token::generate());
+
+    let api_key: BaseApiKey = query_as(
+        r#"
+        INSERT INTO t_api_key (f_id, f_user_id, f_org_id, f_name, f_prefix, f_key_hash, f_scopes, f_expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING f_id, f_org_id, f_name, f_prefix, f_scopes, f_expires_at, f_last_used_at, f_created_at
+    "#,
+    )
+    .bind(Uuid::now_v7().to_string())
+    .bind(&claims.sub)
+    .bind(&args.org_id)
+    .bind(args.name.trim())
+    .bind(&key[..DISPLAY_PREFIX_LEN])
+    .bind(opaque_token::digest(&key))
+    .bind(&scopes)
+    .bind(expires_at)
+    .fetch_one(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(accept().with_code(201).with_data(CreateApiKeyReply {
+        api_key: api_key_reply(api_key),
+        key,
+    }))
+}
+
+pub async fn list_api_keys(claims: &UserClaims, repo: &Repo) -> ServiceResult<ListApiKeysReply> {
+    let api_keys: Vec<BaseApiKey> = query_as(
+        r#"
+        SELECT f_id, f_org_id, f_name, f_prefix, f_scopes, f_expires_at, f_last_used_at, f_created_at
+        FROM t_api_key
+        WHERE f_user_id = $1
+        ORDER BY f_created_at
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(accept().with_data(ListApiKeysReply {
+        api_keys: api_keys.into_iter().map(api_key_reply).collect(),
+    }))
+}
+
+pub async fn revoke_api_key(
+    claims: &UserClaims,
+    api_key_id: &str,
+    repo: &Repo,
+) -> ServiceResult<()> {
+    let deleted = query(
+        r#"
+        DELETE FROM t_api_key
+        WHERE f_id = $1 AND f_user_id = $2
+    "#,
+    )
+    .bind(api_key_id)
+    .bind(&claims.sub)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(reject(404, "API key not found"));
+    }
+
+    Ok(accept().with_message("API key revoked"))
+}
+
+/// Resolve an API key into the claims its owner would get, restricted to the key's
+/// scopes and organization. `None` for unknown, expired or orphaned keys, and for keys
+/// of accounts scheduled for deletion.
+pub async fn authenticate_api_key(
+    key: &str,
+    config: &AppConfig,
+    repo: &Repo,
+) -> InterResult<Option<UserClaims>> {
+    let grant: Option<ApiKeyGrant> = query_as(
+        r#"
+        SELECT k.f_id, k.f_user_id, k.f_org_id, k.f_scopes, k.f_last_used_at
+        FROM t_api_key k
+        JOIN t_user u ON u.f_id = k.f_user_id
+        WHERE k.f_key_hash = $1 AND (k.f_expires_at IS NULL OR k.f_expires_at > now())
+          AND u.f_deleted_at IS NULL
+    "#,
+    )
+    .bind(opaque_token::digest(key))
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(grant) = grant else {
+        return Ok(None);
+    };
+
+    // An org-scoped key stops working once its owner leaves the organization.
+    if let Some(org_id) = &grant.f_org_id
+        && select_member_role(repo.pool(), org_id, &grant.f_user_id)
+            .await?
+            .is_none()
+    {
+        tracing::debug!("API key {} outlived its owner's membership", grant.f_id);
+
+        return Ok(None);
+    }
+
+    let stale = grant
+        .f_last_used_at
+        .is_none_or(|at| at + LAST_USED_RESOLUTION < OffsetDateTime::now_utc());
+
+    if stale {
+        query("UPDATE t_api_key SET f_last_used_at = now() WHERE f_id = $1")
+            .bind(&grant.f_id)
+            .execute(repo.pool())
+            .await
+            .trace_error()?;
+    }
+
+    let claims = user_claims(&grant.f_user_id, config, repo)
+        .await?
+        .with_org(grant.f_org_id)
+        .with_scopes(&grant.f_scopes);
+
+    Ok(Some(claims))
+}
@@ -0,0 +1,52 @@
+use sqlx::{Executor, Postgres, query};
+use uuid::Uuid;
+
+use crate::{result_trace::ResultTrace as _, service::result::InterResult};
+
+/// Too many failed logins, the account is locked for a while.
+pub const ACCOUNT_LOCKED: &str = "account.locked";
+/// An administrator lifted a lockout early.
+pub const ACCOUNT_UNLOCKED: &str = "account.unlocked";
+/// The new address of an email change was confirmed.
+pub const EMAIL_CHANGED: &str = "email.changed";
+/// An email change was undone from the former address.
+pub const EMAIL_CHANGE_REVERTED: &str = "email.change_reverted";
+/// The user asked for their account to be deleted.
+pub const ACCOUNT_DELETION_REQUESTED: &str = "account.deletion_requested";
+/// A login within the grace period kept the account.
+pub const ACCOUNT_DELETION_CANCELLED: &str = "account.deletion_cancelled";
+/// The account was erased once its grace period ended.
+pub const ACCOUNT_PURGED: &str = "account.purged";
+
+/// A security-relevant event, stored in `t_audit_log`.
+#[derive(Debug, Default)]
+pub struct AuditEntry<'a> {
+    pub action: &'a str,
+    pub user_id: Option<&'a str>,
+    pub actor_id: Option<&'a str>,
+    pub ip: Option<String>,
+    pub detail: String,
+}
+
+pub(super) async fn record_audit<'c, E>(exec: E, entry: AuditEntry<'_>) -> InterResult<()>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    query(
+        r#"
+        INSERT INTO t_audit_log (f_id, f_action, f_user_id, f_actor_id, f_ip, f_detail)
+        VALUES ($1, $2, $3, $4, $5, $6)
+    "#,
+    )
+    .bind(Uuid::now_v7().to_string())
+    .bind(entry.action)
+    .bind(entry.user_id)
+    .bind(entry.actor_id)
+    .bind(entry.ip)
+    .bind(entry.detail)
+    .execute(exec)
+    .await
+    .trace_error()?;
+
+    Ok(())
+}
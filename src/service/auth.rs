@@ -1,53 +1,98 @@
-use argon2::{
-    Argon2, PasswordHash, PasswordHasher as _, PasswordVerifier as _,
-    password_hash::{Error as PasswordHashError, SaltString, rand_core::OsRng},
-};
-use sqlx::{Executor, Postgres, query, query_as};
+use std::{net::IpAddr, sync::LazyLock};
+
+use redis::{AsyncTypedCommands as _, Script, aio::ConnectionManager};
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Postgres, query, query_as, query_scalar};
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 use crate::{
-    config::AppConfig,
-    jwt_codec::{JwtCodec, UserClaims},
-    model::user::{LoginUserArgs, LoginUserReply},
-    repo::{Repo, user::UserSecrets},
+    cache::Cache,
+    config::{AppConfig, EmailVerificationPolicy},
+    jwt_codec::{JwtCodec, UserClaims, unix_millis},
+    mailer::Mailer,
+    model::{
+        organization::SwitchOrgReply,
+        user::{
+            LoginUserArgs, LoginUserReply, LogoutUserArgs, RefreshTokenArgs, RefreshTokenReply,
+            RegisterUserArgs, RegisterUserReply,
+        },
+    },
+    opaque_token,
+    password_hasher::PasswordHasher,
+    password_policy::PasswordPolicy,
+    permission,
+    repo::{
+        Repo, is_unique_violation,
+        user::{UserGrant, UserSecrets},
+    },
     result_trace::ResultTrace as _,
-    service::result::{InterResult, ServiceResult, accept, reject},
+    service::{
+        account::{cancel_account_deletion, is_purgeable},
+        lockout::{check_login_allowed, clear_login_failures, record_login_failure},
+        mfa::{is_mfa_enabled, start_mfa_challenge},
+        oauth::revoke_client_refresh_tokens,
+        organization::select_member_role,
+        password::check_password_policy,
+        result::{InterResult, ServiceResult, accept, reject},
+        session::session_key,
+        verification::send_verification_mail,
+    },
 };
 
-fn generate_password_hash(password: &str) -> InterResult<String> {
-    let salt = SaltString::generate(&mut OsRng);
-
-    let argon2 = Argon2::default();
+/// Atomically rotate the live refresh token of a family.
+///
+/// Returns `1` when `ARGV[1]` was the live token and has been replaced by `ARGV[2]`,
+/// `0` when `ARGV[1]` was an already rotated token (the family is then revoked),
+/// and `-1` when the family no longer exists.
+static ROTATE_REFRESH_TOKEN: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local current = redis.call('GET', KEYS[1])
+        if not current then
+            return -1
+        end
+        if current ~= ARGV[1] then
+            redis.call('DEL', KEYS[1])
+            return 0
+        end
+        redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+        return 1
+    "#,
+    )
+});
 
-    let password_hash = argon2
-        .hash_password(password.as_bytes(), &salt)
-        .trace_warn()?
-        .to_string();
+pub(super) async fn generate_password_hash(
+    password: &str,
+    password_hasher: &PasswordHasher,
+) -> InterResult<String> {
+    let password_hash = password_hasher.hash(password).await.trace_warn()?;
 
     Ok(password_hash)
 }
 
-fn verify_password(password: &str, hash: &str) -> InterResult<bool> {
-    let parsed_hash = PasswordHash::new(hash).trace_warn()?;
-
-    let argon2 = Argon2::default();
+pub(super) async fn verify_password(
+    password: &str,
+    hash: &str,
+    password_hasher: &PasswordHasher,
+) -> InterResult<bool> {
+    let matched = password_hasher.verify(password, hash).await.trace_warn()?;
 
-    match argon2.verify_password(password.as_bytes(), &parsed_hash) {
-        Ok(()) => Ok(true),
-        Err(PasswordHashError::Password) => Ok(false),
-        Err(err) => Err(err.into()),
-    }
+    Ok(matched)
 }
 
-async fn select_user_secrets<'c, E>(exec: E, email: &str) -> InterResult<Option<UserSecrets>>
+pub(super) async fn select_user_secrets<'c, E>(
+    exec: E,
+    email: &str,
+) -> InterResult<Option<UserSecrets>>
 where
     E: 'c + Executor<'c, Database = Postgres>,
 {
     let password_hash: Option<UserSecrets> = query_as(
         r#"
-        SELECT f_user_id, f_password_hash
+        SELECT f_id, f_password_hash, f_email_verified_at, f_deleted_at
         FROM t_user
-        WHERE f_email = $1
+        WHERE lower(f_email) = lower($1)
     "#,
     )
     .bind(email)
@@ -57,26 +102,56 @@ where
     Ok(password_hash)
 }
 
-async fn register_new_user<'c, E>(
+pub(super) async fn register_new_user<'c, E>(
     exec: E,
     user_id: &str,
     email: &str,
     nickname: &str,
     password_hash: &str,
+    email_verified: bool,
 ) -> InterResult<()>
 where
     E: 'c + Executor<'c, Database = Postgres>,
 {
-    query(
+    let inserted = query(
         r#"
-        INSERT INTO t_user (f_user_id, f_email, f_nickname, f_password_hash)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO t_user (f_id, f_email, f_nickname, f_password_hash, f_email_verified_at)
+        VALUES ($1, $2, $3, $4, CASE WHEN $5 THEN now() END)
     "#,
     )
     .bind(user_id)
     .bind(email)
     .bind(nickname)
     .bind(password_hash)
+    .bind(email_verified)
+    .execute(exec)
+    .await;
+
+    // Registrations of one email can race past the lookup, the unique index settles it.
+    if let Err(err) = &inserted
+        && is_unique_violation(err)
+    {
+        return Err(reject(409, "Email already registered"));
+    }
+
+    inserted.trace_error()?;
+
+    Ok(())
+}
+
+pub(super) async fn assign_role<'c, E>(exec: E, user_id: &str, role: &str) -> InterResult<()>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    query(
+        r#"
+        INSERT INTO t_user_role (f_user_id, f_role)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+    "#,
+    )
+    .bind(user_id)
+    .bind(role)
     .execute(exec)
     .await
     .trace_error()?;
@@ -84,60 +159,685 @@ where
     Ok(())
 }
 
-fn generate_token(user_id: &str, config: &AppConfig, jwt_codec: &JwtCodec) -> InterResult<String> {
-    let claims = UserClaims::with_exp(user_id, config.jwt_exp_seconds);
+/// Select the roles of a user and the permissions they grant.
+async fn select_user_grants<'c, E>(
+    exec: E,
+    user_id: &str,
+) -> InterResult<(Vec<String>, Vec<String>)>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let rows: Vec<UserGrant> = query_as(
+        r#"
+        SELECT ur.f_role, rp.f_permission
+        FROM t_user_role ur
+        LEFT JOIN t_role_permission rp ON rp.f_role = ur.f_role
+        WHERE ur.f_user_id = $1
+    "#,
+    )
+    .bind(user_id)
+    .fetch_all(exec)
+    .await
+    .trace_error()?;
+
+    let mut roles = Vec::new();
+    let mut permissions = Vec::new();
+
+    for row in rows {
+        if !roles.contains(&row.f_role) {
+            roles.push(row.f_role);
+        }
+
+        if let Some(p) = row.f_permission
+            && !permissions.contains(&p)
+        {
+            permissions.push(p);
+        }
+    }
+
+    Ok((roles, permissions))
+}
+
+async fn select_email_verified<'c, E>(exec: E, user_id: &str) -> InterResult<bool>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let verified: Option<bool> = query_scalar(
+        r#"
+        SELECT f_email_verified_at IS NOT NULL
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(user_id)
+    .fetch_optional(exec)
+    .await
+    .trace_error()?;
+
+    Ok(verified.unwrap_or(false))
+}
+
+/// Claims of a new access token for a user, carrying their current grants.
+pub(super) async fn user_claims(
+    user_id: &str,
+    config: &AppConfig,
+    repo: &Repo,
+) -> InterResult<UserClaims> {
+    // Grants are read on every issuance, so role changes apply from the next refresh on.
+    let (roles, permissions) = select_user_grants(repo.pool(), user_id).await?;
+
+    let email_verified = select_email_verified(repo.pool(), user_id).await?;
+
+    let claims = UserClaims::with_exp(user_id, config, config.jwt_exp_seconds)
+        .with_grants(roles, permissions)
+        .with_email_verified(email_verified);
+
+    Ok(claims)
+}
+
+async fn generate_token(
+    user_id: &str,
+    org_id: Option<&str>,
+    session_id: &str,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+) -> InterResult<String> {
+    let claims = user_claims(user_id, config, repo)
+        .await?
+        .with_org(org_id.map(str::to_string))
+        .with_session(session_id);
 
     let token = jwt_codec.encode(&claims).trace_error()?;
 
     Ok(token)
 }
 
+/// What is stored in Redis for every issued refresh token, keyed by the token digest.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct RefreshTokenRecord {
+    pub user_id: String,
+    pub family_id: String,
+    /// Active organization carried over to refreshed access tokens.
+    #[serde(default)]
+    pub org_id: Option<String>,
+}
+
+/// Key of the record of a single refresh token.
+fn refresh_token_key(digest: &str) -> String {
+    format!("refresh:token:{}", digest)
+}
+
+/// Key holding the digest of the only refresh token of a family that may still be used.
+/// A family is a session, it ends when this key goes.
+pub(super) fn refresh_family_key(family_id: &str) -> String {
+    format!("refresh:family:{}", family_id)
+}
+
+/// Key of the set of refresh token families issued to a user.
+pub(super) fn refresh_user_families_key(user_id: &str) -> String {
+    format!("refresh:user:{}", user_id)
+}
+
+/// Key marking a single access token as revoked.
+fn revoked_token_key(jti: &str) -> String {
+    format!("revoked:jti:{}", jti)
+}
+
+/// Key holding the time, in milliseconds, before which every access token of a user is revoked.
+fn revoked_before_key(user_id: &str) -> String {
+    format!("revoked:user:{}", user_id)
+}
+
+async fn store_refresh_token(
+    conn: &mut ConnectionManager,
+    digest: &str,
+    record: &RefreshTokenRecord,
+    config: &AppConfig,
+) -> InterResult<()> {
+    let record = serde_json::to_string(record)?;
+
+    conn.set_ex(
+        refresh_token_key(digest),
+        record,
+        config.refresh_token_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    Ok(())
+}
+
+/// Consume a refresh token without a successor, its family lives on as long as
+/// its family key, e.g. to back a cookie session.
+pub(super) async fn take_refresh_token(
+    refresh_token: &str,
+    cache: &Cache,
+) -> InterResult<Option<RefreshTokenRecord>> {
+    let mut conn = cache.conn();
+
+    let record = conn
+        .get_del(refresh_token_key(&opaque_token::digest(refresh_token)))
+        .await
+        .trace_error()?;
+
+    match record {
+        Some(record) => Ok(Some(serde_json::from_str(&record)?)),
+        None => Ok(None),
+    }
+}
+
+pub(super) struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// Issue an access token and the first refresh token of a new token family.
+pub(super) async fn issue_token_pair(
+    user_id: &str,
+    org_id: Option<&str>,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> InterResult<TokenPair> {
+    let family_id = Uuid::now_v7().to_string();
+
+    let token = generate_token(user_id, org_id, &family_id, config, jwt_codec, repo).await?;
+
+    let refresh_token = opaque_token::generate();
+    let digest = opaque_token::digest(&refresh_token);
+
+    let record = RefreshTokenRecord {
+        user_id: user_id.to_string(),
+        family_id,
+        org_id: org_id.map(str::to_string),
+    };
+
+    let mut conn = cache.conn();
+
+    store_refresh_token(&mut conn, &digest, &record, config).await?;
+
+    conn.set_ex(
+        refresh_family_key(&record.family_id),
+        &digest,
+        config.refresh_token_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    let families_key = refresh_user_families_key(user_id);
+
+    conn.sadd(&families_key, &record.family_id)
+        .await
+        .trace_error()?;
+    conn.expire(&families_key, config.refresh_token_exp_seconds as i64)
+        .await
+        .trace_error()?;
+
+    Ok(TokenPair {
+        token,
+        refresh_token,
+    })
+}
+
+/// Finish a successful first factor: hand out tokens, with `org_id` active if given,
+/// or an MFA token when the user has a second factor that `second_factor_done` does
+/// not already account for.
+pub(super) async fn complete_login(
+    user_id: String,
+    second_factor_done: bool,
+    org_id: Option<&str>,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<LoginUserReply> {
+    if !second_factor_done && is_mfa_enabled(repo.pool(), &user_id).await? {
+        let mfa_token = start_mfa_challenge(&user_id, config, cache).await?;
+
+        return Ok(accept()
+            .with_message("Second factor required")
+            .with_data(LoginUserReply {
+                user_id,
+                token: None,
+                refresh_token: None,
+                expires_in: None,
+                mfa_token: Some(mfa_token),
+            }));
+    }
+
+    // Only a complete login keeps an account scheduled for deletion.
+    let cancelled = cancel_account_deletion(&user_id, config, repo).await?;
+
+    let pair = issue_token_pair(&user_id, org_id, config, jwt_codec, repo, cache).await?;
+
+    let reply = match cancelled {
+        true => accept().with_message("Account deletion cancelled"),
+        false => accept(),
+    };
+
+    Ok(reply.with_data(LoginUserReply {
+        user_id,
+        token: Some(pair.token),
+        refresh_token: Some(pair.refresh_token),
+        expires_in: Some(config.jwt_exp_seconds),
+        mfa_token: None,
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn register_user(
+    args: RegisterUserArgs,
+    config: &AppConfig,
+    password_policy: &PasswordPolicy,
+    jwt_codec: &JwtCodec,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> ServiceResult<RegisterUserReply> {
+    check_password_policy(
+        "password",
+        &args.password,
+        &[&args.email, &args.nickname],
+        password_policy,
+    )
+    .await?;
+
+    let user_id = Uuid::now_v7().to_string();
+
+    let password_hash = generate_password_hash(&args.password, password_hasher).await?;
+
+    // A registered email is caught by the unique index, answered with 409.
+    let mut trx = repo.pool().begin().await?;
+
+    register_new_user(
+        &mut *trx,
+        &user_id,
+        &args.email,
+        &args.nickname,
+        &password_hash,
+        false,
+    )
+    .await?;
+
+    assign_role(&mut *trx, &user_id, permission::DEFAULT_ROLE).await?;
+
+    trx.commit().await?;
+
+    // The account exists either way, a lost mail can be sent again.
+    send_verification_mail(&user_id, &args.email, config, cache, mailer)
+        .await
+        .trace_warn()
+        .ok();
+
+    if config.email_verification_policy == EmailVerificationPolicy::BlockLogin {
+        return Ok(accept().with_code(201).with_data(RegisterUserReply {
+            user_id,
+            token: None,
+            refresh_token: None,
+            expires_in: None,
+        }));
+    }
+
+    // Generate tokens for the new user.
+    let pair = issue_token_pair(&user_id, None, config, jwt_codec, repo, cache).await?;
+
+    Ok(accept().with_code(201).with_data(RegisterUserReply {
+        user_id,
+        token: Some(pair.token),
+        refresh_token: Some(pair.refresh_token),
+        expires_in: Some(config.jwt_exp_seconds),
+    }))
+}
+
 pub async fn login_user(
     args: LoginUserArgs,
+    client_ip: IpAddr,
     config: &AppConfig,
     jwt_codec: &JwtCodec,
+    password_hasher: &PasswordHasher,
     repo: &Repo,
+    cache: &Cache,
 ) -> ServiceResult<LoginUserReply> {
-    let mut trx = repo.pool().begin().await?;
+    check_login_allowed(&args.email, client_ip, config, cache).await?;
+
+    // Unknown emails and wrong passwords are rejected alike so that the
+    // endpoint cannot be used to probe which emails are registered.
+    // Accounts past their deletion grace period are as good as gone.
+    let secrets = select_user_secrets(repo.pool(), &args.email)
+        .await?
+        .filter(|secrets| !is_purgeable(secrets.f_deleted_at, config));
 
-    let secrets = match select_user_secrets(&mut *trx, &args.email).await? {
-        None => {
-            // If the user does not exist, register a new user.
-            let user_id = Uuid::now_v7().to_string();
+    let Some(secrets) = secrets else {
+        // Spend the time a wrong password would, so timing does not tell unknown emails apart.
+        verify_password(
+            &args.password,
+            password_hasher.dummy_hash(),
+            password_hasher,
+        )
+        .await?;
 
-            let password_hash = generate_password_hash(&args.password)?;
+        record_login_failure(&args.email, None, client_ip, config, repo, cache).await?;
 
-            register_new_user(
-                &mut *trx,
-                &user_id,
-                &args.email,
-                &args.nickname,
-                &password_hash,
-            )
-            .await?;
+        return Err(reject(401, "Invalid email or password"));
+    };
 
-            // Generate a token for the new user.
-            let token = generate_token(&user_id, config, jwt_codec)?;
+    if !(verify_password(&args.password, &secrets.f_password_hash, password_hasher).await?) {
+        let user_id = Some(secrets.f_id.as_str());
 
-            return Ok(accept()
-                .with_code(204)
-                .with_data(LoginUserReply { user_id, token }));
-        }
-        Some(p) => p,
+        record_login_failure(&args.email, user_id, client_ip, config, repo, cache).await?;
+
+        return Err(reject(401, "Invalid email or password"));
+    }
+
+    clear_login_failures(&args.email, cache).await?;
+
+    if config.email_verification_policy == EmailVerificationPolicy::BlockLogin
+        && secrets.f_email_verified_at.is_none()
+    {
+        return Err(reject(403, "Email address is not verified"));
+    }
+
+    // The password is only known now, the moment to move its hash to the current cost and pepper.
+    if password_hasher.needs_rehash(&secrets.f_password_hash) {
+        let password_hash = generate_password_hash(&args.password, password_hasher).await?;
+
+        // Opened only now, so that no connection is held across the hashing.
+        let mut trx = repo.pool().begin().await?;
+
+        // Matching the old hash leaves a password changed meanwhile untouched.
+        query(
+            r#"
+            UPDATE t_user
+            SET f_password_hash = $3
+            WHERE f_id = $1 AND f_password_hash = $2
+        "#,
+        )
+        .bind(&secrets.f_id)
+        .bind(&secrets.f_password_hash)
+        .bind(&password_hash)
+        .execute(&mut *trx)
+        .await
+        .trace_error()?;
+
+        trx.commit().await?;
+
+        tracing::debug!("Rehashed password of user {}", secrets.f_id);
+    }
+
+    // Password matched, generate tokens.
+    complete_login(secrets.f_id, false, None, config, jwt_codec, repo, cache).await
+}
+
+pub async fn refresh_token(
+    args: RefreshTokenArgs,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<RefreshTokenReply> {
+    let digest = opaque_token::digest(&args.refresh_token);
+
+    let mut conn = cache.conn();
+
+    let Some(record) = conn.get(refresh_token_key(&digest)).await.trace_error()? else {
+        return Err(reject(401, "Invalid refresh token"));
     };
 
-    // If the user exists, verify the password.
+    let record: RefreshTokenRecord = serde_json::from_str(&record)?;
+
+    let next_refresh_token = opaque_token::generate();
+    let next_digest = opaque_token::digest(&next_refresh_token);
+
+    // The successor is stored before the rotation; if the rotation fails it is
+    // never referenced by the family and simply expires.
+    store_refresh_token(&mut conn, &next_digest, &record, config).await?;
+
+    let rotated: i64 = ROTATE_REFRESH_TOKEN
+        .key(refresh_family_key(&record.family_id))
+        .arg(&digest)
+        .arg(&next_digest)
+        .arg(config.refresh_token_exp_seconds)
+        .invoke_async(&mut conn)
+        .await
+        .trace_error()?;
 
-    if !(verify_password(&args.password, &secrets.f_password_hash)?) {
-        // Unmatched password, return an error.
-        return Err(reject(400, "Invalid password"));
+    match rotated {
+        1 => {}
+        0 => {
+            // An already rotated token was presented again: either the client or an
+            // attacker holds a stolen copy, so the whole family is revoked.
+            tracing::warn!(
+                "Refresh token reuse detected for user {}, family {} revoked",
+                record.user_id,
+                record.family_id
+            );
+
+            return Err(reject(401, "Invalid refresh token"));
+        }
+        _ => return Err(reject(401, "Invalid refresh token")),
     }
 
-    // Password matched, generate a token.
-    let token = generate_token(&secrets.f_id, config, jwt_codec)?;
+    // The user may have left the active organization since the family was issued.
+    let org_id = match record.org_id.as_deref() {
+        Some(org_id) => select_member_role(repo.pool(), org_id, &record.user_id)
+            .await?
+            .map(|_| org_id),
+        None => None,
+    };
+
+    // Refreshing is activity, the session lives on as long as its refresh token.
+    conn.expire(
+        session_key(&record.family_id),
+        config.refresh_token_exp_seconds as i64,
+    )
+    .await
+    .trace_error()?;
+
+    let token = generate_token(
+        &record.user_id,
+        org_id,
+        &record.family_id,
+        config,
+        jwt_codec,
+        repo,
+    )
+    .await?;
 
-    Ok(accept().with_data(LoginUserReply {
-        user_id: secrets.f_id,
+    Ok(accept().with_data(RefreshTokenReply {
+        user_id: record.user_id,
         token,
+        refresh_token: next_refresh_token,
+        expires_in: config.jwt_exp_seconds,
+    }))
+}
+
+/// End a refresh token family and the session it backs, access tokens carrying it included.
+async fn end_family(
+    conn: &mut ConnectionManager,
+    user_id: &str,
+    family_id: &str,
+) -> InterResult<()> {
+    conn.del(&[refresh_family_key(family_id), session_key(family_id)])
+        .await
+        .trace_error()?;
+    conn.srem(refresh_user_families_key(user_id), family_id)
+        .await
+        .trace_error()?;
+
+    Ok(())
+}
+
+/// Make `org_id` the active organization of the caller, who must already be a member of it.
+/// A new token family is issued so that refreshed tokens keep the organization.
+pub async fn switch_org(
+    org_id: &str,
+    claims: &UserClaims,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<SwitchOrgReply> {
+    // The new family replaces the caller's session, which would otherwise live on
+    // with the previous organization.
+    if let Some(sid) = &claims.sid {
+        end_family(&mut cache.conn(), &claims.sub, sid).await?;
+    }
+
+    let pair = issue_token_pair(&claims.sub, Some(org_id), config, jwt_codec, repo, cache).await?;
+
+    Ok(accept().with_data(SwitchOrgReply {
+        user_id: claims.sub.clone(),
+        org_id: org_id.to_string(),
+        token: Some(pair.token),
+        refresh_token: Some(pair.refresh_token),
+        expires_in: Some(config.jwt_exp_seconds),
     }))
 }
+
+/// What one round trip to Redis tells about an access token.
+#[derive(Debug)]
+pub struct TokenStatus {
+    /// Revoked by a logout, or its session has ended.
+    pub revoked: bool,
+    /// Device record of the token's session, read along so that recording the
+    /// session's use needs no lookup of its own.
+    pub session_record: Option<String>,
+}
+
+/// Check whether an access token has been revoked, fetching its session record too.
+pub async fn token_status(claims: &UserClaims, cache: &Cache) -> InterResult<TokenStatus> {
+    let mut conn = cache.conn();
+
+    let mut keys = vec![
+        revoked_token_key(&claims.jti),
+        revoked_before_key(&claims.sub),
+    ];
+
+    if let Some(sid) = &claims.sid {
+        keys.push(refresh_family_key(sid));
+        keys.push(session_key(sid));
+    }
+
+    let mut values = conn.mget(&keys).await?;
+
+    let session_record = values.get_mut(3).and_then(Option::take);
+
+    let revoked_before = values
+        .get(1)
+        .and_then(Option::as_deref)
+        .and_then(|v| v.parse::<i64>().ok());
+
+    let revoked = values.first().is_some_and(Option::is_some)
+        || (claims.sid.is_some() && values.get(2).is_none_or(Option::is_none))
+        || revoked_before.is_some_and(|before| claims.issued_before(before));
+
+    Ok(TokenStatus {
+        revoked,
+        session_record,
+    })
+}
+
+/// Check whether an access token has been revoked by a logout, or its session has ended.
+pub async fn is_token_revoked(claims: &UserClaims, cache: &Cache) -> InterResult<bool> {
+    Ok(token_status(claims, cache).await?.revoked)
+}
+
+/// Revoke a single access token until it expires.
+pub(super) async fn revoke_access_token(claims: &UserClaims, cache: &Cache) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    // Keep the revocation around only for as long as the token would be valid.
+    let remaining = claims.exp - OffsetDateTime::now_utc().unix_timestamp();
+
+    if remaining > 0 {
+        conn.set_ex(revoked_token_key(&claims.jti), 1, remaining as u64)
+            .await
+            .trace_error()?;
+    }
+
+    Ok(())
+}
+
+pub async fn logout_user(
+    args: LogoutUserArgs,
+    claims: &UserClaims,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    revoke_access_token(claims, cache).await?;
+
+    let mut conn = cache.conn();
+
+    let mut family_ids: Vec<String> = claims.sid.iter().cloned().collect();
+
+    if let Some(refresh_token) = args.refresh_token {
+        let digest = opaque_token::digest(&refresh_token);
+
+        if let Some(record) = conn.get(refresh_token_key(&digest)).await.trace_error()? {
+            let record: RefreshTokenRecord = serde_json::from_str(&record)?;
+
+            // Never let a caller end somebody else's session.
+            if record.user_id == claims.sub {
+                family_ids.push(record.family_id);
+            }
+        }
+    }
+
+    for family_id in family_ids {
+        end_family(&mut conn, &claims.sub, &family_id).await?;
+    }
+
+    Ok(accept().with_message("Logged out"))
+}
+
+pub async fn logout_all(
+    claims: &UserClaims,
+    config: &AppConfig,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    revoke_all_tokens(&claims.sub, config, cache).await?;
+
+    Ok(accept().with_message("Logged out from all sessions"))
+}
+
+/// Revoke every access token issued to a user so far along with all refresh token families
+/// and the refresh tokens of OAuth clients acting on the user's behalf.
+pub(super) async fn revoke_all_tokens(
+    user_id: &str,
+    config: &AppConfig,
+    cache: &Cache,
+) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    // Milliseconds, so that a token issued right after, e.g. to the caller of a password
+    // change, is not caught by the cutoff of its own second.
+    let now_ms = unix_millis(OffsetDateTime::now_utc());
+
+    // Access tokens issued before now have expired once `jwt_exp_seconds` elapsed,
+    // so the marker does not need to outlive them.
+    conn.set_ex(
+        revoked_before_key(user_id),
+        now_ms,
+        config.jwt_exp_seconds as u64,
+    )
+    .await
+    .trace_error()?;
+
+    let families_key = refresh_user_families_key(user_id);
+
+    let families = conn.smembers(&families_key).await.trace_error()?;
+
+    let mut keys: Vec<String> = families
+        .iter()
+        .flat_map(|f| [refresh_family_key(f), session_key(f)])
+        .collect();
+    keys.push(families_key);
+
+    conn.del(keys).await.trace_error()?;
+
+    revoke_client_refresh_tokens(user_id, cache).await?;
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use redis::{AsyncTypedCommands as _, cmd};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::UserClaims,
+    opaque_token,
+    repo::Repo,
+    result_trace::ResultTrace as _,
+    service::{
+        auth::{take_refresh_token, user_claims},
+        result::{InterResult, reject},
+    },
+};
+
+/// What is stored in Redis for a cookie session, keyed by the digest of the cookie.
+#[derive(Debug, Serialize, Deserialize)]
+struct CookieSessionRecord {
+    user_id: String,
+    /// Refresh token family backing the session, listed and ended like any other session.
+    session_id: String,
+    org_id: Option<String>,
+    csrf_digest: String,
+    /// Claims handed to handlers, rebuilt once they expire so that grant changes apply
+    /// as they would from a token refresh.
+    claims: Option<UserClaims>,
+}
+
+fn cookie_session_key(digest: &str) -> String {
+    format!("cookie:session:{}", digest)
+}
+
+/// Values of the session cookie and of the CSRF cookie.
+pub struct CookieSessionTokens {
+    pub session_token: String,
+    pub csrf_token: String,
+}
+
+/// Move a freshly issued token pair into a cookie session. The refresh token is consumed,
+/// the session lasts as long as a refresh token would have.
+pub async fn start_cookie_session(
+    refresh_token: &str,
+    config: &AppConfig,
+    cache: &Cache,
+) -> InterResult<CookieSessionTokens> {
+    let Some(refresh) = take_refresh_token(refresh_token, cache).await? else {
+        return Err(reject(401, "Invalid refresh token"));
+    };
+
+    let session_token = opaque_token::generate();
+    let csrf_token = opaque_token::generate();
+
+    let record = serde_json::to_string(&CookieSessionRecord {
+        user_id: refresh.user_id,
+        session_id: refresh.family_id,
+        org_id: refresh.org_id,
+        csrf_digest: opaque_token::digest(&csrf_token),
+        claims: None,
+    })?;
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        cookie_session_key(&opaque_token::digest(&session_token)),
+        record,
+        config.refresh_token_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    Ok(CookieSessionTokens {
+        session_token,
+        csrf_token,
+    })
+}
+
+/// Resolve a session cookie into claims, `None` for unknown or expired sessions.
+///
+/// Requests with unsafe methods must echo the CSRF cookie in the CSRF header (double
+/// submit), and the token must be the one issued with this session.
+pub async fn authenticate_cookie_session(
+    session_token: &str,
+    csrf: Option<(&str, &str)>,
+    unsafe_method: bool,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> InterResult<Option<UserClaims>> {
+    let key = cookie_session_key(&opaque_token::digest(session_token));
+
+    let mut conn = cache.conn();
+
+    let Some(record) = conn.get(&key).await.trace_error()? else {
+        return Ok(None);
+    };
+
+    let mut record: CookieSessionRecord = serde_json::from_str(&record)?;
+
+    if unsafe_method {
+        let submitted = csrf.filter(|(cookie, header)| cookie == header);
+
+        if submitted.is_none_or(|(_, header)| opaque_token::digest(header) != record.csrf_digest) {
+            tracing::debug!("CSRF check failed for session {}", record.session_id);
+
+            return Err(reject(403, "Invalid CSRF token"));
+        }
+    }
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    if let Some(claims) = record.claims.as_ref().filter(|claims| claims.exp > now) {
+        return Ok(Some(claims.clone()));
+    }
+
+    let claims = user_claims(&record.user_id, config, repo)
+        .await?
+        .with_org(record.org_id.clone())
+        .with_session(&record.session_id);
+
+    record.claims = Some(claims.clone());
+
+    // `KEEPTTL` leaves the lifetime of the session as it was set at login.
+    let _: () = cmd("SET")
+        .arg(&key)
+        .arg(serde_json::to_string(&record)?)
+        .arg("KEEPTTL")
+        .query_async(&mut conn)
+        .await
+        .trace_error()?;
+
+    Ok(Some(claims))
+}
+
+/// Forget a session cookie. The session itself is ended by the logout it comes with.
+pub async fn end_cookie_session(session_token: &str, cache: &Cache) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    conn.del(cookie_session_key(&opaque_token::digest(session_token)))
+        .await
+        .trace_error()?;
+
+    Ok(())
+}
@@ -0,0 +1,365 @@
+use redis::AsyncTypedCommands as _;
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Postgres, query, query_as, query_scalar};
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::UserClaims,
+    mailer::{Mail, Mailer},
+    model::{
+        user::{ChangeEmailArgs, ConfirmEmailChangeArgs, RevertEmailChangeArgs},
+        validation::FieldError,
+    },
+    opaque_token,
+    password_hasher::PasswordHasher,
+    repo::{Repo, is_unique_violation},
+    result_trace::ResultTrace as _,
+    service::{
+        audit::{AuditEntry, EMAIL_CHANGE_REVERTED, EMAIL_CHANGED, record_audit},
+        auth::{revoke_all_tokens, verify_password},
+        rate_limit::hit_rate_limit,
+        result::{InterResult, ServiceResult, accept, reject, reject_fields},
+    },
+};
+
+/// Longest address SMTP can carry.
+const EMAIL_MAX_LENGTH: usize = 254;
+
+/// What is stored in Redis for a confirmation token. The former address is kept so that
+/// a change confirmed after the account moved on to yet another address is refused.
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailChangeRecord {
+    user_id: String,
+    old_email: String,
+    new_email: String,
+}
+
+/// What is stored in Redis for a revert token, along with the digest of the confirmation
+/// token so that reverting before the change was confirmed cancels it.
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailRevertRecord {
+    user_id: String,
+    old_email: String,
+    new_email: String,
+    confirm_digest: String,
+}
+
+fn confirm_token_key(digest: &str) -> String {
+    format!("change:email:confirm:{}", digest)
+}
+
+fn revert_token_key(digest: &str) -> String {
+    format!("change:email:revert:{}", digest)
+}
+
+/// Rough shape of an address, whether it works is what the confirmation mail finds out.
+fn is_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    email.len() <= EMAIL_MAX_LENGTH
+        && !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.chars().any(char::is_whitespace)
+}
+
+/// Whether another account than `user_id` goes by `email`, in any case.
+async fn email_taken<'c, E>(exec: E, email: &str, user_id: &str) -> InterResult<bool>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let taken: Option<i32> = query_scalar(
+        r#"
+        SELECT 1
+        FROM t_user
+        WHERE lower(f_email) = lower($1) AND f_id <> $2
+    "#,
+    )
+    .bind(email)
+    .bind(user_id)
+    .fetch_optional(exec)
+    .await
+    .trace_error()?;
+
+    Ok(taken.is_some())
+}
+
+/// Start moving the caller's account to another address: a confirmation link goes to the
+/// new address and a notice with a link undoing the change goes to the current one.
+pub async fn request_email_change(
+    claims: &UserClaims,
+    args: ChangeEmailArgs,
+    config: &AppConfig,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> ServiceResult<()> {
+    // Bounds both the mails sent and the passwords a stolen session can try.
+    hit_rate_limit(
+        cache,
+        &format!("change-email:{}", claims.sub),
+        config.verification_resend_limit,
+        config.verification_resend_window_seconds,
+    )
+    .await?;
+
+    let new_email = args.new_email.trim().to_string();
+
+    if !is_email(&new_email) {
+        return Err(reject_fields(
+            400,
+            "Invalid email",
+            vec![FieldError {
+                field: "new_email".to_string(),
+                code: "invalid".to_string(),
+                message: "Not a valid email address".to_string(),
+            }],
+        ));
+    }
+
+    let account: Option<(String, String)> = query_as(
+        r#"
+        SELECT f_password_hash, f_email
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some((password_hash, old_email)) = account else {
+        return Err(reject(404, "User not found"));
+    };
+
+    if !(verify_password(&args.password, &password_hash, password_hasher).await?) {
+        return Err(reject(401, "Password is incorrect"));
+    }
+
+    if new_email == old_email {
+        return Err(reject(400, "This is already the account's email"));
+    }
+
+    if email_taken(repo.pool(), &new_email, &claims.sub).await? {
+        return Err(reject(409, "Email already registered"));
+    }
+
+    let confirm_token = opaque_token::generate();
+    let revert_token = opaque_token::generate();
+
+    let confirm_digest = opaque_token::digest(&confirm_token);
+
+    let confirm_record = serde_json::to_string(&EmailChangeRecord {
+        user_id: claims.sub.clone(),
+        old_email: old_email.clone(),
+        new_email: new_email.clone(),
+    })?;
+
+    let revert_record = serde_json::to_string(&EmailRevertRecord {
+        user_id: claims.sub.clone(),
+        old_email: old_email.clone(),
+        new_email: new_email.clone(),
+        confirm_digest: confirm_digest.clone(),
+    })?;
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        confirm_token_key(&confirm_digest),
+        confirm_record,
+        config.email_change_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    conn.set_ex(
+        revert_token_key(&opaque_token::digest(&revert_token)),
+        revert_record,
+        config.email_change_revert_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    mailer
+        .send(Mail {
+            to: new_email.clone(),
+            subject: "Confirm your new email address".to_string(),
+            body: format!(
+                "Confirm that your account should use this address from now on: {}/confirm-email-change?token={}\n\nIf you did not ask for this, ignore this mail.",
+                config.app_base_url, confirm_token
+            ),
+        })
+        .await
+        .trace_error()?;
+
+    mailer
+        .send(Mail {
+            to: old_email,
+            subject: "Your email address is being changed".to_string(),
+            body: format!(
+                "A change of your account's email address to {} was requested. It takes effect once confirmed from the new address.\n\nIf this was not you, keep this address: {}/revert-email-change?token={}\nThen reset your password.",
+                new_email, config.app_base_url, revert_token
+            ),
+        })
+        .await
+        .trace_error()?;
+
+    Ok(accept().with_message("A confirmation link has been sent to the new address"))
+}
+
+/// Swap the account to the new address. Following the link proves the new address is
+/// reachable, so it counts as verified.
+pub async fn confirm_email_change(
+    args: ConfirmEmailChangeArgs,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    let mut conn = cache.conn();
+
+    // `GETDEL` makes the token single-use.
+    let record = conn
+        .get_del(confirm_token_key(&opaque_token::digest(&args.token)))
+        .await
+        .trace_error()?;
+
+    let Some(record) = record else {
+        return Err(reject(400, "Invalid or expired confirmation token"));
+    };
+
+    let record: EmailChangeRecord = serde_json::from_str(&record)?;
+
+    let mut trx = repo.pool().begin().await?;
+
+    // Somebody may have registered the address since the change was requested.
+    if email_taken(&mut *trx, &record.new_email, &record.user_id).await? {
+        return Err(reject(409, "Email already registered"));
+    }
+
+    let result = query(
+        r#"
+        UPDATE t_user
+        SET f_email = $3, f_email_verified_at = now(),
+            f_updated_at = now(), f_version = f_version + 1
+        WHERE f_id = $1 AND f_email = $2
+    "#,
+    )
+    .bind(&record.user_id)
+    .bind(&record.old_email)
+    .bind(&record.new_email)
+    .execute(&mut *trx)
+    .await;
+
+    // A registration can still take the address between the check above and the update.
+    if let Err(err) = &result
+        && is_unique_violation(err)
+    {
+        return Err(reject(409, "Email already registered"));
+    }
+
+    let result = result.trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(400, "Invalid or expired confirmation token"));
+    }
+
+    record_audit(
+        &mut *trx,
+        AuditEntry {
+            action: EMAIL_CHANGED,
+            user_id: Some(&record.user_id),
+            detail: format!("{} -> {}", record.old_email, record.new_email),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_message("Email changed"))
+}
+
+/// Take the account back to its former address, or cancel the change if it was not
+/// confirmed yet. Whoever asked for the change knew the password, so every session ends.
+pub async fn revert_email_change(
+    args: RevertEmailChangeArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    let mut conn = cache.conn();
+
+    let record = conn
+        .get_del(revert_token_key(&opaque_token::digest(&args.token)))
+        .await
+        .trace_error()?;
+
+    let Some(record) = record else {
+        return Err(reject(400, "Invalid or expired revert token"));
+    };
+
+    let record: EmailRevertRecord = serde_json::from_str(&record)?;
+
+    conn.del(confirm_token_key(&record.confirm_digest))
+        .await
+        .trace_error()?;
+
+    let mut trx = repo.pool().begin().await?;
+
+    if email_taken(&mut *trx, &record.old_email, &record.user_id).await? {
+        return Err(reject(
+            409,
+            "The former email now belongs to another account",
+        ));
+    }
+
+    // Matches nothing when the change was never confirmed, which the deletion above cancelled.
+    // The link was mailed to the former address, so following it proves ownership.
+    let result = query(
+        r#"
+        UPDATE t_user
+        SET f_email = $3, f_email_verified_at = now(),
+            f_updated_at = now(), f_version = f_version + 1
+        WHERE f_id = $1 AND f_email = $2
+    "#,
+    )
+    .bind(&record.user_id)
+    .bind(&record.new_email)
+    .bind(&record.old_email)
+    .execute(&mut *trx)
+    .await;
+
+    if let Err(err) = &result
+        && is_unique_violation(err)
+    {
+        return Err(reject(
+            409,
+            "The former email now belongs to another account",
+        ));
+    }
+
+    result.trace_error()?;
+
+    record_audit(
+        &mut *trx,
+        AuditEntry {
+            action: EMAIL_CHANGE_REVERTED,
+            user_id: Some(&record.user_id),
+            detail: format!("{} -> {}", record.new_email, record.old_email),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    trx.commit().await?;
+
+    revoke_all_tokens(&record.user_id, config, cache).await?;
+
+    Ok(accept().with_message("Email change reverted, reset your password if it was not you"))
+}
@@ -0,0 +1,352 @@
+use std::net::IpAddr;
+
+use sqlx::{query, query_as, query_scalar};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    cache::Cache,
+    config::{AppConfig, EmailVerificationPolicy},
+    jwt_codec::JwtCodec,
+    mailer::{Mail, Mailer},
+    model::{
+        invitation::{
+            AcceptInvitationArgs, AcceptInvitationReply, CreateInvitationArgs, InvitationReply,
+            ListInvitationsReply,
+        },
+        organization::OrgRole,
+    },
+    opaque_token,
+    password_hasher::PasswordHasher,
+    password_policy::PasswordPolicy,
+    permission,
+    repo::{
+        Repo,
+        invitation::{BaseInvitation, ClaimedInvitation},
+    },
+    result_trace::ResultTrace as _,
+    service::{
+        account::is_purgeable,
+        auth::{
+            assign_role, complete_login, generate_password_hash, register_new_user,
+            select_user_secrets, verify_password,
+        },
+        lockout::{check_login_allowed, clear_login_failures, record_login_failure},
+        organization::{insert_membership, parse_role, require_role, select_member_role},
+        password::check_password_policy,
+        result::{InterResult, ServiceResult, ServiceValue, accept, reject},
+    },
+};
+
+fn invitation_reply(row: BaseInvitation) -> InterResult<InvitationReply> {
+    Ok(InvitationReply {
+        invitation_id: row.f_id,
+        email: row.f_email,
+        role: parse_role(&row.f_role)?,
+        invited_by: row.f_invited_by,
+        created_at: row.f_created_at,
+        expires_at: row.f_expires_at,
+    })
+}
+
+pub async fn create_invitation(
+    org_id: &str,
+    user_id: &str,
+    role: OrgRole,
+    args: CreateInvitationArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    mailer: &Mailer,
+) -> ServiceResult<InvitationReply> {
+    require_role(role, OrgRole::Admin)?;
+
+    if args.role == OrgRole::Owner && role != OrgRole::Owner {
+        return Err(reject(403, "Only owners can invite owners"));
+    }
+
+    let email = args.email.trim();
+
+    if !email.contains('@') {
+        return Err(reject(400, "Invalid email"));
+    }
+
+    let token = opaque_token::generate();
+    let expires_at = OffsetDateTime::now_utc() + Duration::seconds(config.invitation_exp_seconds);
+
+    let mut trx = repo.pool().begin().await?;
+
+    let already_member: Option<i32> = query_scalar(
+        r#"
+        SELECT 1
+        FROM t_membership m
+        JOIN t_user u ON u.f_id = m.f_user_id
+        WHERE m.f_org_id = $1 AND lower(u.f_email) = lower($2)
+    "#,
+    )
+    .bind(org_id)
+    .bind(email)
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    if already_member.is_some() {
+        return Err(reject(409, "Already a member of this organization"));
+    }
+
+    // A new invitation supersedes any pending one for the same email.
+    query(
+        r#"
+        UPDATE t_invitation
+        SET f_revoked_at = now()
+        WHERE f_org_id = $1 AND lower(f_email) = lower($2)
+          AND f_accepted_at IS NULL AND f_revoked_at IS NULL
+    "#,
+    )
+    .bind(org_id)
+    .bind(email)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let row: BaseInvitation = query_as(
+        r#"
+        INSERT INTO t_invitation (f_id, f_org_id, f_email, f_role, f_token_hash, f_invited_by, f_expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING f_id, f_email, f_role, f_invited_by, f_created_at, f_expires_at
+    "#,
+    )
+    .bind(Uuid::now_v7().to_string())
+    .bind(org_id)
+    .bind(email)
+    .bind(args.role.as_str())
+    .bind(opaque_token::digest(&token))
+    .bind(user_id)
+    .bind(expires_at)
+    .fetch_one(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let org_name: String = query_scalar("SELECT f_name FROM t_organization WHERE f_id = $1")
+        .bind(org_id)
+        .fetch_one(&mut *trx)
+        .await
+        .trace_error()?;
+
+    trx.commit().await?;
+
+    mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: format!("You are invited to join {}", org_name),
+            body: format!(
+                "You have been invited to join {} as {}.\n\nAccept the invitation: {}/accept-invitation?token={}\n\nThe link expires on {}.",
+                org_name,
+                args.role.as_str(),
+                config.app_base_url,
+                token,
+                expires_at
+            ),
+        })
+        .await
+        .trace_error()?;
+
+    Ok(accept().with_code(201).with_data(invitation_reply(row)?))
+}
+
+pub async fn list_invitations(
+    org_id: &str,
+    role: OrgRole,
+    repo: &Repo,
+) -> ServiceResult<ListInvitationsReply> {
+    require_role(role, OrgRole::Admin)?;
+
+    let rows: Vec<BaseInvitation> = query_as(
+        r#"
+        SELECT f_id, f_email, f_role, f_invited_by, f_created_at, f_expires_at
+        FROM t_invitation
+        WHERE f_org_id = $1
+          AND f_accepted_at IS NULL AND f_revoked_at IS NULL
+          AND f_expires_at > now()
+        ORDER BY f_created_at
+    "#,
+    )
+    .bind(org_id)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    let invitations = rows
+        .into_iter()
+        .map(invitation_reply)
+        .collect::<InterResult<_>>()?;
+
+    Ok(accept().with_data(ListInvitationsReply { invitations }))
+}
+
+pub async fn revoke_invitation(
+    org_id: &str,
+    role: OrgRole,
+    invitation_id: &str,
+    repo: &Repo,
+) -> ServiceResult<()> {
+    require_role(role, OrgRole::Admin)?;
+
+    let result = query(
+        r#"
+        UPDATE t_invitation
+        SET f_revoked_at = now()
+        WHERE f_id = $1 AND f_org_id = $2
+          AND f_accepted_at IS NULL AND f_revoked_at IS NULL
+    "#,
+    )
+    .bind(invitation_id)
+    .bind(org_id)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(404, "Invitation not found"));
+    }
+
+    Ok(accept().with_message("Invitation revoked"))
+}
+
+/// Accept an invitation, signing in to the account of the invited email or creating it.
+/// Holding the token proves ownership of the email, so no further verification is needed
+/// for new accounts. Signing in to an existing account is a login like any other.
+#[allow(clippy::too_many_arguments)]
+pub async fn accept_invitation(
+    args: AcceptInvitationArgs,
+    client_ip: IpAddr,
+    config: &AppConfig,
+    password_policy: &PasswordPolicy,
+    jwt_codec: &JwtCodec,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<AcceptInvitationReply> {
+    let mut trx = repo.pool().begin().await?;
+
+    // Claiming the invitation in the same statement as checking it makes it single-use.
+    let claimed: Option<ClaimedInvitation> = query_as(
+        r#"
+        UPDATE t_invitation
+        SET f_accepted_at = now()
+        WHERE f_token_hash = $1
+          AND f_accepted_at IS NULL AND f_revoked_at IS NULL
+          AND f_expires_at > now()
+        RETURNING f_org_id, f_email, f_role
+    "#,
+    )
+    .bind(opaque_token::digest(&args.token))
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let Some(invitation) = claimed else {
+        return Err(reject(404, "Invalid or expired invitation"));
+    };
+
+    let user_id = match select_user_secrets(&mut *trx, &invitation.f_email).await? {
+        // Past its grace period the account is as good as gone, as in a login.
+        Some(secrets) if is_purgeable(secrets.f_deleted_at, config) => {
+            return Err(reject(401, "Account has been deleted"));
+        }
+        Some(secrets) => {
+            check_login_allowed(&invitation.f_email, client_ip, config, cache).await?;
+
+            if !(verify_password(&args.password, &secrets.f_password_hash, password_hasher).await?)
+            {
+                let user_id = Some(secrets.f_id.as_str());
+
+                record_login_failure(&invitation.f_email, user_id, client_ip, config, repo, cache)
+                    .await?;
+
+                return Err(reject(401, "Invalid email or password"));
+            }
+
+            clear_login_failures(&invitation.f_email, cache).await?;
+
+            if config.email_verification_policy == EmailVerificationPolicy::BlockLogin
+                && secrets.f_email_verified_at.is_none()
+            {
+                return Err(reject(403, "Email address is not verified"));
+            }
+
+            secrets.f_id
+        }
+        None => {
+            let Some(nickname) = args.nickname else {
+                return Err(reject(400, "Nickname is required to create an account"));
+            };
+
+            check_password_policy(
+                "password",
+                &args.password,
+                &[&invitation.f_email, &nickname],
+                password_policy,
+            )
+            .await?;
+
+            let user_id = Uuid::now_v7().to_string();
+
+            let password_hash = generate_password_hash(&args.password, password_hasher).await?;
+
+            register_new_user(
+                &mut *trx,
+                &user_id,
+                &invitation.f_email,
+                &nickname,
+                &password_hash,
+                // The invitation token was mailed to this address.
+                true,
+            )
+            .await?;
+
+            assign_role(&mut *trx, &user_id, permission::DEFAULT_ROLE).await?;
+
+            user_id
+        }
+    };
+
+    // Someone who joined in the meantime keeps the role they already have.
+    if select_member_role(&mut *trx, &invitation.f_org_id, &user_id)
+        .await?
+        .is_none()
+    {
+        let role = parse_role(&invitation.f_role)?;
+
+        insert_membership(&mut *trx, &invitation.f_org_id, &user_id, role).await?;
+    }
+
+    trx.commit().await?;
+
+    let ServiceValue {
+        code,
+        message,
+        data,
+    } = complete_login(
+        user_id,
+        false,
+        Some(&invitation.f_org_id),
+        config,
+        jwt_codec,
+        repo,
+        cache,
+    )
+    .await?;
+
+    Ok(ServiceValue {
+        code,
+        message,
+        data: data.map(|login| AcceptInvitationReply {
+            user_id: login.user_id,
+            org_id: invitation.f_org_id,
+            token: login.token,
+            refresh_token: login.refresh_token,
+            expires_in: login.expires_in,
+            mfa_token: login.mfa_token,
+        }),
+    })
+}
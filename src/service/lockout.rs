@@ -0,0 +1,331 @@
+use std::net::IpAddr;
+
+use redis::{AsyncTypedCommands as _, cmd};
+use sqlx::query_scalar;
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::UserClaims,
+    repo::Repo,
+    result_trace::ResultTrace as _,
+    service::{
+        audit::{ACCOUNT_LOCKED, ACCOUNT_UNLOCKED, AuditEntry, record_audit},
+        rate_limit::seconds_left,
+        result::{InterResult, ServiceResult, accept, reject, reject_retry_after},
+    },
+};
+
+/// Emails are matched case-insensitively, so that varying the case buys no extra guesses
+/// or mails.
+pub(super) fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn email_failures_key(email: &str) -> String {
+    format!("login:failures:email:{}", normalize_email(email))
+}
+
+fn ip_failures_key(ip: IpAddr) -> String {
+    format!("login:failures:ip:{}", ip)
+}
+
+/// Key present while the next attempt for an email has to wait.
+fn email_delay_key(email: &str) -> String {
+    format!("login:delay:{}", normalize_email(email))
+}
+
+/// Key present while an email is locked out.
+fn email_lock_key(email: &str) -> String {
+    format!("login:lock:{}", normalize_email(email))
+}
+
+/// What the failures of an email within the window call for.
+#[derive(Debug, PartialEq, Eq)]
+enum FailurePenalty {
+    None,
+    /// Seconds to wait before the next attempt.
+    Delay(u64),
+    Lock,
+}
+
+/// Past `login_delay_after_failures` every further failure doubles the wait, up to
+/// `login_delay_max_seconds`, and `login_lockout_threshold` failures lock the email out.
+fn failure_penalty(failures: u64, config: &AppConfig) -> FailurePenalty {
+    if failures >= config.login_lockout_threshold {
+        return FailurePenalty::Lock;
+    }
+
+    if failures < config.login_delay_after_failures {
+        return FailurePenalty::None;
+    }
+
+    let doublings = (failures - config.login_delay_after_failures).min(32) as u32;
+
+    let delay = config
+        .login_delay_base_seconds
+        .saturating_mul(1 << doublings)
+        .min(config.login_delay_max_seconds);
+
+    FailurePenalty::Delay(delay)
+}
+
+/// Refuse a login attempt while the email is locked or delayed, or the client
+/// address has failed too often. Checked before the password, so that a locked
+/// account gives nothing away even for the right password.
+pub(super) async fn check_login_allowed(
+    email: &str,
+    client_ip: IpAddr,
+    config: &AppConfig,
+    cache: &Cache,
+) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    if let Some(retry_after) = seconds_left(&mut conn, &email_lock_key(email)).await? {
+        return Err(reject_retry_after(
+            423,
+            "Account temporarily locked after too many failed logins",
+            retry_after,
+        ));
+    }
+
+    if let Some(retry_after) = seconds_left(&mut conn, &email_delay_key(email)).await? {
+        return Err(reject_retry_after(
+            429,
+            "Too many failed logins, try again later",
+            retry_after,
+        ));
+    }
+
+    let ip_key = ip_failures_key(client_ip);
+
+    let ip_failures: u64 = conn
+        .get(&ip_key)
+        .await
+        .trace_error()?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    if ip_failures >= config.login_ip_failure_limit {
+        let retry_after = seconds_left(&mut conn, &ip_key)
+            .await?
+            .unwrap_or(config.login_failure_window_seconds);
+
+        return Err(reject_retry_after(
+            429,
+            "Too many failed logins, try again later",
+            retry_after,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Count a failed login, delaying or locking the email as `failure_penalty` says.
+/// Unknown emails are counted alike, so that lockouts do not reveal which emails
+/// are registered.
+pub(super) async fn record_login_failure(
+    email: &str,
+    user_id: Option<&str>,
+    client_ip: IpAddr,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    let mut failures = 0;
+
+    for key in [email_failures_key(email), ip_failures_key(client_ip)] {
+        let hits = conn.incr(&key, 1).await.trace_error()?;
+
+        // `NX` keeps the window anchored to the first failure.
+        let _: bool = cmd("EXPIRE")
+            .arg(&key)
+            .arg(config.login_failure_window_seconds)
+            .arg("NX")
+            .query_async(&mut conn)
+            .await
+            .trace_error()?;
+
+        if failures == 0 {
+            failures = hits as u64;
+        }
+    }
+
+    match failure_penalty(failures, config) {
+        FailurePenalty::Lock => {
+            conn.set_ex(email_lock_key(email), 1, config.login_lockout_seconds)
+                .await
+                .trace_error()?;
+
+            // The lockout starts a fresh count.
+            conn.del(&[email_failures_key(email), email_delay_key(email)])
+                .await
+                .trace_error()?;
+
+            tracing::warn!(
+                "Login for {} locked after {} failures, last from {}",
+                email,
+                failures,
+                client_ip
+            );
+
+            record_audit(
+                repo.pool(),
+                AuditEntry {
+                    action: ACCOUNT_LOCKED,
+                    user_id,
+                    ip: Some(client_ip.to_string()),
+                    detail: format!(
+                        "{} failed logins, locked for {} seconds",
+                        failures, config.login_lockout_seconds
+                    ),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+        FailurePenalty::Delay(delay) => {
+            conn.set_ex(email_delay_key(email), 1, delay)
+                .await
+                .trace_error()?;
+        }
+        FailurePenalty::None => {}
+    }
+
+    Ok(())
+}
+
+/// Forget the failures of an email after a successful login.
+/// The client address keeps its count, one good account does not clear a spraying client.
+pub(super) async fn clear_login_failures(email: &str, cache: &Cache) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    conn.del(&[email_failures_key(email), email_delay_key(email)])
+        .await
+        .trace_error()?;
+
+    Ok(())
+}
+
+/// Lift the lockout and the failure count of a user's email.
+pub async fn unlock_account(
+    claims: &UserClaims,
+    user_id: &str,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    let email: Option<String> = query_scalar(
+        r#"
+        SELECT f_email
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(user_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(email) = email else {
+        return Err(reject(404, "User not found"));
+    };
+
+    let mut conn = cache.conn();
+
+    let removed = conn
+        .del(&[
+            email_lock_key(&email),
+            email_failures_key(&email),
+            email_delay_key(&email),
+        ])
+        .await
+        .trace_error()?;
+
+    record_audit(
+        repo.pool(),
+        AuditEntry {
+            action: ACCOUNT_UNLOCKED,
+            user_id: Some(user_id),
+            actor_id: Some(&claims.sub),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    if removed == 0 {
+        return Ok(accept().with_message("Account was not locked"));
+    }
+
+    Ok(accept().with_message("Account unlocked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Delays from the third failure on, one second doubling up to a minute, and a
+    /// lockout at the tenth.
+    fn config() -> AppConfig {
+        let mut config = AppConfig::try_from_file(None).expect("app_config.json");
+
+        config.login_delay_after_failures = 3;
+        config.login_delay_base_seconds = 1;
+        config.login_delay_max_seconds = 60;
+        config.login_lockout_threshold = 10;
+
+        config
+    }
+
+    #[test]
+    fn tolerates_failures_below_the_delay_threshold() {
+        let config = config();
+
+        assert_eq!(failure_penalty(1, &config), FailurePenalty::None);
+        assert_eq!(failure_penalty(2, &config), FailurePenalty::None);
+    }
+
+    #[test]
+    fn doubles_the_delay_with_every_failure() {
+        let config = config();
+
+        assert_eq!(failure_penalty(3, &config), FailurePenalty::Delay(1));
+        assert_eq!(failure_penalty(4, &config), FailurePenalty::Delay(2));
+        assert_eq!(failure_penalty(5, &config), FailurePenalty::Delay(4));
+        assert_eq!(failure_penalty(8, &config), FailurePenalty::Delay(32));
+    }
+
+    #[test]
+    fn caps_the_delay() {
+        let config = config();
+
+        // 64 seconds uncapped.
+        assert_eq!(failure_penalty(9, &config), FailurePenalty::Delay(60));
+    }
+
+    #[test]
+    fn locks_at_the_threshold() {
+        let config = config();
+
+        assert_eq!(failure_penalty(10, &config), FailurePenalty::Lock);
+        assert_eq!(failure_penalty(11, &config), FailurePenalty::Lock);
+    }
+
+    #[test]
+    fn large_counts_neither_overflow_nor_escape_the_cap() {
+        let mut config = config();
+        config.login_lockout_threshold = u64::MAX;
+
+        assert_eq!(failure_penalty(40, &config), FailurePenalty::Delay(60));
+        assert_eq!(
+            failure_penalty(u64::MAX - 1, &config),
+            FailurePenalty::Delay(60)
+        );
+
+        config.login_delay_base_seconds = u64::MAX / 2;
+        config.login_delay_max_seconds = u64::MAX;
+
+        assert_eq!(failure_penalty(5, &config), FailurePenalty::Delay(u64::MAX));
+    }
+}
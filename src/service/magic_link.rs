@@ -0,0 +1,142 @@
+use std::net::IpAddr;
+
+use redis::AsyncTypedCommands as _;
+use sqlx::{query, query_scalar};
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::JwtCodec,
+    mailer::{Mail, Mailer},
+    model::user::{ConsumeMagicLinkArgs, LoginUserReply, MagicLinkArgs},
+    opaque_token,
+    repo::Repo,
+    result_trace::ResultTrace as _,
+    service::{
+        auth::complete_login,
+        rate_limit::hit_rate_limit,
+        result::{ServiceResult, accept, reject},
+    },
+};
+
+fn magic_link_key(digest: &str) -> String {
+    format!("magic:login:{}", digest)
+}
+
+pub async fn request_magic_link(
+    args: MagicLinkArgs,
+    client_ip: IpAddr,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> ServiceResult<()> {
+    if !config.magic_link_enabled {
+        return Err(reject(404, "Magic link login is disabled"));
+    }
+
+    // The address limit keeps one client from mailing many inboxes,
+    // the email limit keeps many clients from flooding one inbox.
+    hit_rate_limit(
+        cache,
+        &format!("magic-link:ip:{}", client_ip),
+        config.magic_link_ip_limit,
+        config.magic_link_window_seconds,
+    )
+    .await?;
+
+    hit_rate_limit(
+        cache,
+        &format!("magic-link:email:{}", args.email.trim().to_lowercase()),
+        config.magic_link_email_limit,
+        config.magic_link_window_seconds,
+    )
+    .await?;
+
+    let user_id: Option<String> = query_scalar(
+        r#"
+        SELECT f_id
+        FROM t_user
+        WHERE lower(f_email) = lower($1)
+    "#,
+    )
+    .bind(&args.email)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    if let Some(user_id) = user_id {
+        let token = opaque_token::generate();
+
+        let mut conn = cache.conn();
+
+        conn.set_ex(
+            magic_link_key(&opaque_token::digest(&token)),
+            &user_id,
+            config.magic_link_exp_seconds,
+        )
+        .await
+        .trace_error()?;
+
+        mailer
+            .send(Mail {
+                to: args.email.clone(),
+                subject: "Your login link".to_string(),
+                body: format!(
+                    "Log in: {}/magic-link?token={}\n\nThe link works once and expires in {} minutes. If you did not ask for it, ignore this mail.",
+                    config.app_base_url,
+                    token,
+                    config.magic_link_exp_seconds / 60
+                ),
+            })
+            .await
+            .trace_error()?;
+    }
+
+    // The reply never tells whether the email is registered.
+    Ok(accept().with_message("If the email is registered, a login link has been sent"))
+}
+
+pub async fn consume_magic_link(
+    args: ConsumeMagicLinkArgs,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<LoginUserReply> {
+    if !config.magic_link_enabled {
+        return Err(reject(404, "Magic link login is disabled"));
+    }
+
+    let mut conn = cache.conn();
+
+    // `GETDEL` makes the link single-use.
+    let user_id = conn
+        .get_del(magic_link_key(&opaque_token::digest(&args.token)))
+        .await
+        .trace_error()?;
+
+    let Some(user_id) = user_id else {
+        return Err(reject(400, "Invalid or expired login link"));
+    };
+
+    // The link was mailed to the address, so following it also proves ownership.
+    let result = query(
+        r#"
+        UPDATE t_user
+        SET f_email_verified_at = COALESCE(f_email_verified_at, now())
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&user_id)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(400, "Invalid or expired login link"));
+    }
+
+    // The link stands for the first factor, a TOTP second factor still applies.
+    complete_login(user_id, false, None, config, jwt_codec, repo, cache).await
+}
@@ -0,0 +1,561 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+use redis::AsyncTypedCommands as _;
+use sqlx::{Executor, Postgres, query, query_as, query_scalar};
+use time::OffsetDateTime;
+use totp_rs::{Algorithm, TOTP};
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::{JwtCodec, UserClaims},
+    model::{
+        mfa::{ConfirmTotpArgs, ConfirmTotpReply, DisableTotpArgs, EnrollTotpReply, VerifyMfaArgs},
+        user::LoginUserReply,
+    },
+    opaque_token,
+    password_hasher::PasswordHasher,
+    repo::{Repo, mfa::UserTotp},
+    result_trace::ResultTrace as _,
+    secret_box::SecretBox,
+    service::{
+        auth::{complete_login, verify_password},
+        rate_limit::hit_rate_limit,
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// Seconds covered by one TOTP code, as recommended by RFC 6238.
+const TOTP_STEP: u64 = 30;
+
+/// Number of random bytes in a TOTP secret (160 bits, the HMAC-SHA1 block output).
+const TOTP_SECRET_BYTES: usize = 20;
+
+/// Number of recovery codes handed out at confirmation.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+fn mfa_pending_key(digest: &str) -> String {
+    format!("mfa:pending:{}", digest)
+}
+
+fn totp(secret: Vec<u8>, account_name: String, config: &AppConfig) -> InterResult<TOTP> {
+    // No skew here, `matching_step` walks the neighbouring steps itself.
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP,
+        secret,
+        Some(config.mfa_issuer.clone()),
+        account_name,
+    )?;
+
+    Ok(totp)
+}
+
+/// Find the time step `code` belongs to at `unix_time`, tolerating one step of clock drift
+/// either way. Steps up to `last_step` were used already, a code never counts twice.
+fn matching_step(totp: &TOTP, code: &str, unix_time: u64, last_step: i64) -> Option<i64> {
+    let now = unix_time / TOTP_STEP;
+
+    [now.saturating_sub(1), now, now + 1]
+        .into_iter()
+        .filter(|&step| step as i64 > last_step)
+        .find(|step| totp.check(code, step * TOTP_STEP))
+        .map(|step| step as i64)
+}
+
+fn unix_now() -> u64 {
+    OffsetDateTime::now_utc().unix_timestamp() as u64
+}
+
+/// Recovery codes are compared without separators and case, as users tend to retype them.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 8];
+
+    OsRng.fill_bytes(&mut bytes);
+
+    let code = hex::encode(bytes);
+
+    format!(
+        "{}-{}-{}-{}",
+        &code[..4],
+        &code[4..8],
+        &code[8..12],
+        &code[12..]
+    )
+}
+
+/// Whether the user has a confirmed second factor.
+pub(super) async fn is_mfa_enabled<'c, E>(exec: E, user_id: &str) -> InterResult<bool>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let enabled: bool = query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM t_user_totp
+            WHERE f_user_id = $1 AND f_confirmed_at IS NOT NULL
+        )
+    "#,
+    )
+    .bind(user_id)
+    .fetch_one(exec)
+    .await
+    .trace_error()?;
+
+    Ok(enabled)
+}
+
+/// Hand out the short-lived token that `verify_mfa` exchanges for real tokens.
+pub(super) async fn start_mfa_challenge(
+    user_id: &str,
+    config: &AppConfig,
+    cache: &Cache,
+) -> InterResult<String> {
+    let mfa_token = opaque_token::generate();
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        mfa_pending_key(&opaque_token::digest(&mfa_token)),
+        user_id,
+        config.mfa_pending_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    Ok(mfa_token)
+}
+
+async fn select_totp<'c, E>(exec: E, user_id: &str) -> InterResult<Option<UserTotp>>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let totp: Option<UserTotp> = query_as(
+        r#"
+        SELECT f_secret, f_last_step, f_confirmed_at
+        FROM t_user_totp
+        WHERE f_user_id = $1
+    "#,
+    )
+    .bind(user_id)
+    .fetch_optional(exec)
+    .await
+    .trace_error()?;
+
+    Ok(totp)
+}
+
+/// Record `step` as used, failing when it is not newer than the last accepted one.
+async fn advance_totp_step<'c, E>(exec: E, user_id: &str, step: i64) -> InterResult<bool>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let result = query(
+        r#"
+        UPDATE t_user_totp
+        SET f_last_step = $2
+        WHERE f_user_id = $1 AND f_last_step < $2
+    "#,
+    )
+    .bind(user_id)
+    .bind(step)
+    .execute(exec)
+    .await
+    .trace_error()?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn enroll_totp(
+    claims: &UserClaims,
+    config: &AppConfig,
+    repo: &Repo,
+    secret_box: &SecretBox,
+) -> ServiceResult<EnrollTotpReply> {
+    let mut trx = repo.pool().begin().await?;
+
+    if let Some(existing) = select_totp(&mut *trx, &claims.sub).await?
+        && existing.f_confirmed_at.is_some()
+    {
+        return Err(reject(409, "Two-factor authentication is already enabled"));
+    }
+
+    let email: Option<String> = query_scalar(
+        r#"
+        SELECT f_email
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let Some(email) = email else {
+        return Err(reject(404, "User not found"));
+    };
+
+    let mut secret = vec![0u8; TOTP_SECRET_BYTES];
+
+    OsRng.fill_bytes(&mut secret);
+
+    let sealed = secret_box.seal(&secret).trace_error()?;
+
+    let totp = totp(secret, email, config)?;
+
+    // Enrolling again before confirming replaces the pending secret.
+    query(
+        r#"
+        INSERT INTO t_user_totp (f_user_id, f_secret)
+        VALUES ($1, $2)
+        ON CONFLICT (f_user_id) DO UPDATE
+        SET f_secret = EXCLUDED.f_secret, f_last_step = 0, f_created_at = now()
+    "#,
+    )
+    .bind(&claims.sub)
+    .bind(&sealed)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_data(EnrollTotpReply {
+        secret: totp.get_secret_base32(),
+        otpauth_uri: totp.get_url(),
+    }))
+}
+
+pub async fn confirm_totp(
+    claims: &UserClaims,
+    args: ConfirmTotpArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    secret_box: &SecretBox,
+) -> ServiceResult<ConfirmTotpReply> {
+    let mut trx = repo.pool().begin().await?;
+
+    let Some(existing) = select_totp(&mut *trx, &claims.sub).await? else {
+        return Err(reject(404, "No pending two-factor enrollment"));
+    };
+
+    if existing.f_confirmed_at.is_some() {
+        return Err(reject(409, "Two-factor authentication is already enabled"));
+    }
+
+    let secret = secret_box.open(&existing.f_secret).trace_error()?;
+
+    let totp = totp(secret, claims.sub.clone(), config)?;
+
+    let Some(step) = matching_step(&totp, args.code.trim(), unix_now(), existing.f_last_step)
+    else {
+        return Err(reject(400, "Invalid code"));
+    };
+
+    query(
+        r#"
+        UPDATE t_user_totp
+        SET f_confirmed_at = now(), f_last_step = $2
+        WHERE f_user_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .bind(step)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    query(
+        r#"
+        DELETE FROM t_recovery_code
+        WHERE f_user_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| opaque_token::digest(&normalize_recovery_code(code)))
+        .collect();
+
+    query(
+        r#"
+        INSERT INTO t_recovery_code (f_user_id, f_code_hash)
+        SELECT $1, UNNEST($2::TEXT[])
+    "#,
+    )
+    .bind(&claims.sub)
+    .bind(&hashes)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    trx.commit().await?;
+
+    Ok(accept()
+        .with_message("Two-factor authentication enabled")
+        .with_data(ConfirmTotpReply { recovery_codes }))
+}
+
+pub async fn disable_totp(
+    claims: &UserClaims,
+    args: DisableTotpArgs,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+) -> ServiceResult<()> {
+    let password_hash: Option<String> = query_scalar(
+        r#"
+        SELECT f_password_hash
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(password_hash) = password_hash else {
+        return Err(reject(404, "User not found"));
+    };
+
+    // Verified before the transaction, hashing is slow.
+    if !(verify_password(&args.password, &password_hash, password_hasher).await?) {
+        return Err(reject(401, "Password is incorrect"));
+    }
+
+    let mut trx = repo.pool().begin().await?;
+
+    let unchanged: Option<i32> = query_scalar(
+        r#"
+        SELECT 1
+        FROM t_user
+        WHERE f_id = $1 AND f_password_hash = $2
+        FOR UPDATE
+    "#,
+    )
+    .bind(&claims.sub)
+    .bind(&password_hash)
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    if unchanged.is_none() {
+        return Err(reject(409, "Password changed meanwhile, try again"));
+    }
+
+    query(
+        r#"
+        DELETE FROM t_user_totp
+        WHERE f_user_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    query(
+        r#"
+        DELETE FROM t_recovery_code
+        WHERE f_user_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_message("Two-factor authentication disabled"))
+}
+
+pub async fn verify_mfa(
+    args: VerifyMfaArgs,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+    secret_box: &SecretBox,
+) -> ServiceResult<LoginUserReply> {
+    let pending_key = mfa_pending_key(&opaque_token::digest(&args.mfa_token));
+
+    // Six-digit codes are guessable, so a pending token only allows a few attempts.
+    hit_rate_limit(
+        cache,
+        &pending_key,
+        config.mfa_verify_attempt_limit,
+        config.mfa_pending_exp_seconds,
+    )
+    .await?;
+
+    let mut conn = cache.conn();
+
+    let Some(user_id) = conn.get(&pending_key).await.trace_error()? else {
+        return Err(reject(401, "Invalid or expired MFA token"));
+    };
+
+    let code = args.code.trim();
+
+    let accepted = match select_totp(repo.pool(), &user_id).await? {
+        Some(UserTotp {
+            f_secret,
+            f_last_step,
+            f_confirmed_at: Some(_),
+        }) if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) => {
+            let secret = secret_box.open(&f_secret).trace_error()?;
+
+            let totp = totp(secret, user_id.clone(), config)?;
+
+            // Checked again by the update, against a concurrent verification.
+            match matching_step(&totp, code, unix_now(), f_last_step) {
+                Some(step) => advance_totp_step(repo.pool(), &user_id, step).await?,
+                None => false,
+            }
+        }
+        Some(UserTotp {
+            f_confirmed_at: Some(_),
+            ..
+        }) => {
+            let result = query(
+                r#"
+                UPDATE t_recovery_code
+                SET f_used_at = now()
+                WHERE f_user_id = $1 AND f_code_hash = $2 AND f_used_at IS NULL
+            "#,
+            )
+            .bind(&user_id)
+            .bind(opaque_token::digest(&normalize_recovery_code(code)))
+            .execute(repo.pool())
+            .await
+            .trace_error()?;
+
+            if result.rows_affected() > 0 {
+                tracing::info!("User {} signed in with a recovery code", user_id);
+            }
+
+            result.rows_affected() > 0
+        }
+        // MFA was disabled since the password step, the user has to log in again.
+        _ => false,
+    };
+
+    if !accepted {
+        return Err(reject(401, "Invalid code"));
+    }
+
+    conn.del(&pending_key).await.trace_error()?;
+
+    complete_login(user_id, true, None, config, jwt_codec, repo, cache).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 seed of the RFC 6238 test vectors.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    /// RFC 6238 appendix B times with the SHA-1 codes, cut to our six digits.
+    const RFC_VECTORS: [(u64, &str); 6] = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    fn rfc_totp() -> TOTP {
+        let config = AppConfig::try_from_file(None).expect("app_config.json");
+
+        totp(RFC_SECRET.to_vec(), "user".to_string(), &config).unwrap()
+    }
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        let totp = rfc_totp();
+
+        for (time, code) in RFC_VECTORS {
+            assert_eq!(
+                matching_step(&totp, code, time, 0),
+                Some((time / TOTP_STEP) as i64),
+                "at {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn tolerates_one_step_of_drift_only() {
+        let totp = rfc_totp();
+
+        let (time, code) = RFC_VECTORS[3];
+        let step = (time / TOTP_STEP) as i64;
+
+        assert_eq!(matching_step(&totp, code, time - TOTP_STEP, 0), Some(step));
+        assert_eq!(matching_step(&totp, code, time + TOTP_STEP, 0), Some(step));
+        assert_eq!(matching_step(&totp, code, time - 2 * TOTP_STEP, 0), None);
+        assert_eq!(matching_step(&totp, code, time + 2 * TOTP_STEP, 0), None);
+    }
+
+    #[test]
+    fn rejects_a_replayed_step() {
+        let totp = rfc_totp();
+
+        let (time, code) = RFC_VECTORS[3];
+        let step = (time / TOTP_STEP) as i64;
+
+        assert_eq!(matching_step(&totp, code, time, step - 1), Some(step));
+        assert_eq!(matching_step(&totp, code, time, step), None);
+        assert_eq!(matching_step(&totp, code, time + TOTP_STEP, step), None);
+    }
+
+    #[test]
+    fn rejects_a_wrong_code() {
+        let totp = rfc_totp();
+
+        let (time, _) = RFC_VECTORS[3];
+
+        assert_eq!(matching_step(&totp, "000000", time, 0), None);
+    }
+
+    #[test]
+    fn normalizes_recovery_codes() {
+        assert_eq!(
+            normalize_recovery_code(" AbCd-1234 ef56_7890\n"),
+            "abcd1234ef567890"
+        );
+    }
+
+    #[test]
+    fn generated_recovery_codes_survive_normalization() {
+        let code = generate_recovery_code();
+
+        assert_eq!(code.len(), 19);
+        assert_eq!(code.matches('-').count(), 3);
+
+        let normalized = normalize_recovery_code(&code);
+
+        assert_eq!(normalized, code.replace('-', ""));
+        assert_eq!(normalize_recovery_code(&code.to_uppercase()), normalized);
+    }
+}
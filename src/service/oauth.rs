@@ -0,0 +1,768 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use redis::AsyncTypedCommands as _;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use sqlx::{query, query_as, query_scalar};
+use uuid::Uuid;
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::{JwtCodec, UserClaims},
+    model::oauth::{
+        CreateOAuthClientArgs, CreateOAuthClientReply, ListOAuthClientsReply, OAuthAuthorizeArgs,
+        OAuthAuthorizeReply, OAuthClientCredentials, OAuthClientReply, OAuthConsentArgs,
+        OAuthConsentReply, OAuthIntrospectReply, OAuthScopeReply, OAuthTokenArgs, OAuthTokenReply,
+        OAuthTokenTypeArgs,
+    },
+    opaque_token,
+    repo::{Repo, oauth::OAuthClient},
+    result_trace::ResultTrace as _,
+    scope,
+    service::{
+        auth::{is_token_revoked, revoke_access_token, user_claims},
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// What is stored in Redis for an authorization code until the client redeems it.
+#[derive(Debug, Serialize, Deserialize)]
+struct AuthorizationCodeRecord {
+    client_id: String,
+    user_id: String,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    code_challenge: String,
+}
+
+/// What is stored in Redis for a refresh token issued to a client.
+#[derive(Debug, Serialize, Deserialize)]
+struct OAuthRefreshRecord {
+    client_id: String,
+    user_id: String,
+    scopes: Vec<String>,
+}
+
+fn authorization_code_key(digest: &str) -> String {
+    format!("oauth:code:{}", digest)
+}
+
+fn oauth_refresh_key(digest: &str) -> String {
+    format!("oauth:refresh:{}", digest)
+}
+
+/// Set of the digests of refresh tokens issued to clients on behalf of a user.
+fn oauth_user_key(user_id: &str) -> String {
+    format!("oauth:user:{}", user_id)
+}
+
+fn client_reply(client: OAuthClient) -> OAuthClientReply {
+    OAuthClientReply {
+        client_id: client.f_id,
+        name: client.f_name,
+        redirect_uris: client.f_redirect_uris,
+        scopes: client.f_scopes,
+        confidential: client.f_secret_hash.is_some(),
+        created_at: client.f_created_at,
+    }
+}
+
+async fn select_client(repo: &Repo, client_id: &str) -> InterResult<Option<OAuthClient>> {
+    let client: Option<OAuthClient> = query_as(
+        r#"
+        SELECT f_id, f_name, f_secret_hash, f_redirect_uris, f_scopes, f_created_at
+        FROM t_oauth_client
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(client_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(client)
+}
+
+pub async fn create_client(
+    claims: &UserClaims,
+    mut args: CreateOAuthClientArgs,
+    repo: &Repo,
+) -> ServiceResult<CreateOAuthClientReply> {
+    if args.name.trim().is_empty() {
+        return Err(reject(400, "Client name is required"));
+    }
+
+    if args.redirect_uris.is_empty() {
+        return Err(reject(400, "At least one redirect URI is required"));
+    }
+
+    // RFC 6749 section 3.1.2: absolute URIs without a fragment, matched exactly later on.
+    for uri in &args.redirect_uris {
+        if Url::parse(uri).map_or(true, |url| url.fragment().is_some()) {
+            return Err(reject(400, format!("Invalid redirect URI {}", uri)));
+        }
+    }
+
+    args.scopes.sort();
+    args.scopes.dedup();
+
+    if args.scopes.is_empty() || !args.scopes.iter().all(|s| scope::is_known(s)) {
+        return Err(reject(400, "Unknown scope"));
+    }
+
+    let client_id = Uuid::now_v7().to_string();
+
+    let client_secret = args.confidential.then(opaque_token::generate);
+    let secret_hash = client_secret.as_deref().map(opaque_token::digest);
+
+    let client: OAuthClient = query_as(
+        r#"
+        INSERT INTO t_oauth_client (f_id, f_owner_id, f_name, f_secret_hash, f_redirect_uris, f_scopes)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING f_id, f_name, f_secret_hash, f_redirect_uris, f_scopes, f_created_at
+    "#,
+    )
+    .bind(&client_id)
+    .bind(&claims.sub)
+    .bind(args.name.trim())
+    .bind(&secret_hash)
+    .bind(&args.redirect_uris)
+    .bind(&args.scopes)
+    .fetch_one(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(accept().with_code(201).with_data(CreateOAuthClientReply {
+        client: client_reply(client),
+        client_secret,
+    }))
+}
+
+pub async fn list_clients(
+    claims: &UserClaims,
+    repo: &Repo,
+) -> ServiceResult<ListOAuthClientsReply> {
+    let clients: Vec<OAuthClient> = query_as(
+        r#"
+        SELECT f_id, f_name, f_secret_hash, f_redirect_uris, f_scopes, f_created_at
+        FROM t_oauth_client
+        WHERE f_owner_id = $1
+        ORDER BY f_created_at
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(accept().with_data(ListOAuthClientsReply {
+        clients: clients.into_iter().map(client_reply).collect(),
+    }))
+}
+
+/// Delete a client of the caller. Its refresh tokens stop working as the client
+/// no longer authenticates, access tokens already issued run out on their own.
+pub async fn delete_client(claims: &UserClaims, client_id: &str, repo: &Repo) -> ServiceResult<()> {
+    let deleted = query(
+        r#"
+        DELETE FROM t_oauth_client
+        WHERE f_id = $1 AND f_owner_id = $2
+    "#,
+    )
+    .bind(client_id)
+    .bind(&claims.sub)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(reject(404, "Client not found"));
+    }
+
+    Ok(accept().with_message("Client deleted"))
+}
+
+/// Check an authorization request and return the client along with the requested scopes.
+/// Errors are shown to the user instead of being sent to an unverified redirect URI.
+async fn validate_request(
+    args: &OAuthAuthorizeArgs,
+    repo: &Repo,
+) -> InterResult<(OAuthClient, Vec<String>)> {
+    if args.response_type != "code" {
+        return Err(reject(400, "Unsupported response type"));
+    }
+
+    // RFC 7636 section 4.2: 43 to 128 characters, the base64url SHA-256 is exactly 43.
+    if args.code_challenge_method != "S256" || args.code_challenge.len() != 43 {
+        return Err(reject(400, "PKCE with S256 is required"));
+    }
+
+    let Some(client) = select_client(repo, &args.client_id).await? else {
+        return Err(reject(400, "Unknown client"));
+    };
+
+    if !client.f_redirect_uris.contains(&args.redirect_uri) {
+        return Err(reject(
+            400,
+            "Redirect URI is not registered for this client",
+        ));
+    }
+
+    let scopes = scope::parse(&args.scope);
+
+    if scopes.is_empty() || !scopes.iter().all(|s| client.f_scopes.contains(s)) {
+        return Err(reject(400, "Invalid scope"));
+    }
+
+    Ok((client, scopes))
+}
+
+async fn select_consented_scopes(
+    repo: &Repo,
+    user_id: &str,
+    client_id: &str,
+) -> InterResult<Vec<String>> {
+    let scopes: Option<Vec<String>> = query_scalar(
+        r#"
+        SELECT f_scopes
+        FROM t_oauth_consent
+        WHERE f_user_id = $1 AND f_client_id = $2
+    "#,
+    )
+    .bind(user_id)
+    .bind(client_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(scopes.unwrap_or_default())
+}
+
+/// RFC 7636 `S256`: whether `code_verifier` hashes to the challenge of the request.
+fn pkce_matches(code_verifier: &str, code_challenge: &str) -> bool {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())) == code_challenge
+}
+
+/// Scopes of a refreshed token, `None` when `requested` asks for any the grant lacks.
+/// RFC 6749 section 6: a refresh may narrow the scopes but never widen them.
+fn narrow_scopes(granted: &[String], requested: Option<&str>) -> Option<Vec<String>> {
+    let Some(requested) = requested else {
+        return Some(granted.to_vec());
+    };
+
+    let requested = scope::parse(requested);
+
+    if requested.is_empty() || !requested.iter().all(|s| granted.contains(s)) {
+        return None;
+    }
+
+    Some(requested)
+}
+
+/// Whether the user behind a grant still exists and still consents to all of its scopes.
+async fn is_grant_valid(grant: &OAuthRefreshRecord, repo: &Repo) -> InterResult<bool> {
+    let active: Option<i32> = query_scalar(
+        r#"
+        SELECT 1
+        FROM t_user
+        WHERE f_id = $1 AND f_deleted_at IS NULL
+    "#,
+    )
+    .bind(&grant.user_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    if active.is_none() {
+        return Ok(false);
+    }
+
+    let consented = select_consented_scopes(repo, &grant.user_id, &grant.client_id).await?;
+
+    Ok(grant.scopes.iter().all(|s| consented.contains(s)))
+}
+
+/// Describe an authorization request for the consent page.
+pub async fn authorize(
+    claims: &UserClaims,
+    args: OAuthAuthorizeArgs,
+    repo: &Repo,
+) -> ServiceResult<OAuthAuthorizeReply> {
+    let (client, scopes) = validate_request(&args, repo).await?;
+
+    let consented = select_consented_scopes(repo, &claims.sub, &client.f_id).await?;
+
+    let consent_required = !scopes.iter().all(|s| consented.contains(s));
+
+    let scopes = scopes
+        .into_iter()
+        .map(|name| OAuthScopeReply {
+            description: scope::describe(&name).unwrap_or_default().to_string(),
+            name,
+        })
+        .collect();
+
+    Ok(accept().with_data(OAuthAuthorizeReply {
+        client_id: client.f_id,
+        client_name: client.f_name,
+        scopes,
+        consent_required,
+    }))
+}
+
+/// Record the user's decision and hand out an authorization code when approved.
+pub async fn consent(
+    claims: &UserClaims,
+    args: OAuthConsentArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<OAuthConsentReply> {
+    let request = args.request;
+
+    let (client, scopes) = validate_request(&request, repo).await?;
+
+    // Validated against the registered URIs, so it parses.
+    let mut redirect_to = Url::parse(&request.redirect_uri)
+        .map_err(|_| reject(400, "Redirect URI is not registered for this client"))?;
+
+    if !args.approved {
+        redirect_to
+            .query_pairs_mut()
+            .append_pair("error", "access_denied");
+    } else {
+        query(
+            r#"
+            INSERT INTO t_oauth_consent (f_user_id, f_client_id, f_scopes)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (f_user_id, f_client_id) DO UPDATE
+            SET f_scopes = ARRAY(SELECT DISTINCT UNNEST(t_oauth_consent.f_scopes || EXCLUDED.f_scopes)),
+                f_updated_at = now()
+        "#,
+        )
+        .bind(&claims.sub)
+        .bind(&client.f_id)
+        .bind(&scopes)
+        .execute(repo.pool())
+        .await
+        .trace_error()?;
+
+        let code = opaque_token::generate();
+
+        let record = serde_json::to_string(&AuthorizationCodeRecord {
+            client_id: client.f_id,
+            user_id: claims.sub.clone(),
+            redirect_uri: request.redirect_uri,
+            scopes,
+            code_challenge: request.code_challenge,
+        })?;
+
+        let mut conn = cache.conn();
+
+        conn.set_ex(
+            authorization_code_key(&opaque_token::digest(&code)),
+            record,
+            config.oauth_code_exp_seconds,
+        )
+        .await
+        .trace_error()?;
+
+        redirect_to.query_pairs_mut().append_pair("code", &code);
+    }
+
+    if let Some(state) = &request.state {
+        redirect_to.query_pairs_mut().append_pair("state", state);
+    }
+
+    Ok(accept().with_data(OAuthConsentReply {
+        redirect_to: redirect_to.into(),
+    }))
+}
+
+// The protocol endpoints below reject with the RFC 6749 error code as message,
+// the HTTP layer turns it into a standard error response.
+
+async fn authenticate_client(
+    credentials: Option<OAuthClientCredentials>,
+    repo: &Repo,
+) -> InterResult<OAuthClient> {
+    let Some(credentials) = credentials else {
+        return Err(reject(401, "invalid_client"));
+    };
+
+    let Some(client) = select_client(repo, &credentials.client_id).await? else {
+        return Err(reject(401, "invalid_client"));
+    };
+
+    let authenticated = match (&client.f_secret_hash, &credentials.client_secret) {
+        (Some(hash), Some(secret)) => opaque_token::digest(secret) == *hash,
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !authenticated {
+        tracing::debug!("Client {} failed to authenticate", client.f_id);
+
+        return Err(reject(401, "invalid_client"));
+    }
+
+    Ok(client)
+}
+
+/// Issue an access token restricted to `scopes`. `grant` is the user grant the token
+/// acts on, continued by a refresh token; client credentials tokens have none.
+async fn issue_client_token(
+    client_id: &str,
+    grant: Option<OAuthRefreshRecord>,
+    scopes: &[String],
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> InterResult<OAuthTokenReply> {
+    // RFC 9068 section 2.2: without a resource owner the client itself is the subject.
+    let claims = match &grant {
+        Some(grant) => user_claims(&grant.user_id, config, repo).await?,
+        None => UserClaims::with_exp(client_id, config, config.jwt_exp_seconds),
+    }
+    .with_client(client_id, scopes);
+
+    let access_token = jwt_codec.encode(&claims).trace_error()?;
+
+    let refresh_token = match grant {
+        Some(grant) => {
+            let refresh_token = opaque_token::generate();
+            let digest = opaque_token::digest(&refresh_token);
+
+            let record = serde_json::to_string(&grant)?;
+
+            let mut conn = cache.conn();
+
+            conn.set_ex(
+                oauth_refresh_key(&digest),
+                record,
+                config.refresh_token_exp_seconds,
+            )
+            .await
+            .trace_error()?;
+
+            // Indexed per user so that revoking all of a user's tokens reaches these too.
+            let user_key = oauth_user_key(&grant.user_id);
+
+            conn.sadd(&user_key, &digest).await.trace_error()?;
+            conn.expire(&user_key, config.refresh_token_exp_seconds as i64)
+                .await
+                .trace_error()?;
+
+            Some(refresh_token)
+        }
+        None => None,
+    };
+
+    Ok(OAuthTokenReply {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: config.jwt_exp_seconds,
+        refresh_token,
+        scope: scopes.join(" "),
+    })
+}
+
+/// The token endpoint, RFC 6749 section 3.2.
+pub async fn token(
+    credentials: Option<OAuthClientCredentials>,
+    args: OAuthTokenArgs,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<OAuthTokenReply> {
+    let client = authenticate_client(credentials, repo).await?;
+
+    let reply = match args.grant_type.as_str() {
+        "authorization_code" => {
+            let (Some(code), Some(code_verifier)) = (&args.code, &args.code_verifier) else {
+                return Err(reject(400, "invalid_request"));
+            };
+
+            let mut conn = cache.conn();
+
+            // `GETDEL` makes the code single-use.
+            let record = conn
+                .get_del(authorization_code_key(&opaque_token::digest(code)))
+                .await
+                .trace_error()?;
+
+            let Some(record) = record else {
+                return Err(reject(400, "invalid_grant"));
+            };
+
+            let record: AuthorizationCodeRecord = serde_json::from_str(&record)?;
+
+            if record.client_id != client.f_id
+                || args.redirect_uri.as_deref() != Some(record.redirect_uri.as_str())
+                || !pkce_matches(code_verifier, &record.code_challenge)
+            {
+                return Err(reject(400, "invalid_grant"));
+            }
+
+            let grant = OAuthRefreshRecord {
+                client_id: record.client_id,
+                user_id: record.user_id,
+                scopes: record.scopes,
+            };
+
+            // The consent may have been withdrawn since the code was handed out.
+            if !is_grant_valid(&grant, repo).await? {
+                return Err(reject(400, "invalid_grant"));
+            }
+
+            let scopes = grant.scopes.clone();
+
+            issue_client_token(
+                &client.f_id,
+                Some(grant),
+                &scopes,
+                config,
+                jwt_codec,
+                repo,
+                cache,
+            )
+            .await?
+        }
+        "refresh_token" => {
+            let Some(refresh_token) = &args.refresh_token else {
+                return Err(reject(400, "invalid_request"));
+            };
+
+            let digest = opaque_token::digest(refresh_token);
+
+            let mut conn = cache.conn();
+
+            // Refresh tokens rotate, a used one is gone.
+            let record = conn
+                .get_del(oauth_refresh_key(&digest))
+                .await
+                .trace_error()?;
+
+            let Some(record) = record else {
+                return Err(reject(400, "invalid_grant"));
+            };
+
+            let record: OAuthRefreshRecord = serde_json::from_str(&record)?;
+
+            conn.srem(oauth_user_key(&record.user_id), &digest)
+                .await
+                .trace_error()?;
+
+            if record.client_id != client.f_id {
+                return Err(reject(400, "invalid_grant"));
+            }
+
+            // The account may have been deleted or the consent withdrawn since the grant.
+            if !is_grant_valid(&record, repo).await? {
+                return Err(reject(400, "invalid_grant"));
+            }
+
+            let Some(scopes) = narrow_scopes(&record.scopes, args.scope.as_deref()) else {
+                return Err(reject(400, "invalid_scope"));
+            };
+
+            // The refresh token keeps the scopes of the original grant.
+            issue_client_token(
+                &client.f_id,
+                Some(record),
+                &scopes,
+                config,
+                jwt_codec,
+                repo,
+                cache,
+            )
+            .await?
+        }
+        "client_credentials" => {
+            if client.f_secret_hash.is_none() {
+                return Err(reject(400, "unauthorized_client"));
+            }
+
+            let scopes = match &args.scope {
+                Some(requested) => scope::parse(requested),
+                None => client.f_scopes.clone(),
+            };
+
+            if scopes.is_empty() || !scopes.iter().all(|s| client.f_scopes.contains(s)) {
+                return Err(reject(400, "invalid_scope"));
+            }
+
+            issue_client_token(&client.f_id, None, &scopes, config, jwt_codec, repo, cache).await?
+        }
+        _ => return Err(reject(400, "unsupported_grant_type")),
+    };
+
+    Ok(accept().with_data(reply))
+}
+
+/// Token introspection, RFC 7662. A client can only introspect tokens issued to itself,
+/// any other token is reported inactive.
+pub async fn introspect(
+    credentials: Option<OAuthClientCredentials>,
+    args: OAuthTokenTypeArgs,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<OAuthIntrospectReply> {
+    let client = authenticate_client(credentials, repo).await?;
+
+    if let Ok(claims) = jwt_codec.decode(&args.token)
+        && claims.client_id.as_deref() == Some(client.f_id.as_str())
+        && !is_token_revoked(&claims, cache).await?
+    {
+        return Ok(accept().with_data(OAuthIntrospectReply {
+            active: true,
+            scope: claims.scope,
+            client_id: claims.client_id,
+            sub: Some(claims.sub),
+            token_type: Some("Bearer".to_string()),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+        }));
+    }
+
+    let mut conn = cache.conn();
+
+    let record = conn
+        .get(oauth_refresh_key(&opaque_token::digest(&args.token)))
+        .await
+        .trace_error()?;
+
+    if let Some(record) = record {
+        let record: OAuthRefreshRecord = serde_json::from_str(&record)?;
+
+        if record.client_id == client.f_id {
+            return Ok(accept().with_data(OAuthIntrospectReply {
+                active: true,
+                scope: Some(record.scopes.join(" ")),
+                client_id: Some(record.client_id),
+                sub: Some(record.user_id),
+                ..Default::default()
+            }));
+        }
+    }
+
+    Ok(accept().with_data(OAuthIntrospectReply::default()))
+}
+
+/// Token revocation, RFC 7009. Unknown tokens and tokens of other clients are
+/// acknowledged alike, the response never tells them apart.
+pub async fn revoke(
+    credentials: Option<OAuthClientCredentials>,
+    args: OAuthTokenTypeArgs,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    let client = authenticate_client(credentials, repo).await?;
+
+    if let Ok(claims) = jwt_codec.decode(&args.token) {
+        if claims.client_id.as_deref() == Some(client.f_id.as_str()) {
+            revoke_access_token(&claims, cache).await?;
+        }
+
+        return Ok(accept());
+    }
+
+    let digest = opaque_token::digest(&args.token);
+    let key = oauth_refresh_key(&digest);
+
+    let mut conn = cache.conn();
+
+    if let Some(record) = conn.get(&key).await.trace_error()? {
+        let record: OAuthRefreshRecord = serde_json::from_str(&record)?;
+
+        if record.client_id == client.f_id {
+            conn.del(&key).await.trace_error()?;
+            conn.srem(oauth_user_key(&record.user_id), &digest)
+                .await
+                .trace_error()?;
+        }
+    }
+
+    Ok(accept())
+}
+
+/// Revoke every refresh token issued to clients on behalf of a user.
+pub(super) async fn revoke_client_refresh_tokens(user_id: &str, cache: &Cache) -> InterResult<()> {
+    let mut conn = cache.conn();
+
+    let user_key = oauth_user_key(user_id);
+
+    let digests = conn.smembers(&user_key).await.trace_error()?;
+
+    let mut keys: Vec<String> = digests.iter().map(|d| oauth_refresh_key(d)).collect();
+    keys.push(user_key);
+
+    conn.del(keys).await.trace_error()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_the_rfc_7636_verifier() {
+        assert!(pkce_matches(
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        ));
+    }
+
+    #[test]
+    fn rejects_another_verifier() {
+        let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+        assert!(!pkce_matches(
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXl",
+            challenge
+        ));
+
+        // `plain` would send the challenge as the verifier.
+        assert!(!pkce_matches(challenge, challenge));
+        assert!(!pkce_matches("", challenge));
+    }
+
+    #[test]
+    fn refresh_keeps_the_granted_scopes_by_default() {
+        let granted = scopes(&[scope::USERS_READ, scope::ORGS_READ]);
+
+        assert_eq!(narrow_scopes(&granted, None), Some(granted.clone()));
+    }
+
+    #[test]
+    fn refresh_narrows_scopes() {
+        let granted = scopes(&[scope::USERS_READ, scope::ORGS_READ]);
+
+        assert_eq!(
+            narrow_scopes(&granted, Some("orgs:read")),
+            Some(scopes(&[scope::ORGS_READ]))
+        );
+    }
+
+    #[test]
+    fn refresh_never_widens_scopes() {
+        let granted = scopes(&[scope::USERS_READ]);
+
+        assert_eq!(
+            narrow_scopes(&granted, Some("users:read users:write")),
+            None
+        );
+        assert_eq!(narrow_scopes(&granted, Some("orgs:write")), None);
+        assert_eq!(narrow_scopes(&granted, Some("  ")), None);
+    }
+}
@@ -0,0 +1,311 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use redis::AsyncTypedCommands as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use sqlx::{query, query_as, query_scalar};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    cache::Cache,
+    config::{AppConfig, EmailVerificationPolicy},
+    jwt_codec::JwtCodec,
+    model::{
+        oidc::{OidcAuthorizeReply, OidcCallbackArgs},
+        user::LoginUserReply,
+    },
+    oidc_client::{IdTokenClaims, OidcClient},
+    opaque_token,
+    password_hasher::PasswordHasher,
+    permission,
+    repo::{Repo, user::UserSecrets},
+    result_trace::ResultTrace as _,
+    service::{
+        auth::{
+            assign_role, complete_login, generate_password_hash, register_new_user,
+            revoke_all_tokens,
+        },
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// What is stored in Redis between the redirect to the provider and the callback.
+#[derive(Debug, Serialize, Deserialize)]
+struct OidcStateRecord {
+    provider: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+fn oidc_state_key(state: &str) -> String {
+    format!("oidc:state:{}", state)
+}
+
+pub async fn authorize(
+    provider: &str,
+    config: &AppConfig,
+    cache: &Cache,
+    oidc_client: &OidcClient,
+) -> ServiceResult<OidcAuthorizeReply> {
+    if oidc_client.provider(provider).is_none() {
+        return Err(reject(404, "Unknown identity provider"));
+    }
+
+    let state = opaque_token::generate();
+    let nonce = opaque_token::generate();
+
+    // A 43 character random verifier, the shortest RFC 7636 allows.
+    let code_verifier = opaque_token::generate();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    let authorization_url = oidc_client
+        .authorization_url(provider, &state, &nonce, &code_challenge)
+        .await
+        .trace_error()?;
+
+    let record = serde_json::to_string(&OidcStateRecord {
+        provider: provider.to_string(),
+        nonce,
+        code_verifier,
+    })?;
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        oidc_state_key(&state),
+        record,
+        config.oidc_state_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    Ok(accept().with_data(OidcAuthorizeReply { authorization_url }))
+}
+
+/// Tie a first sign-in with `provider` to the account going by the same email, or to a new
+/// account, returning its id.
+#[allow(clippy::too_many_arguments)]
+async fn link_identity(
+    provider: &str,
+    trust_email: bool,
+    claims: &IdTokenClaims,
+    config: &AppConfig,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+) -> InterResult<String> {
+    let Some(email) = claims.email.as_deref() else {
+        return Err(reject(400, "The identity provider did not share an email"));
+    };
+
+    let email_verified = trust_email && claims.email_verified == Some(true);
+
+    // Nobody knows this password, a password can be set through a reset.
+    let password_hash = generate_password_hash(&opaque_token::generate(), password_hasher).await?;
+
+    let mut trx = repo.pool().begin().await?;
+
+    let existing: Option<UserSecrets> = query_as(
+        r#"
+        SELECT f_id, f_password_hash, f_email_verified_at, f_deleted_at
+        FROM t_user
+        WHERE lower(f_email) = lower($1)
+        FOR UPDATE
+    "#,
+    )
+    .bind(email)
+    .fetch_optional(&mut *trx)
+    .await
+    .trace_error()?;
+
+    let mut taken_over = false;
+
+    let user_id = match existing {
+        // Only a provider trusted to verify emails may take over an existing account.
+        Some(_) if !email_verified => {
+            return Err(reject(409, "Email already registered"));
+        }
+        // Whoever registered the address never proved to own it, and may be waiting for
+        // its owner to show up. Nothing they set up carries over to the owner's account.
+        Some(user) if user.f_email_verified_at.is_none() => {
+            tracing::warn!(
+                "Linking {} identity to unverified user {}, resetting its credentials",
+                provider,
+                user.f_id
+            );
+
+            query(
+                r#"
+                UPDATE t_user
+                SET f_password_hash = $2, f_email_verified_at = now(),
+                    f_updated_at = now(), f_version = f_version + 1
+                WHERE f_id = $1
+            "#,
+            )
+            .bind(&user.f_id)
+            .bind(&password_hash)
+            .execute(&mut *trx)
+            .await
+            .trace_error()?;
+
+            for statement in [
+                "DELETE FROM t_user_totp WHERE f_user_id = $1",
+                "DELETE FROM t_recovery_code WHERE f_user_id = $1",
+                "DELETE FROM t_passkey WHERE f_user_id = $1",
+                "DELETE FROM t_api_key WHERE f_user_id = $1",
+            ] {
+                query(statement)
+                    .bind(&user.f_id)
+                    .execute(&mut *trx)
+                    .await
+                    .trace_error()?;
+            }
+
+            taken_over = true;
+
+            user.f_id
+        }
+        Some(user) => {
+            tracing::info!("Linking {} identity to user {}", provider, user.f_id);
+
+            user.f_id
+        }
+        None => {
+            let user_id = Uuid::now_v7().to_string();
+
+            let nickname = claims
+                .name
+                .as_deref()
+                .or(claims.preferred_username.as_deref())
+                .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+
+            register_new_user(
+                &mut *trx,
+                &user_id,
+                email,
+                nickname,
+                &password_hash,
+                email_verified,
+            )
+            .await?;
+
+            assign_role(&mut *trx, &user_id, permission::DEFAULT_ROLE).await?;
+
+            user_id
+        }
+    };
+
+    query(
+        r#"
+        INSERT INTO t_user_identity (f_provider, f_subject, f_user_id, f_email, f_last_login_at)
+        VALUES ($1, $2, $3, $4, now())
+    "#,
+    )
+    .bind(provider)
+    .bind(&claims.sub)
+    .bind(&user_id)
+    .bind(email)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    trx.commit().await?;
+
+    if taken_over {
+        revoke_all_tokens(&user_id, config, cache).await?;
+    }
+
+    Ok(user_id)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn callback(
+    provider: &str,
+    args: OidcCallbackArgs,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+    oidc_client: &OidcClient,
+) -> ServiceResult<LoginUserReply> {
+    let Some(provider_config) = oidc_client.provider(provider) else {
+        return Err(reject(404, "Unknown identity provider"));
+    };
+
+    let mut conn = cache.conn();
+
+    // `GETDEL` makes the state single-use, a replayed callback finds nothing.
+    let record = conn
+        .get_del(oidc_state_key(&args.state))
+        .await
+        .trace_error()?;
+
+    let Some(record) = record else {
+        return Err(reject(400, "Invalid or expired state"));
+    };
+
+    let record: OidcStateRecord = serde_json::from_str(&record)?;
+
+    if record.provider != provider {
+        return Err(reject(400, "Invalid or expired state"));
+    }
+
+    let claims = oidc_client
+        .exchange_code(provider, &args.code, &record.code_verifier, &record.nonce)
+        .await
+        .trace_warn()?;
+
+    // Most sign-ins come back with an identity linked already.
+    let linked: Option<String> = query_scalar(
+        r#"
+        UPDATE t_user_identity
+        SET f_last_login_at = now(), f_email = COALESCE($3, f_email)
+        WHERE f_provider = $1 AND f_subject = $2
+        RETURNING f_user_id
+    "#,
+    )
+    .bind(provider)
+    .bind(&claims.sub)
+    .bind(&claims.email)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let user_id = match linked {
+        Some(user_id) => user_id,
+        None => {
+            link_identity(
+                provider,
+                provider_config.trust_email,
+                &claims,
+                config,
+                password_hasher,
+                repo,
+                cache,
+            )
+            .await?
+        }
+    };
+
+    let email_verified_at: Option<OffsetDateTime> = query_scalar(
+        r#"
+        SELECT f_email_verified_at
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&user_id)
+    .fetch_one(repo.pool())
+    .await
+    .trace_error()?;
+
+    if config.email_verification_policy == EmailVerificationPolicy::BlockLogin
+        && email_verified_at.is_none()
+    {
+        return Err(reject(403, "Email address is not verified"));
+    }
+
+    // The provider only stands for the first factor, a TOTP second factor still applies.
+    complete_login(user_id, false, None, config, jwt_codec, repo, cache).await
+}
@@ -0,0 +1,359 @@
+use sqlx::{Executor, Postgres, query, query_as, query_scalar};
+use uuid::Uuid;
+
+use crate::{
+    model::organization::{
+        CreateOrgArgs, ListMembersReply, ListOrgsReply, OrgMember, OrgReply, OrgRole,
+        SetMemberRoleArgs, UpdateOrgArgs,
+    },
+    repo::{
+        Repo,
+        organization::{MemberOrganization, OrganizationMember},
+    },
+    result_trace::ResultTrace as _,
+    service::result::{InterResult, ServiceResult, accept, reject},
+};
+
+pub(super) fn require_role(role: OrgRole, required: OrgRole) -> InterResult<()> {
+    if role < required {
+        return Err(reject(403, "Insufficient organization role"));
+    }
+
+    Ok(())
+}
+
+pub(super) fn parse_role(role: &str) -> InterResult<OrgRole> {
+    OrgRole::parse(role).ok_or_else(|| {
+        tracing::error!("Unknown organization role '{}' in database", role);
+
+        reject(500, "Unknown organization role")
+    })
+}
+
+/// Select the role of a user in an organization, `None` if they are not a member.
+pub async fn select_member_role<'c, E>(
+    exec: E,
+    org_id: &str,
+    user_id: &str,
+) -> InterResult<Option<OrgRole>>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let role: Option<String> = query_scalar(
+        r#"
+        SELECT f_role
+        FROM t_membership
+        WHERE f_org_id = $1 AND f_user_id = $2
+    "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .fetch_optional(exec)
+    .await
+    .trace_error()?;
+
+    role.as_deref().map(parse_role).transpose()
+}
+
+pub async fn insert_membership<'c, E>(
+    exec: E,
+    org_id: &str,
+    user_id: &str,
+    role: OrgRole,
+) -> InterResult<()>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    query(
+        r#"
+        INSERT INTO t_membership (f_org_id, f_user_id, f_role)
+        VALUES ($1, $2, $3)
+    "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(role.as_str())
+    .execute(exec)
+    .await
+    .trace_error()?;
+
+    Ok(())
+}
+
+/// Lock the organization row so that concurrent membership changes are serialized,
+/// and count its owners.
+async fn lock_and_count_owners<'c, E>(exec: E, org_id: &str) -> InterResult<i64>
+where
+    E: 'c + Executor<'c, Database = Postgres>,
+{
+    let owners: i64 = query_scalar(
+        r#"
+        WITH locked AS (
+            SELECT f_id FROM t_organization WHERE f_id = $1 FOR UPDATE
+        )
+        SELECT COUNT(*)
+        FROM t_membership m
+        JOIN locked o ON o.f_id = m.f_org_id
+        WHERE m.f_role = 'owner'
+    "#,
+    )
+    .bind(org_id)
+    .fetch_one(exec)
+    .await
+    .trace_error()?;
+
+    Ok(owners)
+}
+
+pub async fn create_org(
+    user_id: &str,
+    args: CreateOrgArgs,
+    repo: &Repo,
+) -> ServiceResult<OrgReply> {
+    let name = args.name.trim();
+
+    if name.is_empty() {
+        return Err(reject(400, "Organization name must not be empty"));
+    }
+
+    let org_id = Uuid::now_v7().to_string();
+
+    let mut trx = repo.pool().begin().await?;
+
+    let created_at = query_scalar(
+        r#"
+        INSERT INTO t_organization (f_id, f_name)
+        VALUES ($1, $2)
+        RETURNING f_created_at
+    "#,
+    )
+    .bind(&org_id)
+    .bind(name)
+    .fetch_one(&mut *trx)
+    .await
+    .trace_error()?;
+
+    // The creator owns the organization.
+    insert_membership(&mut *trx, &org_id, user_id, OrgRole::Owner).await?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_code(201).with_data(OrgReply {
+        org_id,
+        name: name.to_string(),
+        role: OrgRole::Owner,
+        created_at,
+    }))
+}
+
+pub async fn list_orgs(user_id: &str, repo: &Repo) -> ServiceResult<ListOrgsReply> {
+    let rows: Vec<MemberOrganization> = query_as(
+        r#"
+        SELECT o.f_id, o.f_name, m.f_role, o.f_created_at
+        FROM t_membership m
+        JOIN t_organization o ON o.f_id = m.f_org_id
+        WHERE m.f_user_id = $1
+        ORDER BY o.f_created_at
+    "#,
+    )
+    .bind(user_id)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    let orgs = rows
+        .into_iter()
+        .map(|o| {
+            Ok(OrgReply {
+                org_id: o.f_id,
+                name: o.f_name,
+                role: parse_role(&o.f_role)?,
+                created_at: o.f_created_at,
+            })
+        })
+        .collect::<InterResult<_>>()?;
+
+    Ok(accept().with_data(ListOrgsReply { orgs }))
+}
+
+pub async fn get_org(org_id: &str, user_id: &str, repo: &Repo) -> ServiceResult<OrgReply> {
+    let row: Option<MemberOrganization> = query_as(
+        r#"
+        SELECT o.f_id, o.f_name, m.f_role, o.f_created_at
+        FROM t_membership m
+        JOIN t_organization o ON o.f_id = m.f_org_id
+        WHERE m.f_org_id = $1 AND m.f_user_id = $2
+    "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    match row {
+        None => Err(reject(404, "Organization not found")),
+        Some(o) => Ok(accept().with_data(OrgReply {
+            org_id: o.f_id,
+            name: o.f_name,
+            role: parse_role(&o.f_role)?,
+            created_at: o.f_created_at,
+        })),
+    }
+}
+
+pub async fn update_org(
+    org_id: &str,
+    user_id: &str,
+    role: OrgRole,
+    args: UpdateOrgArgs,
+    repo: &Repo,
+) -> ServiceResult<OrgReply> {
+    require_role(role, OrgRole::Admin)?;
+
+    let name = args.name.trim();
+
+    if name.is_empty() {
+        return Err(reject(400, "Organization name must not be empty"));
+    }
+
+    query("UPDATE t_organization SET f_name = $2 WHERE f_id = $1")
+        .bind(org_id)
+        .bind(name)
+        .execute(repo.pool())
+        .await
+        .trace_error()?;
+
+    get_org(org_id, user_id, repo).await
+}
+
+pub async fn delete_org(org_id: &str, role: OrgRole, repo: &Repo) -> ServiceResult<()> {
+    require_role(role, OrgRole::Owner)?;
+
+    // Memberships go along through `ON DELETE CASCADE`.
+    query("DELETE FROM t_organization WHERE f_id = $1")
+        .bind(org_id)
+        .execute(repo.pool())
+        .await
+        .trace_error()?;
+
+    Ok(accept().with_message("Organization deleted"))
+}
+
+pub async fn list_members(org_id: &str, repo: &Repo) -> ServiceResult<ListMembersReply> {
+    let rows: Vec<OrganizationMember> = query_as(
+        r#"
+        SELECT m.f_user_id, u.f_email, u.f_nickname, m.f_role, m.f_created_at
+        FROM t_membership m
+        JOIN t_user u ON u.f_id = m.f_user_id
+        WHERE m.f_org_id = $1
+        ORDER BY m.f_created_at
+    "#,
+    )
+    .bind(org_id)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    let members = rows
+        .into_iter()
+        .map(|m| {
+            Ok(OrgMember {
+                user_id: m.f_user_id,
+                email: m.f_email,
+                nickname: m.f_nickname,
+                role: parse_role(&m.f_role)?,
+                joined_at: m.f_created_at,
+            })
+        })
+        .collect::<InterResult<_>>()?;
+
+    Ok(accept().with_data(ListMembersReply { members }))
+}
+
+pub async fn set_member_role(
+    org_id: &str,
+    role: OrgRole,
+    member_id: &str,
+    args: SetMemberRoleArgs,
+    repo: &Repo,
+) -> ServiceResult<()> {
+    require_role(role, OrgRole::Admin)?;
+
+    let mut trx = repo.pool().begin().await?;
+
+    let owners = lock_and_count_owners(&mut *trx, org_id).await?;
+
+    let Some(current) = select_member_role(&mut *trx, org_id, member_id).await? else {
+        return Err(reject(404, "Member not found"));
+    };
+
+    // Only owners may hand out or take away ownership.
+    if (current == OrgRole::Owner || args.role == OrgRole::Owner) && role != OrgRole::Owner {
+        return Err(reject(403, "Only owners can change ownership"));
+    }
+
+    if current == OrgRole::Owner && args.role != OrgRole::Owner && owners <= 1 {
+        return Err(reject(409, "An organization must keep at least one owner"));
+    }
+
+    query(
+        r#"
+        UPDATE t_membership
+        SET f_role = $3
+        WHERE f_org_id = $1 AND f_user_id = $2
+    "#,
+    )
+    .bind(org_id)
+    .bind(member_id)
+    .bind(args.role.as_str())
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_message("Member role updated"))
+}
+
+pub async fn remove_member(
+    org_id: &str,
+    user_id: &str,
+    role: OrgRole,
+    member_id: &str,
+    repo: &Repo,
+) -> ServiceResult<()> {
+    // Anyone may leave, removing others takes an admin.
+    if member_id != user_id {
+        require_role(role, OrgRole::Admin)?;
+    }
+
+    let mut trx = repo.pool().begin().await?;
+
+    let owners = lock_and_count_owners(&mut *trx, org_id).await?;
+
+    let Some(current) = select_member_role(&mut *trx, org_id, member_id).await? else {
+        return Err(reject(404, "Member not found"));
+    };
+
+    if current == OrgRole::Owner {
+        if role != OrgRole::Owner {
+            return Err(reject(403, "Only owners can remove owners"));
+        }
+
+        if owners <= 1 {
+            return Err(reject(409, "An organization must keep at least one owner"));
+        }
+    }
+
+    query("DELETE FROM t_membership WHERE f_org_id = $1 AND f_user_id = $2")
+        .bind(org_id)
+        .bind(member_id)
+        .execute(&mut *trx)
+        .await
+        .trace_error()?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_message("Member removed"))
+}
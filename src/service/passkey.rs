@@ -0,0 +1,536 @@
+use redis::AsyncTypedCommands as _;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, query_scalar};
+use time::OffsetDateTime;
+
+use crate::{
+    cache::Cache,
+    config::{AppConfig, EmailVerificationPolicy},
+    jwt_codec::{JwtCodec, UserClaims},
+    model::{
+        passkey::{
+            AuthenticatorSelection, BeginPasskeyLoginArgs, CredentialDescriptor,
+            CredentialParameter, FinishPasskeyLoginArgs, FinishPasskeyRegistrationArgs,
+            ListPasskeysReply, PasskeyCreationOptions, PasskeyReply, PasskeyRequestOptions,
+            PasskeyUserEntity, RelyingParty, RenamePasskeyArgs,
+        },
+        user::LoginUserReply,
+    },
+    opaque_token,
+    repo::{
+        Repo,
+        passkey::{BasePasskey, PasskeyCredential},
+    },
+    result_trace::ResultTrace as _,
+    service::{
+        auth::complete_login,
+        result::{InterResult, ServiceError, ServiceResult, accept, reject, reject_fields},
+    },
+    webauthn::{self, AuthenticatorData, ClientData, CoseKey, SUPPORTED_ALGORITHMS, WebAuthnError},
+};
+
+const PUBLIC_KEY: &str = "public-key";
+
+/// What is stored in Redis for every issued challenge.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeRecord {
+    /// `webauthn.create` or `webauthn.get`, the ceremony the challenge was issued for.
+    ceremony: String,
+    /// Registering user, or the account an authentication was restricted to.
+    user_id: Option<String>,
+}
+
+fn challenge_key(challenge: &str) -> String {
+    format!("webauthn:challenge:{}", challenge)
+}
+
+fn user_verification(config: &AppConfig) -> String {
+    if config.webauthn_require_user_verification {
+        "required".to_string()
+    } else {
+        "preferred".to_string()
+    }
+}
+
+/// Ceremony failures are only detailed in the logs.
+fn ceremony_failed(err: WebAuthnError) -> ServiceError {
+    tracing::debug!("WebAuthn ceremony rejected: {}", err);
+
+    reject(400, "Passkey verification failed")
+}
+
+async fn store_challenge(
+    ceremony: &str,
+    user_id: Option<&str>,
+    config: &AppConfig,
+    cache: &Cache,
+) -> InterResult<String> {
+    let challenge = opaque_token::generate();
+
+    let record = serde_json::to_string(&ChallengeRecord {
+        ceremony: ceremony.to_string(),
+        user_id: user_id.map(str::to_string),
+    })?;
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        challenge_key(&challenge),
+        record,
+        config.webauthn_challenge_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    Ok(challenge)
+}
+
+/// Consume the challenge echoed in client data, it is only good for one ceremony.
+async fn take_challenge(
+    client_data: &ClientData,
+    cache: &Cache,
+) -> InterResult<Option<ChallengeRecord>> {
+    let mut conn = cache.conn();
+
+    let record = conn
+        .get_del(challenge_key(&client_data.challenge))
+        .await
+        .trace_error()?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let record: ChallengeRecord = serde_json::from_str(&record)?;
+
+    Ok((record.ceremony == client_data.kind).then_some(record))
+}
+
+async fn select_credential_descriptors(
+    user_id: &str,
+    repo: &Repo,
+) -> InterResult<Vec<CredentialDescriptor>> {
+    let ids: Vec<String> = query_scalar(
+        r#"
+        SELECT f_id
+        FROM t_passkey
+        WHERE f_user_id = $1
+    "#,
+    )
+    .bind(user_id)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    Ok(ids
+        .into_iter()
+        .map(|id| CredentialDescriptor {
+            kind: PUBLIC_KEY.to_string(),
+            id,
+        })
+        .collect())
+}
+
+pub async fn begin_registration(
+    claims: &UserClaims,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<PasskeyCreationOptions> {
+    let user: Option<(String, String)> = query_as(
+        r#"
+        SELECT f_email, f_nickname
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some((email, nickname)) = user else {
+        return Err(reject(404, "User not found"));
+    };
+
+    let challenge = store_challenge("webauthn.create", Some(&claims.sub), config, cache).await?;
+
+    // Already registered authenticators refuse to create a second credential.
+    let exclude_credentials = select_credential_descriptors(&claims.sub, repo).await?;
+
+    Ok(accept().with_data(PasskeyCreationOptions {
+        challenge,
+        rp: RelyingParty {
+            id: config.webauthn_rp_id.clone(),
+            name: config.webauthn_rp_name.clone(),
+        },
+        user: PasskeyUserEntity {
+            id: webauthn::encode_base64url(claims.sub.as_bytes()),
+            name: email,
+            display_name: nickname,
+        },
+        pub_key_cred_params: SUPPORTED_ALGORITHMS
+            .into_iter()
+            .map(|alg| CredentialParameter {
+                kind: PUBLIC_KEY.to_string(),
+                alg,
+            })
+            .collect(),
+        timeout: config.webauthn_challenge_exp_seconds * 1000,
+        exclude_credentials,
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred".to_string(),
+            user_verification: user_verification(config),
+        },
+        attestation: "none".to_string(),
+    }))
+}
+
+pub async fn finish_registration(
+    claims: &UserClaims,
+    args: FinishPasskeyRegistrationArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<PasskeyReply> {
+    // Checked before the challenge is taken, fixing the name does not need a new ceremony.
+    let errors = args.validate();
+
+    if !errors.is_empty() {
+        return Err(reject_fields(400, "Invalid passkey name", errors));
+    }
+
+    let response = &args.credential.response;
+
+    let client_data_json = webauthn::decode_base64url(&response.client_data_json, "client data")
+        .map_err(ceremony_failed)?;
+
+    let client_data = ClientData::parse(
+        &client_data_json,
+        "webauthn.create",
+        &config.webauthn_origin,
+    )
+    .map_err(ceremony_failed)?;
+
+    let record = take_challenge(&client_data, cache).await?;
+
+    if record.and_then(|r| r.user_id).as_deref() != Some(claims.sub.as_str()) {
+        return Err(reject(400, "Invalid or expired challenge"));
+    }
+
+    let attestation_object =
+        webauthn::decode_base64url(&response.attestation_object, "attestation object")
+            .map_err(ceremony_failed)?;
+
+    let auth_data =
+        webauthn::attestation_auth_data(&attestation_object).map_err(ceremony_failed)?;
+
+    let auth_data = AuthenticatorData::parse(
+        &auth_data,
+        &config.webauthn_rp_id,
+        config.webauthn_require_user_verification,
+    )
+    .map_err(ceremony_failed)?;
+
+    let Some(attested) = auth_data.attested else {
+        return Err(ceremony_failed(WebAuthnError::Malformed(
+            "attested credential data",
+        )));
+    };
+
+    let credential_id = webauthn::encode_base64url(&attested.credential_id);
+
+    if credential_id != args.credential.id {
+        return Err(ceremony_failed(WebAuthnError::Malformed("credential ID")));
+    }
+
+    // Refuse keys that could never be used to log in.
+    CoseKey::parse(&attested.public_key).map_err(ceremony_failed)?;
+
+    let row: Option<BasePasskey> = query_as(
+        r#"
+        INSERT INTO t_passkey (f_id, f_user_id, f_name, f_public_key, f_sign_count)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (f_id) DO NOTHING
+        RETURNING f_id, f_name, f_created_at, f_last_used_at
+    "#,
+    )
+    .bind(&credential_id)
+    .bind(&claims.sub)
+    .bind(args.name.trim())
+    .bind(&attested.public_key)
+    .bind(auth_data.sign_count as i64)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(row) = row else {
+        return Err(reject(409, "Passkey already registered"));
+    };
+
+    Ok(accept().with_code(201).with_data(PasskeyReply {
+        passkey_id: row.f_id,
+        name: row.f_name,
+        created_at: row.f_created_at,
+        last_used_at: row.f_last_used_at,
+    }))
+}
+
+pub async fn list_passkeys(claims: &UserClaims, repo: &Repo) -> ServiceResult<ListPasskeysReply> {
+    let rows: Vec<BasePasskey> = query_as(
+        r#"
+        SELECT f_id, f_name, f_created_at, f_last_used_at
+        FROM t_passkey
+        WHERE f_user_id = $1
+        ORDER BY f_created_at
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_all(repo.pool())
+    .await
+    .trace_error()?;
+
+    let passkeys = rows
+        .into_iter()
+        .map(|row| PasskeyReply {
+            passkey_id: row.f_id,
+            name: row.f_name,
+            created_at: row.f_created_at,
+            last_used_at: row.f_last_used_at,
+        })
+        .collect();
+
+    Ok(accept().with_data(ListPasskeysReply { passkeys }))
+}
+
+pub async fn rename_passkey(
+    claims: &UserClaims,
+    passkey_id: &str,
+    args: RenamePasskeyArgs,
+    repo: &Repo,
+) -> ServiceResult<PasskeyReply> {
+    let errors = args.validate();
+
+    if !errors.is_empty() {
+        return Err(reject_fields(400, "Invalid passkey name", errors));
+    }
+
+    let row: Option<BasePasskey> = query_as(
+        r#"
+        UPDATE t_passkey
+        SET f_name = $3
+        WHERE f_id = $1 AND f_user_id = $2
+        RETURNING f_id, f_name, f_created_at, f_last_used_at
+    "#,
+    )
+    .bind(passkey_id)
+    .bind(&claims.sub)
+    .bind(args.name.trim())
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(row) = row else {
+        return Err(reject(404, "Passkey not found"));
+    };
+
+    Ok(accept().with_data(PasskeyReply {
+        passkey_id: row.f_id,
+        name: row.f_name,
+        created_at: row.f_created_at,
+        last_used_at: row.f_last_used_at,
+    }))
+}
+
+pub async fn delete_passkey(
+    claims: &UserClaims,
+    passkey_id: &str,
+    repo: &Repo,
+) -> ServiceResult<()> {
+    let result = query(
+        r#"
+        DELETE FROM t_passkey
+        WHERE f_id = $1 AND f_user_id = $2
+    "#,
+    )
+    .bind(passkey_id)
+    .bind(&claims.sub)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(404, "Passkey not found"));
+    }
+
+    Ok(accept().with_message("Passkey deleted"))
+}
+
+pub async fn begin_login(
+    args: BeginPasskeyLoginArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<PasskeyRequestOptions> {
+    let user_id: Option<String> = match &args.email {
+        Some(email) => query_scalar(
+            r#"
+            SELECT f_id
+            FROM t_user
+            WHERE lower(f_email) = lower($1)
+        "#,
+        )
+        .bind(email)
+        .fetch_optional(repo.pool())
+        .await
+        .trace_error()?,
+        None => None,
+    };
+
+    // Unknown emails get an empty allow list, just like accounts without passkeys,
+    // so the options do not reveal which emails are registered.
+    let allow_credentials = match &user_id {
+        Some(user_id) => select_credential_descriptors(user_id, repo).await?,
+        None => Vec::new(),
+    };
+
+    let challenge = store_challenge("webauthn.get", user_id.as_deref(), config, cache).await?;
+
+    Ok(accept().with_data(PasskeyRequestOptions {
+        challenge,
+        rp_id: config.webauthn_rp_id.clone(),
+        timeout: config.webauthn_challenge_exp_seconds * 1000,
+        allow_credentials,
+        user_verification: user_verification(config),
+    }))
+}
+
+pub async fn finish_login(
+    args: FinishPasskeyLoginArgs,
+    config: &AppConfig,
+    jwt_codec: &JwtCodec,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<LoginUserReply> {
+    let response = &args.credential.response;
+
+    let client_data_json = webauthn::decode_base64url(&response.client_data_json, "client data")
+        .map_err(ceremony_failed)?;
+
+    let client_data = ClientData::parse(&client_data_json, "webauthn.get", &config.webauthn_origin)
+        .map_err(ceremony_failed)?;
+
+    let Some(record) = take_challenge(&client_data, cache).await? else {
+        return Err(reject(400, "Invalid or expired challenge"));
+    };
+
+    let credential: Option<PasskeyCredential> = query_as(
+        r#"
+        SELECT f_user_id, f_public_key, f_sign_count
+        FROM t_passkey
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&args.credential.id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(credential) = credential else {
+        return Err(reject(401, "Unknown passkey"));
+    };
+
+    if record
+        .user_id
+        .is_some_and(|user_id| user_id != credential.f_user_id)
+    {
+        return Err(reject(401, "Unknown passkey"));
+    }
+
+    if let Some(user_handle) = &response.user_handle {
+        let user_handle =
+            webauthn::decode_base64url(user_handle, "user handle").map_err(ceremony_failed)?;
+
+        if user_handle != credential.f_user_id.as_bytes() {
+            return Err(reject(401, "Unknown passkey"));
+        }
+    }
+
+    let raw_auth_data =
+        webauthn::decode_base64url(&response.authenticator_data, "authenticator data")
+            .map_err(ceremony_failed)?;
+
+    let auth_data = AuthenticatorData::parse(
+        &raw_auth_data,
+        &config.webauthn_rp_id,
+        config.webauthn_require_user_verification,
+    )
+    .map_err(ceremony_failed)?;
+
+    let signature =
+        webauthn::decode_base64url(&response.signature, "signature").map_err(ceremony_failed)?;
+
+    CoseKey::parse(&credential.f_public_key)
+        .and_then(|key| key.verify(&raw_auth_data, &client_data_json, &signature))
+        .map_err(ceremony_failed)?;
+
+    // A counter that does not move forward hints at a cloned authenticator.
+    // Authenticators without a counter always report zero.
+    let sign_count = auth_data.sign_count as i64;
+
+    if sign_count != 0 && sign_count <= credential.f_sign_count {
+        tracing::warn!(
+            "Passkey {} of user {} reported a stale sign count",
+            args.credential.id,
+            credential.f_user_id
+        );
+
+        return Err(reject(401, "Passkey verification failed"));
+    }
+
+    // Checked again in the update, two logins racing with the same count get one match.
+    let updated: Option<Option<OffsetDateTime>> = query_scalar(
+        r#"
+        UPDATE t_passkey p
+        SET f_sign_count = $2, f_last_used_at = now()
+        FROM t_user u
+        WHERE p.f_id = $1 AND u.f_id = p.f_user_id
+          AND ($2 = 0 OR p.f_sign_count < $2)
+        RETURNING u.f_email_verified_at
+    "#,
+    )
+    .bind(&args.credential.id)
+    .bind(sign_count)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(email_verified_at) = updated else {
+        tracing::warn!(
+            "Passkey {} of user {} lost a race on its sign count",
+            args.credential.id,
+            credential.f_user_id
+        );
+
+        return Err(reject(401, "Passkey verification failed"));
+    };
+
+    if config.email_verification_policy == EmailVerificationPolicy::BlockLogin
+        && email_verified_at.is_none()
+    {
+        return Err(reject(403, "Email address is not verified"));
+    }
+
+    // A verified passkey combines possession with a PIN or biometric, only a bare
+    // presence check still needs the second factor.
+    complete_login(
+        credential.f_user_id,
+        auth_data.user_verified,
+        None,
+        config,
+        jwt_codec,
+        repo,
+        cache,
+    )
+    .await
+}
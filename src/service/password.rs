@@ -0,0 +1,298 @@
+use std::net::IpAddr;
+
+use redis::AsyncTypedCommands as _;
+use sqlx::{query, query_as, query_scalar};
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::{JwtCodec, UserClaims},
+    mailer::{Mail, Mailer},
+    model::{
+        user::{ChangePasswordArgs, ChangePasswordReply, ForgotPasswordArgs, ResetPasswordArgs},
+        validation::FieldError,
+    },
+    opaque_token,
+    password_hasher::PasswordHasher,
+    password_policy::PasswordPolicy,
+    repo::Repo,
+    result_trace::ResultTrace as _,
+    service::{
+        auth::{generate_password_hash, issue_token_pair, revoke_all_tokens, verify_password},
+        lockout::normalize_email,
+        rate_limit::hit_rate_limit,
+        result::{InterResult, ServiceResult, accept, reject, reject_fields},
+    },
+};
+
+/// Reject passwords that do not satisfy the password policy, with one error on
+/// `field` for every broken rule. `user_inputs` are the account's email and nickname.
+pub(super) async fn check_password_policy(
+    field: &str,
+    password: &str,
+    user_inputs: &[&str],
+    password_policy: &PasswordPolicy,
+) -> InterResult<()> {
+    let violations = password_policy.check(password, user_inputs).await;
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let errors = violations
+        .iter()
+        .map(|violation| FieldError {
+            field: field.to_string(),
+            code: violation.code().to_string(),
+            message: violation.message(),
+        })
+        .collect();
+
+    Err(reject_fields(
+        400,
+        "Password does not satisfy the password policy",
+        errors,
+    ))
+}
+
+fn reset_token_key(digest: &str) -> String {
+    format!("reset:password:{}", digest)
+}
+
+pub async fn forgot_password(
+    args: ForgotPasswordArgs,
+    client_ip: IpAddr,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> ServiceResult<()> {
+    // The address limit keeps one client from mailing many inboxes,
+    // the email limit keeps many clients from flooding one inbox.
+    hit_rate_limit(
+        cache,
+        &format!("forgot-password:ip:{}", client_ip),
+        config.password_reset_ip_limit,
+        config.password_reset_window_seconds,
+    )
+    .await?;
+
+    hit_rate_limit(
+        cache,
+        &format!("forgot-password:email:{}", normalize_email(&args.email)),
+        config.password_reset_email_limit,
+        config.password_reset_window_seconds,
+    )
+    .await?;
+
+    let user_id: Option<String> = query_scalar(
+        r#"
+        SELECT f_id
+        FROM t_user
+        WHERE lower(f_email) = lower($1)
+    "#,
+    )
+    .bind(&args.email)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    if let Some(user_id) = user_id {
+        let token = opaque_token::generate();
+
+        let mut conn = cache.conn();
+
+        conn.set_ex(
+            reset_token_key(&opaque_token::digest(&token)),
+            &user_id,
+            config.password_reset_exp_seconds,
+        )
+        .await
+        .trace_error()?;
+
+        mailer
+            .send(Mail {
+                to: args.email.clone(),
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Choose a new password: {}/reset-password?token={}\n\nIf you did not ask for this, ignore this mail, your password stays unchanged.",
+                    config.app_base_url, token
+                ),
+            })
+            .await
+            .trace_error()
+            .ok();
+    }
+
+    // The reply never tells whether the email is registered, not even by failing to mail.
+    Ok(accept().with_message("If the email is registered, a reset link has been sent"))
+}
+
+pub async fn reset_password(
+    args: ResetPasswordArgs,
+    config: &AppConfig,
+    password_policy: &PasswordPolicy,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    let mut conn = cache.conn();
+
+    let token_key = reset_token_key(&opaque_token::digest(&args.token));
+
+    // Only looked at for now, a password the policy rejects leaves the link usable.
+    let user_id = conn.get(&token_key).await.trace_error()?;
+
+    let Some(user_id) = user_id else {
+        return Err(reject(400, "Invalid or expired reset token"));
+    };
+
+    let account: Option<(String, String)> = query_as(
+        r#"
+        SELECT f_email, f_nickname
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&user_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some((email, nickname)) = account else {
+        return Err(reject(400, "Invalid or expired reset token"));
+    };
+
+    check_password_policy(
+        "password",
+        &args.password,
+        &[&email, &nickname],
+        password_policy,
+    )
+    .await?;
+
+    let password_hash = generate_password_hash(&args.password, password_hasher).await?;
+
+    // `GETDEL` makes the token single-use, of concurrent resets with it only one gets here.
+    if conn.get_del(&token_key).await.trace_error()?.as_ref() != Some(&user_id) {
+        return Err(reject(400, "Invalid or expired reset token"));
+    }
+
+    // The token was mailed to the address, so following it also proves ownership.
+    let result = query(
+        r#"
+        UPDATE t_user
+        SET f_password_hash = $2,
+            f_email_verified_at = COALESCE(f_email_verified_at, now())
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&user_id)
+    .bind(&password_hash)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(400, "Invalid or expired reset token"));
+    }
+
+    // Whoever knew the old password must not stay logged in.
+    revoke_all_tokens(&user_id, config, cache).await?;
+
+    Ok(accept().with_message("Password has been reset"))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn change_password(
+    claims: &UserClaims,
+    args: ChangePasswordArgs,
+    config: &AppConfig,
+    password_policy: &PasswordPolicy,
+    jwt_codec: &JwtCodec,
+    password_hasher: &PasswordHasher,
+    repo: &Repo,
+    cache: &Cache,
+) -> ServiceResult<ChangePasswordReply> {
+    // Hashing is slow, so no transaction is held open across it. The update below only
+    // applies over the hash that was verified.
+    let account: Option<(String, String, String)> = query_as(
+        r#"
+        SELECT f_password_hash, f_email, f_nickname
+        FROM t_user
+        WHERE f_id = $1
+    "#,
+    )
+    .bind(&claims.sub)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some((current_hash, email, nickname)) = account else {
+        return Err(reject(404, "User not found"));
+    };
+
+    if !(verify_password(&args.current_password, &current_hash, password_hasher).await?) {
+        return Err(reject(401, "Current password is incorrect"));
+    }
+
+    check_password_policy(
+        "new_password",
+        &args.new_password,
+        &[&email, &nickname],
+        password_policy,
+    )
+    .await?;
+
+    let password_hash = generate_password_hash(&args.new_password, password_hasher).await?;
+
+    let result = query(
+        r#"
+        UPDATE t_user
+        SET f_password_hash = $3
+        WHERE f_id = $1 AND f_password_hash = $2
+    "#,
+    )
+    .bind(&claims.sub)
+    .bind(&current_hash)
+    .bind(&password_hash)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(409, "Password changed meanwhile, try again"));
+    }
+
+    if !args.sign_out_other_sessions {
+        return Ok(accept()
+            .with_message("Password changed")
+            .with_data(ChangePasswordReply {
+                token: None,
+                refresh_token: None,
+                expires_in: None,
+            }));
+    }
+
+    // Revocation cannot spare a single session, so every token goes and the caller
+    // continues on a fresh pair in the same organization.
+    revoke_all_tokens(&claims.sub, config, cache).await?;
+
+    let pair = issue_token_pair(
+        &claims.sub,
+        claims.org_id.as_deref(),
+        config,
+        jwt_codec,
+        repo,
+        cache,
+    )
+    .await?;
+
+    Ok(accept()
+        .with_message("Password changed, other sessions are signed out")
+        .with_data(ChangePasswordReply {
+            token: Some(pair.token),
+            refresh_token: Some(pair.refresh_token),
+            expires_in: Some(config.jwt_exp_seconds),
+        }))
+}
@@ -0,0 +1,56 @@
+use redis::{AsyncTypedCommands as _, IntegerReplyOrNoOp, aio::ConnectionManager, cmd};
+
+use crate::{
+    cache::Cache,
+    result_trace::ResultTrace as _,
+    service::result::{InterResult, reject_retry_after},
+};
+
+/// Seconds until a key expires, `None` when it does not exist or never expires.
+pub(super) async fn seconds_left(
+    conn: &mut ConnectionManager,
+    key: &str,
+) -> InterResult<Option<u64>> {
+    match conn.ttl(key).await.trace_error()? {
+        IntegerReplyOrNoOp::IntegerReply(seconds) if seconds > 0 => Ok(Some(seconds as u64)),
+        _ => Ok(None),
+    }
+}
+
+/// Count a hit against a fixed-window limit and reject it with `429` once more than
+/// `limit` hits happened in the current window of `window_seconds`.
+pub(super) async fn hit_rate_limit(
+    cache: &Cache,
+    bucket: &str,
+    limit: u64,
+    window_seconds: u64,
+) -> InterResult<()> {
+    let key = format!("ratelimit:{}", bucket);
+
+    let mut conn = cache.conn();
+
+    let hits = conn.incr(&key, 1).await.trace_error()?;
+
+    // `NX` keeps the window anchored to the first hit instead of sliding it on every hit.
+    let _: bool = cmd("EXPIRE")
+        .arg(&key)
+        .arg(window_seconds)
+        .arg("NX")
+        .query_async(&mut conn)
+        .await
+        .trace_error()?;
+
+    if hits as u64 > limit {
+        let retry_after = seconds_left(&mut conn, &key)
+            .await?
+            .unwrap_or(window_seconds);
+
+        return Err(reject_retry_after(
+            429,
+            "Too many requests, try again later",
+            retry_after,
+        ));
+    }
+
+    Ok(())
+}
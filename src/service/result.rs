@@ -1,6 +1,8 @@
 use serde::Serialize;
 use thiserror::Error;
 
+use crate::model::validation::FieldError;
+
 pub type ServiceResult<T> = Result<ServiceValue<T>, ServiceError>;
 
 pub type InterResult<T> = Result<T, ServiceError>;
@@ -22,19 +24,65 @@ where
     }
 }
 
+/// Reject a request the client may repeat once `retry_after` seconds have passed.
+pub(super) fn reject_retry_after<M>(code: u16, message: M, retry_after: u64) -> ServiceError
+where
+    M: Into<String>,
+{
+    ServiceError::RetryAfter {
+        code,
+        message: message.into(),
+        retry_after,
+    }
+}
+
+/// Reject a request naming each invalid field.
+pub(super) fn reject_fields<M>(code: u16, message: M, errors: Vec<FieldError>) -> ServiceError
+where
+    M: Into<String>,
+{
+    ServiceError::Fields {
+        code,
+        message: message.into(),
+        errors,
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ServiceError {
     #[error("Generic service error - code: {code}, message: {message}")]
     Generic { code: u16, message: String },
+    #[error("Retry later - code: {code}, message: {message}, retry after: {retry_after}s")]
+    RetryAfter {
+        code: u16,
+        message: String,
+        retry_after: u64,
+    },
+    #[error("Invalid fields - code: {code}, message: {message}, fields: {errors:?}")]
+    Fields {
+        code: u16,
+        message: String,
+        errors: Vec<FieldError>,
+    },
 
     #[error("JWT encoding/decoding error: {0}")]
     JwtCodec(#[from] jsonwebtoken::errors::Error),
     #[error("Password hash error: {0}")]
-    PasswordHash(#[from] argon2::password_hash::Error),
+    PasswordHash(#[from] crate::password_hasher::PasswordHashError),
     #[error("Cache error: {0}")]
     Cache(#[from] redis::RedisError),
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("Serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Mail error: {0}")]
+    Mail(#[from] crate::mailer::MailError),
+    #[error("Secret box error: {0}")]
+    SecretBox(#[from] crate::secret_box::SecretBoxError),
+    #[error("TOTP error: {0}")]
+    Totp(#[from] totp_rs::TotpUrlError),
+    #[error("OIDC error: {0}")]
+    Oidc(#[from] crate::oidc_client::OidcError),
 }
 
 #[derive(Debug, Serialize)]
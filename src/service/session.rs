@@ -0,0 +1,227 @@
+use std::net::IpAddr;
+
+use redis::AsyncTypedCommands as _;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    jwt_codec::UserClaims,
+    model::session::{ListSessionsReply, SessionReply},
+    result_trace::ResultTrace as _,
+    service::{
+        auth::{refresh_family_key, refresh_user_families_key},
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// Last seen time is recorded at most this often, sparing a write on every request.
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+
+/// Device a session was last used from, stored in Redis next to its refresh token family.
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionRecord {
+    user_agent: Option<String>,
+    ip: String,
+    last_seen_at: i64,
+}
+
+/// Key of the record of a session, whose id is the id of its refresh token family.
+pub(super) fn session_key(session_id: &str) -> String {
+    format!("session:{}", session_id)
+}
+
+/// Family ids are UUIDv7, which carry the time the session was created.
+fn session_created_at(session_id: &str) -> Option<OffsetDateTime> {
+    let (secs, nanos) = Uuid::parse_str(session_id).ok()?.get_timestamp()?.to_unix();
+
+    let created_at = OffsetDateTime::from_unix_timestamp(secs as i64).ok()?;
+
+    Some(created_at + time::Duration::nanoseconds(nanos.into()))
+}
+
+/// Whether a request from `user_agent` and `ip` at `now` is worth recording over the
+/// session's current `record`: it is older than a minute or names another device.
+pub fn needs_touch(record: Option<&str>, user_agent: Option<&str>, ip: IpAddr, now: i64) -> bool {
+    let Some(record) = record.and_then(|r| serde_json::from_str::<SessionRecord>(r).ok()) else {
+        return true;
+    };
+
+    let fresh = record.last_seen_at + LAST_SEEN_RESOLUTION_SECONDS > now;
+
+    !(fresh && record.user_agent.as_deref() == user_agent && record.ip == ip.to_string())
+}
+
+/// Note that the session of `claims` was used from `user_agent` and `ip`, for requests
+/// `needs_touch` lets through.
+pub async fn touch_session(
+    claims: &UserClaims,
+    user_agent: Option<&str>,
+    ip: IpAddr,
+    config: &AppConfig,
+    cache: &Cache,
+) -> InterResult<()> {
+    let Some(session_id) = &claims.sid else {
+        return Ok(());
+    };
+
+    let record = serde_json::to_string(&SessionRecord {
+        user_agent: user_agent.map(str::to_string),
+        ip: ip.to_string(),
+        last_seen_at: OffsetDateTime::now_utc().unix_timestamp(),
+    })?;
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        session_key(session_id),
+        record,
+        config.refresh_token_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    Ok(())
+}
+
+pub async fn list_sessions(claims: &UserClaims, cache: &Cache) -> ServiceResult<ListSessionsReply> {
+    let mut conn = cache.conn();
+
+    let families_key = refresh_user_families_key(&claims.sub);
+
+    let session_ids = conn.smembers(&families_key).await.trace_error()?;
+
+    let mut sessions = Vec::new();
+
+    for session_id in session_ids {
+        // The set is only pruned here, families expire on their own.
+        if !conn
+            .exists(refresh_family_key(&session_id))
+            .await
+            .trace_error()?
+        {
+            conn.srem(&families_key, &session_id).await.trace_error()?;
+
+            continue;
+        }
+
+        let record = match conn.get(session_key(&session_id)).await.trace_error()? {
+            Some(record) => Some(serde_json::from_str::<SessionRecord>(&record)?),
+            None => None,
+        };
+
+        let Some(created_at) = session_created_at(&session_id) else {
+            continue;
+        };
+
+        let current = claims.sid.as_deref() == Some(session_id.as_str());
+
+        sessions.push(match record {
+            Some(record) => SessionReply {
+                session_id,
+                user_agent: record.user_agent,
+                ip: Some(record.ip),
+                created_at,
+                last_seen_at: OffsetDateTime::from_unix_timestamp(record.last_seen_at).ok(),
+                current,
+            },
+            None => SessionReply {
+                session_id,
+                user_agent: None,
+                ip: None,
+                created_at,
+                last_seen_at: None,
+                current,
+            },
+        });
+    }
+
+    // Most recently active first, sessions never used since login last.
+    sessions.sort_by(|a, b| {
+        b.last_seen_at
+            .cmp(&a.last_seen_at)
+            .then(b.created_at.cmp(&a.created_at))
+    });
+
+    Ok(accept().with_data(ListSessionsReply { sessions }))
+}
+
+/// End a session of the caller: its refresh token stops working and access tokens
+/// issued to it are rejected from the next request on.
+pub async fn terminate_session(
+    claims: &UserClaims,
+    session_id: &str,
+    cache: &Cache,
+) -> ServiceResult<()> {
+    let mut conn = cache.conn();
+
+    let removed = conn
+        .srem(refresh_user_families_key(&claims.sub), session_id)
+        .await
+        .trace_error()?;
+
+    if removed == 0 {
+        return Err(reject(404, "Session not found"));
+    }
+
+    conn.del(&[refresh_family_key(session_id), session_key(session_id)])
+        .await
+        .trace_error()?;
+
+    Ok(accept().with_message("Session terminated"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn record(last_seen_at: i64) -> String {
+        serde_json::to_string(&SessionRecord {
+            user_agent: Some("agent".to_string()),
+            ip: IP.to_string(),
+            last_seen_at,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn skips_a_fresh_record_of_the_same_device() {
+        let record = record(NOW - LAST_SEEN_RESOLUTION_SECONDS + 1);
+
+        assert!(!needs_touch(Some(&record), Some("agent"), IP, NOW));
+    }
+
+    #[test]
+    fn touches_a_stale_record() {
+        let record = record(NOW - LAST_SEEN_RESOLUTION_SECONDS);
+
+        assert!(needs_touch(Some(&record), Some("agent"), IP, NOW));
+    }
+
+    #[test]
+    fn touches_for_another_device() {
+        let record = record(NOW);
+
+        assert!(needs_touch(Some(&record), Some("other"), IP, NOW));
+        assert!(needs_touch(Some(&record), None, IP, NOW));
+        assert!(needs_touch(
+            Some(&record),
+            Some("agent"),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            NOW
+        ));
+    }
+
+    #[test]
+    fn touches_a_missing_or_unreadable_record() {
+        assert!(needs_touch(None, Some("agent"), IP, NOW));
+        assert!(needs_touch(Some("not json"), Some("agent"), IP, NOW));
+    }
+}
@@ -1,21 +1,46 @@
-use sqlx::query_as;
+use reqwest::Url;
+use sqlx::{query, query_as, query_scalar};
 
+use crate::model::patch::Patch;
+use crate::model::validation::FieldError;
 use crate::repo::user::BaseUser;
 use crate::result_trace::ResultTrace as _;
-use crate::service::result::{accept, reject};
+use crate::service::result::{accept, reject, reject_fields};
 use crate::{
-    model::user::{GetUserArgs, GetUserReply},
+    model::user::{GetUserArgs, GetUserReply, SetUserRolesArgs, SetUserRolesReply, UpdateUserArgs},
     repo::Repo,
     service::result::ServiceResult,
 };
 
+const NICKNAME_MAX_LENGTH: usize = 64;
+const AVATAR_URL_MAX_LENGTH: usize = 2048;
+const LOCALE_MAX_LENGTH: usize = 35;
+const TIMEZONE_MAX_LENGTH: usize = 64;
+
+impl From<BaseUser> for GetUserReply {
+    fn from(u: BaseUser) -> Self {
+        GetUserReply {
+            user_id: u.f_id,
+            email: u.f_email,
+            nickname: u.f_nickname,
+            avatar_url: u.f_avatar_url,
+            locale: u.f_locale,
+            timezone: u.f_timezone,
+            created_at: u.f_created_at,
+            updated_at: u.f_updated_at,
+            version: u.f_version,
+        }
+    }
+}
+
 pub async fn get_user(args: GetUserArgs, repo: &Repo) -> ServiceResult<GetUserReply> {
     // Query the database for the user by id
     let row: Option<BaseUser> = query_as(
         r#"
-        SELECT f_id, f_email, f_nickname, f_created_at
+        SELECT f_id, f_email, f_nickname, f_avatar_url, f_locale, f_timezone,
+               f_created_at, f_updated_at, f_version
         FROM t_user
-        WHERE f_id = $1
+        WHERE f_id = $1 AND f_deleted_at IS NULL
     "#,
     )
     .bind(&args.user_id)
@@ -25,11 +50,228 @@ pub async fn get_user(args: GetUserArgs, repo: &Repo) -> ServiceResult<GetUserRe
 
     match row {
         None => Err(reject(404, "User not found")),
-        Some(u) => Ok(accept().with_data(GetUserReply {
-            user_id: u.f_id,
-            email: u.f_email,
-            nickname: u.f_nickname,
-            created_at: u.f_created_at,
-        })),
+        Some(u) => Ok(accept().with_data(u.into())),
+    }
+}
+
+fn field_error(field: &str, code: &str, message: &str) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        code: code.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// BCP 47 shape: a 2 to 8 letter language, then subtags of 1 to 8 letters or digits.
+fn is_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+
+    let language_ok = subtags
+        .next()
+        .is_some_and(|l| (2..=8).contains(&l.len()) && l.chars().all(|c| c.is_ascii_alphabetic()));
+
+    language_ok
+        && subtags
+            .all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// IANA shape, e.g. `UTC` or `America/Argentina/Buenos_Aires`. Whether the zone exists
+/// is left to the clients rendering times in it.
+fn is_timezone(timezone: &str) -> bool {
+    timezone.split('/').all(|part| {
+        part.starts_with(|c: char| c.is_ascii_alphabetic())
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+    })
+}
+
+fn validate_profile(args: &UpdateUserArgs) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    match &args.nickname {
+        Patch::Null => errors.push(field_error(
+            "nickname",
+            "required",
+            "Nickname cannot be removed",
+        )),
+        Patch::Value(nickname) if nickname.trim().is_empty() => {
+            errors.push(field_error("nickname", "blank", "Nickname cannot be blank"))
+        }
+        Patch::Value(nickname) if nickname.trim().chars().count() > NICKNAME_MAX_LENGTH => errors
+            .push(field_error(
+                "nickname",
+                "too_long",
+                &format!(
+                    "Nickname must be at most {} characters",
+                    NICKNAME_MAX_LENGTH
+                ),
+            )),
+        _ => {}
+    }
+
+    if let Patch::Value(avatar_url) = &args.avatar_url {
+        let valid = avatar_url.len() <= AVATAR_URL_MAX_LENGTH
+            && Url::parse(avatar_url).is_ok_and(|url| matches!(url.scheme(), "https" | "http"));
+
+        if !valid {
+            errors.push(field_error(
+                "avatar_url",
+                "invalid",
+                "Avatar URL must be an absolute http or https URL",
+            ));
+        }
+    }
+
+    if let Patch::Value(locale) = &args.locale
+        && !(locale.len() <= LOCALE_MAX_LENGTH && is_locale(locale))
+    {
+        errors.push(field_error(
+            "locale",
+            "invalid",
+            "Locale must be a language tag such as en-US",
+        ));
+    }
+
+    if let Patch::Value(timezone) = &args.timezone
+        && !(timezone.len() <= TIMEZONE_MAX_LENGTH && is_timezone(timezone))
+    {
+        errors.push(field_error(
+            "timezone",
+            "invalid",
+            "Time zone must be an IANA name such as Europe/Paris",
+        ));
+    }
+
+    errors
+}
+
+fn merge(patch: Patch<String>, current: Option<String>) -> Option<String> {
+    match patch {
+        Patch::Missing => current,
+        Patch::Null => None,
+        Patch::Value(value) => Some(value),
+    }
+}
+
+/// Apply a merge patch to a profile. The update only goes through if the profile is
+/// still at the version the client read, concurrent edits get a 409 to retry from.
+pub async fn update_user(
+    user_id: &str,
+    args: UpdateUserArgs,
+    repo: &Repo,
+) -> ServiceResult<GetUserReply> {
+    let errors = validate_profile(&args);
+
+    if !errors.is_empty() {
+        return Err(reject_fields(400, "Invalid profile", errors));
     }
+
+    let current: Option<BaseUser> = query_as(
+        r#"
+        SELECT f_id, f_email, f_nickname, f_avatar_url, f_locale, f_timezone,
+               f_created_at, f_updated_at, f_version
+        FROM t_user
+        WHERE f_id = $1 AND f_deleted_at IS NULL
+    "#,
+    )
+    .bind(user_id)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    let Some(current) = current else {
+        return Err(reject(404, "User not found"));
+    };
+
+    if current.f_version != args.version {
+        return Err(reject(409, "Profile was changed by another update"));
+    }
+
+    let nickname = match args.nickname {
+        Patch::Value(nickname) => nickname.trim().to_string(),
+        _ => current.f_nickname,
+    };
+
+    // The version check is repeated in the update to close the gap since the read.
+    let updated: Option<BaseUser> = query_as(
+        r#"
+        UPDATE t_user
+        SET f_nickname = $3, f_avatar_url = $4, f_locale = $5, f_timezone = $6,
+            f_updated_at = now(), f_version = f_version + 1
+        WHERE f_id = $1 AND f_version = $2
+        RETURNING f_id, f_email, f_nickname, f_avatar_url, f_locale, f_timezone,
+                  f_created_at, f_updated_at, f_version
+    "#,
+    )
+    .bind(user_id)
+    .bind(args.version)
+    .bind(&nickname)
+    .bind(merge(args.avatar_url, current.f_avatar_url))
+    .bind(merge(args.locale, current.f_locale))
+    .bind(merge(args.timezone, current.f_timezone))
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    match updated {
+        None => Err(reject(409, "Profile was changed by another update")),
+        Some(u) => Ok(accept().with_message("Profile updated").with_data(u.into())),
+    }
+}
+
+pub async fn set_user_roles(
+    user_id: String,
+    mut args: SetUserRolesArgs,
+    repo: &Repo,
+) -> ServiceResult<SetUserRolesReply> {
+    args.roles.sort();
+    args.roles.dedup();
+
+    let mut trx = repo.pool().begin().await?;
+
+    let exists: Option<i32> = query_scalar("SELECT 1 FROM t_user WHERE f_id = $1")
+        .bind(&user_id)
+        .fetch_optional(&mut *trx)
+        .await
+        .trace_error()?;
+
+    if exists.is_none() {
+        return Err(reject(404, "User not found"));
+    }
+
+    let known: i64 = query_scalar("SELECT COUNT(*) FROM t_role WHERE f_name = ANY($1)")
+        .bind(&args.roles)
+        .fetch_one(&mut *trx)
+        .await
+        .trace_error()?;
+
+    if known as usize != args.roles.len() {
+        return Err(reject(400, "Unknown role"));
+    }
+
+    query("DELETE FROM t_user_role WHERE f_user_id = $1")
+        .bind(&user_id)
+        .execute(&mut *trx)
+        .await
+        .trace_error()?;
+
+    query(
+        r#"
+        INSERT INTO t_user_role (f_user_id, f_role)
+        SELECT $1, UNNEST($2::TEXT[])
+    "#,
+    )
+    .bind(&user_id)
+    .bind(&args.roles)
+    .execute(&mut *trx)
+    .await
+    .trace_error()?;
+
+    trx.commit().await?;
+
+    Ok(accept().with_data(SetUserRolesReply {
+        user_id,
+        roles: args.roles,
+    }))
 }
@@ -0,0 +1,140 @@
+use redis::AsyncTypedCommands as _;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as};
+
+use crate::{
+    cache::Cache,
+    config::AppConfig,
+    mailer::{Mail, Mailer},
+    model::user::{ResendVerificationArgs, VerifyEmailArgs},
+    opaque_token,
+    repo::{Repo, user::UnverifiedUser},
+    result_trace::ResultTrace as _,
+    service::{
+        lockout::normalize_email,
+        rate_limit::hit_rate_limit,
+        result::{InterResult, ServiceResult, accept, reject},
+    },
+};
+
+/// What is stored in Redis for a verification token. The email is kept so that a token
+/// mailed to a former address cannot verify the current one.
+#[derive(Debug, Serialize, Deserialize)]
+struct VerificationRecord {
+    user_id: String,
+    email: String,
+}
+
+fn verification_token_key(digest: &str) -> String {
+    format!("verify:email:{}", digest)
+}
+
+/// Mail a single-use verification link to `email`.
+pub(super) async fn send_verification_mail(
+    user_id: &str,
+    email: &str,
+    config: &AppConfig,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> InterResult<()> {
+    let token = opaque_token::generate();
+
+    let record = serde_json::to_string(&VerificationRecord {
+        user_id: user_id.to_string(),
+        email: email.to_string(),
+    })?;
+
+    let mut conn = cache.conn();
+
+    conn.set_ex(
+        verification_token_key(&opaque_token::digest(&token)),
+        record,
+        config.email_verification_exp_seconds,
+    )
+    .await
+    .trace_error()?;
+
+    mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Confirm that this address belongs to you: {}/verify-email?token={}\n\nIf you did not create an account, ignore this mail.",
+                config.app_base_url, token
+            ),
+        })
+        .await
+        .trace_error()?;
+
+    Ok(())
+}
+
+pub async fn verify_email(args: VerifyEmailArgs, repo: &Repo, cache: &Cache) -> ServiceResult<()> {
+    let mut conn = cache.conn();
+
+    // `GETDEL` makes the token single-use.
+    let record = conn
+        .get_del(verification_token_key(&opaque_token::digest(&args.token)))
+        .await
+        .trace_error()?;
+
+    let Some(record) = record else {
+        return Err(reject(400, "Invalid or expired verification token"));
+    };
+
+    let record: VerificationRecord = serde_json::from_str(&record)?;
+
+    let result = query(
+        r#"
+        UPDATE t_user
+        SET f_email_verified_at = COALESCE(f_email_verified_at, now())
+        WHERE f_id = $1 AND f_email = $2
+    "#,
+    )
+    .bind(&record.user_id)
+    .bind(&record.email)
+    .execute(repo.pool())
+    .await
+    .trace_error()?;
+
+    if result.rows_affected() == 0 {
+        return Err(reject(400, "Invalid or expired verification token"));
+    }
+
+    Ok(accept().with_message("Email verified"))
+}
+
+pub async fn resend_verification(
+    args: ResendVerificationArgs,
+    config: &AppConfig,
+    repo: &Repo,
+    cache: &Cache,
+    mailer: &Mailer,
+) -> ServiceResult<()> {
+    hit_rate_limit(
+        cache,
+        &format!("resend-verification:{}", normalize_email(&args.email)),
+        config.verification_resend_limit,
+        config.verification_resend_window_seconds,
+    )
+    .await?;
+
+    let user: Option<UnverifiedUser> = query_as(
+        r#"
+        SELECT f_id, f_email
+        FROM t_user
+        WHERE lower(f_email) = lower($1) AND f_email_verified_at IS NULL
+    "#,
+    )
+    .bind(&args.email)
+    .fetch_optional(repo.pool())
+    .await
+    .trace_error()?;
+
+    if let Some(user) = user {
+        send_verification_mail(&user.f_id, &user.f_email, config, cache, mailer).await?;
+    }
+
+    // The reply never tells whether the email belongs to an unverified account.
+    Ok(accept().with_message("If the account needs verification, a mail has been sent"))
+}
@@ -3,7 +3,12 @@ use std::sync::Arc;
 use crate::cache::Cache;
 use crate::config::AppConfig;
 use crate::jwt_codec::JwtCodec;
+use crate::mailer::Mailer;
+use crate::oidc_client::OidcClient;
+use crate::password_hasher::PasswordHasher;
+use crate::password_policy::PasswordPolicy;
 use crate::repo::Repo;
+use crate::secret_box::SecretBox;
 
 /// `AppState` is a cloneable wrapper around `AppStateInner` using `Arc`.
 #[derive(Clone)]
@@ -12,13 +17,29 @@ pub(crate) struct AppState {
 }
 
 impl AppState {
-    pub fn new(config: AppConfig, repo: Repo, cache: Cache, jwt_codec: JwtCodec) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: AppConfig,
+        repo: Repo,
+        cache: Cache,
+        jwt_codec: JwtCodec,
+        mailer: Mailer,
+        secret_box: SecretBox,
+        oidc_client: OidcClient,
+        password_hasher: PasswordHasher,
+        password_policy: PasswordPolicy,
+    ) -> Self {
         Self {
             inner: Arc::new(AppStateInner {
                 config,
                 repo,
                 cache,
                 jwt_codec,
+                mailer,
+                secret_box,
+                oidc_client,
+                password_hasher,
+                password_policy,
             }),
         }
     }
@@ -38,6 +59,26 @@ impl AppState {
     pub fn jwt_codec(&self) -> &JwtCodec {
         &self.inner.jwt_codec
     }
+
+    pub fn mailer(&self) -> &Mailer {
+        &self.inner.mailer
+    }
+
+    pub fn secret_box(&self) -> &SecretBox {
+        &self.inner.secret_box
+    }
+
+    pub fn oidc_client(&self) -> &OidcClient {
+        &self.inner.oidc_client
+    }
+
+    pub fn password_hasher(&self) -> &PasswordHasher {
+        &self.inner.password_hasher
+    }
+
+    pub fn password_policy(&self) -> &PasswordPolicy {
+        &self.inner.password_policy
+    }
 }
 
 /// `AppStateInner` has not to be Clone because `AppState` is the one being cloned.
@@ -46,4 +87,9 @@ pub struct AppStateInner {
     repo: Repo,
     cache: Cache,
     jwt_codec: JwtCodec,
+    mailer: Mailer,
+    secret_box: SecretBox,
+    oidc_client: OidcClient,
+    password_hasher: PasswordHasher,
+    password_policy: PasswordPolicy,
 }
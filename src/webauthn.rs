@@ -0,0 +1,572 @@
+use std::io::Cursor;
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use ciborium::Value;
+use rsa::{BigUint, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest as _, Sha256};
+use thiserror::Error;
+
+pub type WebAuthnResult<T> = Result<T, WebAuthnError>;
+
+#[derive(Debug, Error)]
+pub enum WebAuthnError {
+    #[error("Malformed {0}")]
+    Malformed(&'static str),
+    #[error("Unexpected ceremony type")]
+    WrongType,
+    #[error("Origin mismatch")]
+    WrongOrigin,
+    #[error("Relying party ID mismatch")]
+    WrongRpId,
+    #[error("User presence flag not set")]
+    UserNotPresent,
+    #[error("User verification flag not set")]
+    UserNotVerified,
+    #[error("Unsupported public key algorithm")]
+    UnsupportedAlgorithm,
+    #[error("Invalid signature")]
+    InvalidSignature,
+}
+
+/// COSE algorithm identifiers offered at registration: ES256, EdDSA and RS256.
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [-7, -8, -257];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Length of the fixed part of authenticator data: RP ID hash, flags and sign count.
+const AUTH_DATA_HEADER: usize = 37;
+
+/// Length of the AAGUID leading attested credential data.
+const AAGUID_BYTES: usize = 16;
+
+pub fn decode_base64url(value: &str, what: &'static str) -> WebAuthnResult<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| WebAuthnError::Malformed(what))
+}
+
+pub fn encode_base64url(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The fields of `clientDataJSON` a relying party has to check.
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Base64url challenge, as issued by the relying party.
+    pub challenge: String,
+    pub origin: String,
+}
+
+impl ClientData {
+    /// Parse `clientDataJSON` and check its type and origin.
+    /// The challenge is left to the caller, which knows where it was stored.
+    pub fn parse(json: &[u8], expected_type: &str, origin: &str) -> WebAuthnResult<Self> {
+        let client_data: Self =
+            serde_json::from_slice(json).map_err(|_| WebAuthnError::Malformed("client data"))?;
+
+        if client_data.kind != expected_type {
+            return Err(WebAuthnError::WrongType);
+        }
+
+        if client_data.origin != origin {
+            return Err(WebAuthnError::WrongOrigin);
+        }
+
+        Ok(client_data)
+    }
+}
+
+/// A credential created during registration.
+#[derive(Debug)]
+pub struct AttestedCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE encoded public key, kept as is for storage.
+    pub public_key: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct AuthenticatorData {
+    /// Whether the authenticator checked a PIN or biometric, not just presence.
+    pub user_verified: bool,
+    pub sign_count: u32,
+    pub attested: Option<AttestedCredential>,
+}
+
+impl AuthenticatorData {
+    /// Parse authenticator data and check the RP ID hash and the user presence
+    /// (and verification, when required) flags.
+    pub fn parse(bytes: &[u8], rp_id: &str, require_uv: bool) -> WebAuthnResult<Self> {
+        if bytes.len() < AUTH_DATA_HEADER {
+            return Err(WebAuthnError::Malformed("authenticator data"));
+        }
+
+        if bytes[..32] != Sha256::digest(rp_id.as_bytes())[..] {
+            return Err(WebAuthnError::WrongRpId);
+        }
+
+        let flags = bytes[32];
+
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebAuthnError::UserNotPresent);
+        }
+
+        if require_uv && flags & FLAG_USER_VERIFIED == 0 {
+            return Err(WebAuthnError::UserNotVerified);
+        }
+
+        let sign_count = u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]);
+
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            Some(parse_attested_credential(&bytes[AUTH_DATA_HEADER..])?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            user_verified: flags & FLAG_USER_VERIFIED != 0,
+            sign_count,
+            attested,
+        })
+    }
+}
+
+fn parse_attested_credential(bytes: &[u8]) -> WebAuthnResult<AttestedCredential> {
+    let malformed = || WebAuthnError::Malformed("attested credential data");
+
+    let rest = bytes.get(AAGUID_BYTES..).ok_or_else(malformed)?;
+
+    let id_len = u16::from_be_bytes([
+        *rest.first().ok_or_else(malformed)?,
+        *rest.get(1).ok_or_else(malformed)?,
+    ]) as usize;
+
+    let credential_id = rest.get(2..2 + id_len).ok_or_else(malformed)?.to_vec();
+
+    let key_bytes = rest.get(2 + id_len..).ok_or_else(malformed)?;
+
+    // The COSE key is followed by extensions when the ED flag is set,
+    // so its length is only known once it has been decoded.
+    let mut cursor = Cursor::new(key_bytes);
+
+    let _: Value = ciborium::from_reader(&mut cursor).map_err(|_| malformed())?;
+
+    let public_key = key_bytes[..cursor.position() as usize].to_vec();
+
+    Ok(AttestedCredential {
+        credential_id,
+        public_key,
+    })
+}
+
+/// Extract the authenticator data of an attestation object.
+///
+/// The attestation statement is not verified: registration asks for `none`,
+/// so the authenticator model is not trusted either way.
+pub fn attestation_auth_data(attestation_object: &[u8]) -> WebAuthnResult<Vec<u8>> {
+    let malformed = || WebAuthnError::Malformed("attestation object");
+
+    let value: Value = ciborium::from_reader(attestation_object).map_err(|_| malformed())?;
+
+    let map = value.as_map().ok_or_else(malformed)?;
+
+    map.iter()
+        .find(|(k, _)| k.as_text() == Some("authData"))
+        .and_then(|(_, v)| v.as_bytes())
+        .cloned()
+        .ok_or_else(malformed)
+}
+
+/// A credential public key in one of the supported algorithms.
+pub enum CoseKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(RsaPublicKey),
+}
+
+fn cose_param(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| {
+            k.as_integer()
+                .is_some_and(|i| i128::from(i) == i128::from(label))
+        })
+        .map(|(_, v)| v)
+}
+
+fn cose_bytes(map: &[(Value, Value)], label: i64) -> WebAuthnResult<&[u8]> {
+    cose_param(map, label)
+        .and_then(Value::as_bytes)
+        .map(Vec::as_slice)
+        .ok_or(WebAuthnError::Malformed("public key"))
+}
+
+impl CoseKey {
+    pub fn parse(bytes: &[u8]) -> WebAuthnResult<Self> {
+        let malformed = || WebAuthnError::Malformed("public key");
+
+        let value: Value = ciborium::from_reader(bytes).map_err(|_| malformed())?;
+
+        let map = value.as_map().ok_or_else(malformed)?;
+
+        let alg = cose_param(map, 3)
+            .and_then(Value::as_integer)
+            .map(i128::from)
+            .ok_or_else(malformed)?;
+
+        match alg {
+            -7 => {
+                let mut point = vec![0x04];
+                point.extend_from_slice(cose_bytes(map, -2)?);
+                point.extend_from_slice(cose_bytes(map, -3)?);
+
+                let key =
+                    p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).map_err(|_| malformed())?;
+
+                Ok(Self::Es256(key))
+            }
+            -8 => {
+                let x: [u8; 32] = cose_bytes(map, -2)?.try_into().map_err(|_| malformed())?;
+
+                let key = ed25519_dalek::VerifyingKey::from_bytes(&x).map_err(|_| malformed())?;
+
+                Ok(Self::EdDsa(key))
+            }
+            -257 => {
+                let n = BigUint::from_bytes_be(cose_bytes(map, -1)?);
+                let e = BigUint::from_bytes_be(cose_bytes(map, -2)?);
+
+                let key = RsaPublicKey::new(n, e).map_err(|_| malformed())?;
+
+                Ok(Self::Rs256(key))
+            }
+            _ => Err(WebAuthnError::UnsupportedAlgorithm),
+        }
+    }
+
+    /// Verify an assertion signature, made over `authenticatorData || SHA-256(clientDataJSON)`.
+    pub fn verify(
+        &self,
+        auth_data: &[u8],
+        client_data_json: &[u8],
+        signature: &[u8],
+    ) -> WebAuthnResult<()> {
+        let mut message = auth_data.to_vec();
+        message.extend_from_slice(&Sha256::digest(client_data_json));
+
+        let verified = match self {
+            Self::Es256(key) => {
+                use p256::ecdsa::signature::Verifier as _;
+
+                p256::ecdsa::Signature::from_der(signature)
+                    .is_ok_and(|sig| key.verify(&message, &sig).is_ok())
+            }
+            Self::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|sig| key.verify_strict(&message, &sig).is_ok()),
+            Self::Rs256(key) => {
+                use rsa::signature::Verifier as _;
+
+                let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key.clone());
+
+                rsa::pkcs1v15::Signature::try_from(signature)
+                    .is_ok_and(|sig| key.verify(&message, &sig).is_ok())
+            }
+        };
+
+        if !verified {
+            return Err(WebAuthnError::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
+
+/// A software authenticator producing what a browser would send, for tests.
+#[cfg(test)]
+pub mod soft {
+    use argon2::password_hash::rand_core::{OsRng, RngCore as _};
+    use ciborium::Value;
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use sha2::{Digest as _, Sha256};
+
+    use super::{FLAG_ATTESTED_CREDENTIAL, FLAG_USER_PRESENT, FLAG_USER_VERIFIED};
+
+    pub fn cbor(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        ciborium::into_writer(value, &mut bytes).expect("encode CBOR");
+
+        bytes
+    }
+
+    pub fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": origin,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// An Ed25519 credential with a counter.
+    pub struct SoftAuthenticator {
+        key: SigningKey,
+        pub credential_id: Vec<u8>,
+        pub sign_count: u32,
+    }
+
+    impl SoftAuthenticator {
+        pub fn new() -> Self {
+            let mut seed = [0u8; 32];
+            let mut credential_id = vec![0u8; 16];
+
+            OsRng.fill_bytes(&mut seed);
+            OsRng.fill_bytes(&mut credential_id);
+
+            Self {
+                key: SigningKey::from_bytes(&seed),
+                credential_id,
+                sign_count: 0,
+            }
+        }
+
+        pub fn cose_key(&self) -> Vec<u8> {
+            cbor(&Value::Map(vec![
+                (1.into(), 1.into()),
+                (3.into(), (-8).into()),
+                ((-1).into(), 6.into()),
+                (
+                    (-2).into(),
+                    Value::Bytes(self.key.verifying_key().to_bytes().to_vec()),
+                ),
+            ]))
+        }
+
+        /// Authenticator data with the given flags, carrying the credential when attesting.
+        pub fn auth_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+            let mut bytes = Sha256::digest(rp_id.as_bytes()).to_vec();
+            bytes.push(flags);
+            bytes.extend_from_slice(&self.sign_count.to_be_bytes());
+
+            if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+                bytes.extend_from_slice(&[0u8; super::AAGUID_BYTES]);
+                bytes.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+                bytes.extend_from_slice(&self.credential_id);
+                bytes.extend_from_slice(&self.cose_key());
+            }
+
+            bytes
+        }
+
+        /// `attestationObject` of a registration with user verification, `none` format.
+        pub fn attestation_object(&self, rp_id: &str) -> Vec<u8> {
+            let flags = FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_CREDENTIAL;
+
+            cbor(&Value::Map(vec![
+                ("fmt".into(), "none".into()),
+                ("attStmt".into(), Value::Map(Vec::new())),
+                (
+                    "authData".into(),
+                    Value::Bytes(self.auth_data(rp_id, flags)),
+                ),
+            ]))
+        }
+
+        /// Authenticator data and signature of an assertion with user verification,
+        /// moving the counter forward.
+        pub fn assert(&mut self, rp_id: &str, client_data_json: &[u8]) -> (Vec<u8>, Vec<u8>) {
+            self.sign_count += 1;
+
+            let auth_data = self.auth_data(rp_id, FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+            let signature = self.sign(&auth_data, client_data_json);
+
+            (auth_data, signature)
+        }
+
+        pub fn sign(&self, auth_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+            let mut message = auth_data.to_vec();
+            message.extend_from_slice(&Sha256::digest(client_data_json));
+
+            self.key.sign(&message).to_bytes().to_vec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::Value;
+    use p256::ecdsa::{SigningKey, signature::Signer as _};
+
+    use super::{soft::*, *};
+
+    const RP_ID: &str = "localhost";
+    const ORIGIN: &str = "http://localhost:3000";
+
+    #[test]
+    fn client_data_accepts_expected_type_and_origin() {
+        let json = client_data("webauthn.get", "abc", ORIGIN);
+
+        let client_data = ClientData::parse(&json, "webauthn.get", ORIGIN).unwrap();
+
+        assert_eq!(client_data.challenge, "abc");
+    }
+
+    #[test]
+    fn client_data_rejects_wrong_origin() {
+        let json = client_data("webauthn.get", "abc", "https://evil.example");
+
+        let result = ClientData::parse(&json, "webauthn.get", ORIGIN);
+
+        assert!(matches!(result, Err(WebAuthnError::WrongOrigin)));
+    }
+
+    #[test]
+    fn client_data_rejects_wrong_type() {
+        let json = client_data("webauthn.create", "abc", ORIGIN);
+
+        let result = ClientData::parse(&json, "webauthn.get", ORIGIN);
+
+        assert!(matches!(result, Err(WebAuthnError::WrongType)));
+    }
+
+    #[test]
+    fn client_data_rejects_garbage() {
+        let result = ClientData::parse(b"not json", "webauthn.get", ORIGIN);
+
+        assert!(matches!(result, Err(WebAuthnError::Malformed(_))));
+    }
+
+    #[test]
+    fn auth_data_reads_flags_and_counter() {
+        let mut authenticator = SoftAuthenticator::new();
+        authenticator.sign_count = 42;
+
+        let bytes = authenticator.auth_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+
+        let auth_data = AuthenticatorData::parse(&bytes, RP_ID, true).unwrap();
+
+        assert!(auth_data.user_verified);
+        assert_eq!(auth_data.sign_count, 42);
+        assert!(auth_data.attested.is_none());
+    }
+
+    #[test]
+    fn auth_data_rejects_other_rp_id() {
+        let bytes = SoftAuthenticator::new().auth_data("evil.example", FLAG_USER_PRESENT);
+
+        let result = AuthenticatorData::parse(&bytes, RP_ID, false);
+
+        assert!(matches!(result, Err(WebAuthnError::WrongRpId)));
+    }
+
+    #[test]
+    fn auth_data_requires_user_presence() {
+        let bytes = SoftAuthenticator::new().auth_data(RP_ID, FLAG_USER_VERIFIED);
+
+        let result = AuthenticatorData::parse(&bytes, RP_ID, false);
+
+        assert!(matches!(result, Err(WebAuthnError::UserNotPresent)));
+    }
+
+    #[test]
+    fn auth_data_requires_user_verification_when_asked() {
+        let bytes = SoftAuthenticator::new().auth_data(RP_ID, FLAG_USER_PRESENT);
+
+        let result = AuthenticatorData::parse(&bytes, RP_ID, true);
+        assert!(matches!(result, Err(WebAuthnError::UserNotVerified)));
+
+        let auth_data = AuthenticatorData::parse(&bytes, RP_ID, false).unwrap();
+        assert!(!auth_data.user_verified);
+    }
+
+    #[test]
+    fn auth_data_rejects_truncated_input() {
+        let bytes = SoftAuthenticator::new().auth_data(RP_ID, FLAG_USER_PRESENT);
+
+        let result = AuthenticatorData::parse(&bytes[..AUTH_DATA_HEADER - 1], RP_ID, false);
+
+        assert!(matches!(result, Err(WebAuthnError::Malformed(_))));
+    }
+
+    #[test]
+    fn attestation_yields_the_credential() {
+        let authenticator = SoftAuthenticator::new();
+
+        let bytes = attestation_auth_data(&authenticator.attestation_object(RP_ID)).unwrap();
+
+        let attested = AuthenticatorData::parse(&bytes, RP_ID, true)
+            .unwrap()
+            .attested
+            .expect("attested credential");
+
+        assert_eq!(attested.credential_id, authenticator.credential_id);
+        assert_eq!(attested.public_key, authenticator.cose_key());
+    }
+
+    #[test]
+    fn ed25519_key_verifies_its_signature_only() {
+        let mut authenticator = SoftAuthenticator::new();
+        let json = client_data("webauthn.get", "abc", ORIGIN);
+
+        let (auth_data, signature) = authenticator.assert(RP_ID, &json);
+
+        let key = CoseKey::parse(&authenticator.cose_key()).unwrap();
+
+        assert!(key.verify(&auth_data, &json, &signature).is_ok());
+
+        let other = client_data("webauthn.get", "abd", ORIGIN);
+
+        assert!(matches!(
+            key.verify(&auth_data, &other, &signature),
+            Err(WebAuthnError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn es256_key_verifies_its_signature_only() {
+        let signing_key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let point = signing_key.verifying_key().to_encoded_point(false);
+
+        let cose_key = cbor(&Value::Map(vec![
+            (1.into(), 2.into()),
+            (3.into(), (-7).into()),
+            ((-1).into(), 1.into()),
+            ((-2).into(), Value::Bytes(point.x().unwrap().to_vec())),
+            ((-3).into(), Value::Bytes(point.y().unwrap().to_vec())),
+        ]));
+
+        let auth_data = SoftAuthenticator::new().auth_data(RP_ID, FLAG_USER_PRESENT);
+        let json = client_data("webauthn.get", "abc", ORIGIN);
+
+        let mut message = auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(&json));
+
+        let signature: p256::ecdsa::Signature = signing_key.sign(&message);
+        let signature = signature.to_der();
+
+        let key = CoseKey::parse(&cose_key).unwrap();
+
+        assert!(key.verify(&auth_data, &json, signature.as_bytes()).is_ok());
+        assert!(matches!(
+            key.verify(&auth_data, &json, &signature.as_bytes()[1..]),
+            Err(WebAuthnError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn cose_key_rejects_unsupported_algorithm() {
+        let cose_key = cbor(&Value::Map(vec![
+            (1.into(), 2.into()),
+            (3.into(), (-35).into()),
+        ]));
+
+        assert!(matches!(
+            CoseKey::parse(&cose_key),
+            Err(WebAuthnError::UnsupportedAlgorithm)
+        ));
+    }
+}